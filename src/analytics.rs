@@ -0,0 +1,465 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::api_client::PublishType;
+use crate::database::Database;
+use crate::{PuzzleStats, RecordKey};
+use chrono::{naive::NaiveDate, Datelike, Duration, Weekday};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Weekdays in the order they should be reported, starting on Monday like the NYT does
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// Running tally of solves, split by whether aids (check/reveal) were used
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AssistTally {
+    /// Number of solved puzzles, assisted or not
+    pub solved: u32,
+    /// Number of solved puzzles where check or reveal was used
+    pub assisted: u32,
+    /// Number of assisted solves with a known solve time
+    assisted_timed: u32,
+    /// Sum of the solve times of assisted solves with a known solve time
+    assisted_secs: u64,
+}
+
+impl AssistTally {
+    fn add(&mut self, record: &PuzzleStats) {
+        self.solved += 1;
+        if record.cheated.unwrap_or(false) {
+            self.assisted += 1;
+            if let Some(secs) = record.solve_time_secs {
+                self.assisted_timed += 1;
+                self.assisted_secs += u64::from(secs);
+            }
+        }
+    }
+
+    /// Fraction of solves that used aids, or `None` if there were no solves
+    #[must_use]
+    pub fn assist_rate(&self) -> Option<f64> {
+        (self.solved > 0).then(|| f64::from(self.assisted) / f64::from(self.solved))
+    }
+
+    /// Average solve time of assisted solves in seconds, if any assisted solve times are known
+    #[must_use]
+    pub fn mean_assisted_secs(&self) -> Option<f64> {
+        #[allow(clippy::cast_precision_loss)]
+        (self.assisted_timed > 0)
+            .then(|| self.assisted_secs as f64 / f64::from(self.assisted_timed))
+    }
+}
+
+/// Summary of solves that used the check or reveal aids
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AssistedReport {
+    pub overall: AssistTally,
+    pub by_weekday: BTreeMap<u32, AssistTally>,
+    pub by_year: BTreeMap<i32, AssistTally>,
+}
+
+impl AssistedReport {
+    /// Build a report from the given records. Unsolved records are ignored.
+    pub fn new<'a, I: IntoIterator<Item = &'a PuzzleStats>>(records: I) -> Self {
+        let mut report = Self::default();
+        for record in records {
+            if !record.is_solved() {
+                continue;
+            }
+            report.overall.add(record);
+            report
+                .by_weekday
+                .entry(record.date.weekday().num_days_from_monday())
+                .or_default()
+                .add(record);
+            report
+                .by_year
+                .entry(record.date.year())
+                .or_default()
+                .add(record);
+        }
+        report
+    }
+
+    /// Tally for the given weekday
+    #[must_use]
+    pub fn weekday(&self, weekday: Weekday) -> AssistTally {
+        self.by_weekday
+            .get(&weekday.num_days_from_monday())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Weekdays ordered from most to least frequently assisted
+    #[must_use]
+    pub fn weekdays_by_assist_rate(&self) -> Vec<(Weekday, AssistTally)> {
+        let mut weekdays: Vec<(Weekday, AssistTally)> = WEEKDAYS
+            .iter()
+            .map(|day| (*day, self.weekday(*day)))
+            .filter(|(_, tally)| tally.solved > 0)
+            .collect();
+        weekdays.sort_by(|(_, a), (_, b)| {
+            b.assist_rate()
+                .partial_cmp(&a.assist_rate())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        weekdays
+    }
+}
+
+/// An unassisted solve with a known solve time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimedSolve {
+    pub date: NaiveDate,
+    pub solve_time_secs: u32,
+}
+
+/// Solve time summary of the unassisted solves of one weekday
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WeekdayAggregate {
+    /// Number of unassisted solves with a known solve time
+    pub count: u32,
+    pub mean_secs: Option<f64>,
+    /// Middle solve time, or the mean of the two middle solve times if `count` is even
+    pub median_secs: Option<f64>,
+    /// Fastest solve, the earliest one on ties
+    pub best: Option<TimedSolve>,
+    /// Slowest solve, the earliest one on ties
+    pub worst: Option<TimedSolve>,
+}
+
+impl WeekdayAggregate {
+    fn from_solves(mut solves: Vec<TimedSolve>) -> Self {
+        solves.sort_by_key(|solve| (solve.solve_time_secs, solve.date));
+        let secs = |i: usize| f64::from(solves[i].solve_time_secs);
+        let n = solves.len();
+        #[allow(clippy::cast_precision_loss)]
+        let mean_secs = (n > 0).then(|| (0..n).map(secs).sum::<f64>() / n as f64);
+        let median_secs = (n > 0).then(|| {
+            if n % 2 == 1 {
+                secs(n / 2)
+            } else {
+                (secs(n / 2 - 1) + secs(n / 2)) / 2.0
+            }
+        });
+        // Sorted by time and then date, so the slowest time's first solve is the earliest
+        let worst = solves.last().and_then(|slowest| {
+            solves
+                .iter()
+                .find(|solve| solve.solve_time_secs == slowest.solve_time_secs)
+        });
+        Self {
+            count: u32::try_from(n).unwrap_or(u32::MAX),
+            mean_secs,
+            median_secs,
+            best: solves.first().copied(),
+            worst: worst.copied(),
+        }
+    }
+}
+
+/// Solve time summaries of unassisted solves by weekday. Built from any records, so callers choose
+/// what to aggregate, e.g. only daily crosswords or only solves from a given year. Unsolved,
+/// assisted, and untimed records are ignored.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WeekdayAggregates {
+    /// Summaries keyed by days from Monday. Weekdays without any solves are left out.
+    pub by_weekday: BTreeMap<u32, WeekdayAggregate>,
+}
+
+impl WeekdayAggregates {
+    pub fn new<'a, I: IntoIterator<Item = &'a PuzzleStats>>(records: I) -> Self {
+        let mut solves: BTreeMap<u32, Vec<TimedSolve>> = BTreeMap::new();
+        for record in records {
+            if record.cheated.unwrap_or(false) {
+                continue;
+            }
+            if let Some(secs) = record.solve_time_secs {
+                solves
+                    .entry(record.date.weekday().num_days_from_monday())
+                    .or_default()
+                    .push(TimedSolve {
+                        date: record.date,
+                        solve_time_secs: secs,
+                    });
+            }
+        }
+        Self {
+            by_weekday: solves
+                .into_iter()
+                .map(|(day, solves)| (day, WeekdayAggregate::from_solves(solves)))
+                .collect(),
+        }
+    }
+
+    /// Summary of the given weekday, or `None` if it has no unassisted solves
+    #[must_use]
+    pub fn weekday(&self, weekday: Weekday) -> Option<&WeekdayAggregate> {
+        self.by_weekday.get(&weekday.num_days_from_monday())
+    }
+
+    /// Summaries of the weekdays with unassisted solves, starting on Monday
+    pub fn iter(&self) -> impl Iterator<Item = (Weekday, &WeekdayAggregate)> {
+        WEEKDAYS
+            .iter()
+            .filter_map(|day| self.weekday(*day).map(|aggregate| (*day, aggregate)))
+    }
+}
+
+/// Rank each unassisted solve against the unassisted solves of the same weekday and publish type
+/// that were completed before it. The rank is the percentage of those earlier solves that were
+/// slower, so 100 means a new personal best. Solves without a solve timestamp, and the first solve
+/// of each weekday, aren't ranked.
+///
+/// Returns a map from record key to percent rank
+pub fn percent_ranks<'a, I: IntoIterator<Item = &'a PuzzleStats>>(
+    records: I,
+) -> HashMap<RecordKey, u8> {
+    let mut solves: Vec<(u32, RecordKey, u32)> = records
+        .into_iter()
+        .filter(|r| !r.cheated.unwrap_or(false))
+        .filter_map(|r| Some((r.solved_unix?, r.key(), r.solve_time_secs?)))
+        .collect();
+    solves.sort_unstable();
+
+    // Sorted solve times seen so far for each weekday and publish type
+    let mut prior: HashMap<(Weekday, PublishType), Vec<u32>> = HashMap::new();
+    let mut ranks = HashMap::new();
+    for (_, key, secs) in solves {
+        let (date, publish_type) = key;
+        let times = prior.entry((date.weekday(), publish_type)).or_default();
+        if !times.is_empty() {
+            let slower = times.len() - times.partition_point(|t| *t <= secs);
+            let rank = slower * 100 / times.len();
+            ranks.insert(key, u8::try_from(rank).unwrap());
+        }
+        let index = times.partition_point(|t| *t < secs);
+        times.insert(index, secs);
+    }
+    ranks
+}
+
+/// A past daily crossword, for comparing against the one published on a given date
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PastSolve {
+    pub date: NaiveDate,
+    /// `None` if the puzzle is in the database but unsolved
+    pub solve_time_secs: Option<u32>,
+    pub cheated: bool,
+}
+
+impl PastSolve {
+    fn get(database: &Database, date: NaiveDate) -> Option<Self> {
+        database.get(date, PublishType::Daily).map(|record| Self {
+            date,
+            solve_time_secs: record.solve_time_secs,
+            cheated: record.cheated.unwrap_or(false),
+        })
+    }
+}
+
+/// The daily crosswords of one past year that line up with a given date
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PastYear {
+    pub year: i32,
+    /// The puzzle published on the same month and day. February 29 falls back to February 28.
+    pub same_date: Option<PastSolve>,
+    /// The puzzle published on the same weekday within three days of the same month and day,
+    /// which is a fairer comparison since difficulty follows the day of the week
+    pub same_weekday: Option<PastSolve>,
+}
+
+/// Daily crossword solves on the same date, and the same weekday nearby, in previous years
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OnThisDay {
+    pub date: NaiveDate,
+    /// Years with at least one matching puzzle in the database, oldest first
+    pub years: Vec<PastYear>,
+}
+
+impl OnThisDay {
+    #[must_use]
+    pub fn new(database: &Database, date: NaiveDate) -> Self {
+        let first_year = database
+            .iter()
+            .filter(|r| r.publish_type == PublishType::Daily)
+            .map(|r| r.date.year())
+            .min()
+            .unwrap_or(date.year());
+        let years = (first_year..date.year())
+            .filter_map(|year| {
+                let anchor = date
+                    .with_year(year)
+                    .or_else(|| date.with_day(28)?.with_year(year))?;
+                // Nearest date with the same weekday, from three days before to three after
+                let ahead = (i64::from(date.weekday().num_days_from_monday())
+                    - i64::from(anchor.weekday().num_days_from_monday()))
+                .rem_euclid(7);
+                let offset = if ahead > 3 { ahead - 7 } else { ahead };
+                let past = PastYear {
+                    year,
+                    same_date: PastSolve::get(database, anchor),
+                    same_weekday: PastSolve::get(database, anchor + Duration::days(offset)),
+                };
+                (past.same_date.is_some() || past.same_weekday.is_some()).then_some(past)
+            })
+            .collect();
+        Self { date, years }
+    }
+}
+
+fn format_past_solve(solve: Option<PastSolve>) -> String {
+    match solve {
+        None => "-".to_string(),
+        Some(solve) => {
+            let time = match solve.solve_time_secs {
+                None => "unsolved".to_string(),
+                Some(secs) => format_secs(Some(f64::from(secs))),
+            };
+            let assisted = if solve.cheated { " (assisted)" } else { "" };
+            format!(
+                "{} {} {}{}",
+                solve.date.weekday(),
+                solve.date,
+                time,
+                assisted
+            )
+        }
+    }
+}
+
+impl fmt::Display for OnThisDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "On this day, {} ({}):",
+            self.date.format("%B %-d"),
+            self.date.weekday()
+        )?;
+        if self.years.is_empty() {
+            return writeln!(f, "  No puzzles from previous years");
+        }
+        for past in &self.years {
+            write!(f, "  {}  {}", past.year, format_past_solve(past.same_date))?;
+            if past.same_weekday != past.same_date {
+                write!(
+                    f,
+                    "    nearest {}: {}",
+                    self.date.weekday(),
+                    format_past_solve(past.same_weekday)
+                )?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn format_rate(tally: &AssistTally) -> String {
+    tally
+        .assist_rate()
+        .map_or_else(|| "-".to_string(), |rate| format!("{:.1}%", rate * 100.0))
+}
+
+/// Format a duration in seconds as `m:ss`, or `-` if unknown
+pub(crate) fn format_secs(secs: Option<f64>) -> String {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    secs.map_or_else(
+        || "-".to_string(),
+        |secs| {
+            let secs = secs.round() as u64;
+            format!("{}:{:02}", secs / 60, secs % 60)
+        },
+    )
+}
+
+impl fmt::Display for WeekdayAggregates {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Unassisted solve times by weekday:")?;
+        let time = |solve: Option<TimedSolve>| {
+            solve.map_or_else(
+                || "-".to_string(),
+                |s| {
+                    format!(
+                        "{} ({})",
+                        format_secs(Some(f64::from(s.solve_time_secs))),
+                        s.date
+                    )
+                },
+            )
+        };
+        for (weekday, aggregate) in self.iter() {
+            writeln!(
+                f,
+                "  {}  {:>4} solves  mean {}  median {}  best {}  worst {}",
+                weekday,
+                aggregate.count,
+                format_secs(aggregate.mean_secs),
+                format_secs(aggregate.median_secs),
+                time(aggregate.best),
+                time(aggregate.worst)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for AssistedReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Assisted solves: {} of {} ({}), average time {}",
+            self.overall.assisted,
+            self.overall.solved,
+            format_rate(&self.overall),
+            format_secs(self.overall.mean_assisted_secs())
+        )?;
+        writeln!(f)?;
+        writeln!(f, "By weekday, most assisted first:")?;
+        for (weekday, tally) in self.weekdays_by_assist_rate() {
+            writeln!(
+                f,
+                "  {}  {:>4} of {:<4} {:>6}  avg {}",
+                weekday,
+                tally.assisted,
+                tally.solved,
+                format_rate(&tally),
+                format_secs(tally.mean_assisted_secs())
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "By year:")?;
+        for (year, tally) in &self.by_year {
+            writeln!(
+                f,
+                "  {}  {:>4} of {:<4} {:>6}  avg {}",
+                year,
+                tally.assisted,
+                tally.solved,
+                format_rate(tally),
+                format_secs(tally.mean_assisted_secs())
+            )?;
+        }
+        Ok(())
+    }
+}
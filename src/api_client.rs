@@ -12,22 +12,74 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use anyhow::Result;
+use crate::auth::{AuthProvider, StaticToken};
+use crate::cache::{CachedResponse, ResponseCache};
+use crate::rate_limit::{HostRateLimiter, MaintenancePause};
+use anyhow::{Context, Result};
 use chrono::naive::NaiveDate;
+use chrono::{DateTime, Utc};
 use core::num::NonZeroU32;
-use governor::clock::DefaultClock;
-use governor::state::direct::NotKeyed;
-use governor::state::InMemoryState;
-use governor::{Quota, RateLimiter};
-use log::error;
-use reqwest::header::{self, HeaderMap, HeaderValue};
-use reqwest::IntoUrl;
-use serde::Deserialize;
+use log::{debug, error, info, warn};
+use rand::Rng;
+use reqwest::header::{self, HeaderMap, HeaderName, HeaderValue};
+use reqwest::{IntoUrl, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
 use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use std::time;
 
+/// Kind of crossword. Each kind has its own series of puzzles, so more than one puzzle may be
+/// published on the same date.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    Hash,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Deserialize,
+    Serialize,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum PublishType {
+    /// The daily crossword
+    #[default]
+    Daily,
+    /// Bonus crosswords, published monthly
+    Bonus,
+    /// Variety crosswords such as cryptics, diagramless, and themeless puzzles
+    Variety,
+    /// Acrostics, published every other Sunday
+    Acrostic,
+}
+
+impl PublishType {
+    /// Name of the publish type used by the NYT API
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Daily => "daily",
+            Self::Bonus => "bonus",
+            Self::Variety => "variety",
+            Self::Acrostic => "acrostic",
+        }
+    }
+}
+
+impl fmt::Display for PublishType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 #[derive(Debug, Clone, Hash, PartialEq, Deserialize)]
 struct PuzzleInfoResponse {
     results: Vec<PuzzleMetadata>,
@@ -37,7 +89,45 @@ struct PuzzleInfoResponse {
 struct PuzzleMetadata {
     print_date: NaiveDate,
     puzzle_id: u32,
-    // other fields don't contain accurate solve data. don't trust them.
+    author: Option<String>,
+    editor: Option<String>,
+    title: Option<String>,
+    percent_filled: Option<u8>,
+    /// `Gold` for puzzles solved without help on or before their publication day
+    star: Option<String>,
+    // other fields don't contain accurate solve data. don't trust them. percent_filled and star
+    // are only a snapshot from when the metadata was fetched, and solve stats should still come
+    // from the stats endpoint.
+}
+
+/// Descriptive information about a puzzle, as returned by the puzzle metadata API
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Default)]
+pub struct PuzzleInfo {
+    pub puzzle_id: u32,
+    /// Constructor(s) of the puzzle
+    pub author: Option<String>,
+    pub editor: Option<String>,
+    /// Only set for puzzles with a title, e.g. Sunday puzzles
+    pub title: Option<String>,
+    /// Percentage of the grid filled in, including unfinished puzzles
+    pub percent_filled: Option<u8>,
+    /// Whether the puzzle earned a gold star
+    pub gold_star: bool,
+}
+
+impl From<PuzzleMetadata> for PuzzleInfo {
+    fn from(metadata: PuzzleMetadata) -> Self {
+        // Untitled puzzles sometimes come back with an empty title instead of null
+        let non_empty = |s: Option<String>| s.filter(|s| !s.is_empty());
+        Self {
+            puzzle_id: metadata.puzzle_id,
+            author: non_empty(metadata.author),
+            editor: non_empty(metadata.editor),
+            title: non_empty(metadata.title),
+            percent_filled: metadata.percent_filled,
+            gold_star: metadata.star.as_deref() == Some("Gold"),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Deserialize)]
@@ -89,6 +179,16 @@ struct RawFirsts {
     solved: Option<u32>,
 }
 
+/// Progress on a puzzle, as returned by the puzzle stats API
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Default)]
+pub struct SolveProgress {
+    /// Stats of the solve, if the puzzle was solved
+    pub solve: Option<SolvedPuzzleStats>,
+    /// When the puzzle was first opened, in seconds since the Unix epoch. Also set for puzzles that
+    /// were started but not finished.
+    pub opened: Option<u32>,
+}
+
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Default)]
 pub struct SolvedPuzzleStats {
     pub solve_time: u32,
@@ -97,8 +197,152 @@ pub struct SolvedPuzzleStats {
     pub cheated: bool,
 }
 
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct StatsAndStreaksResponse {
+    results: StatsAndStreaks,
+}
+
+/// Official streaks and lifetime stats for the daily crossword
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct StatsAndStreaks {
+    pub streaks: Streaks,
+    pub stats: LifetimeStats,
+}
+
+/// Lifetime aggregates for the daily crossword, as computed by the NYT
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LifetimeStats {
+    pub puzzles_attempted: u32,
+    pub puzzles_solved: u32,
+    /// Fraction of attempted puzzles that were solved
+    #[serde(default)]
+    pub solve_rate: Option<f64>,
+    /// Solve times for each day of the week, Monday first
+    #[serde(default)]
+    pub stats_by_day: Vec<WeekdayStats>,
+}
+
+/// Solve times for one day of the week, as computed by the NYT. Times are in seconds.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct WeekdayStats {
+    /// Name of the day of the week, e.g. `Monday`
+    pub label: String,
+    pub avg_time: Option<u32>,
+    pub best_time: Option<u32>,
+    pub best_date: Option<NaiveDate>,
+    pub latest_time: Option<u32>,
+    pub latest_date: Option<NaiveDate>,
+}
+
+/// Official streak counts for the daily crossword
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Deserialize)]
+pub struct Streaks {
+    pub current_streak: u32,
+    pub longest_streak: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+struct PuzzleContentResponse {
+    body: Vec<PuzzleContent>,
+}
+
+/// Grid and clues of a puzzle
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct PuzzleContent {
+    pub dimensions: Dimensions,
+    /// Cells of the grid in row-major order
+    pub cells: Vec<Cell>,
+    pub clues: Vec<Clue>,
+}
+
+/// Size of a puzzle grid
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct Dimensions {
+    pub width: usize,
+    pub height: usize,
+}
+
+/// A single square of a puzzle grid. Black squares have no answer.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+pub struct Cell {
+    /// Letters that go in the square. Rebus squares hold more than one.
+    pub answer: Option<String>,
+    /// Clue number printed in the square, if any
+    pub label: Option<String>,
+    /// Kind of square: 1 for a plain square, 2 for a circled one, 3 for a shaded one
+    #[serde(rename = "type")]
+    pub kind: Option<u8>,
+}
+
+/// A clue along with where it appears in the grid
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Clue {
+    /// `Across` or `Down`
+    pub direction: String,
+    /// Clue number
+    pub label: String,
+    pub text: Vec<ClueText>,
+}
+
+/// Text of a clue. Clues with formatting also come with an HTML version, which isn't kept.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ClueText {
+    pub plain: String,
+}
+
+/// Error returned by requests to the NYT API
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    /// The subscription token was rejected
+    #[error(
+        "NYT subscription token is invalid or expired (server responded with {0}). \
+         Extract a fresh token from your browser and try again."
+    )]
+    Unauthorized(StatusCode),
+    /// The server was still rate-limiting requests after all retries were used up
+    #[error("Rate-limited by the NYT API")]
+    RateLimited { retry_after: Option<time::Duration> },
+    /// Requests were stopped after too many consecutive responses rejecting the token
+    #[error(
+        "Stopped after {failures} consecutive requests were rejected. The NYT subscription token \
+         has likely expired; extract a fresh token from your browser and try again."
+    )]
+    AuthCircuitOpen { failures: u32 },
+    /// Any other unsuccessful response
+    #[error("NYT API responded with {0}")]
+    Status(StatusCode),
+    /// The response body wasn't in the expected format
+    #[error("Malformed response from the NYT API: {0}")]
+    Deserialize(#[from] serde_json::Error),
+    /// The request couldn't be sent or the response couldn't be received
+    #[error("Request to the NYT API failed: {0}")]
+    Transport(#[from] reqwest::Error),
+}
+
+impl ApiError {
+    /// Whether the error is due to a bad subscription token
+    #[must_use]
+    pub fn is_unauthorized(&self) -> bool {
+        matches!(self, Self::Unauthorized(_) | Self::AuthCircuitOpen { .. })
+    }
+}
+
+/// Convert an unsuccessful response status into the matching `ApiError`
+fn check_status(response: &reqwest::Response) -> Result<(), ApiError> {
+    match response.status() {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            Err(ApiError::Unauthorized(response.status()))
+        }
+        StatusCode::TOO_MANY_REQUESTS => Err(ApiError::RateLimited {
+            retry_after: retry_after(response),
+        }),
+        status if !status.is_success() => Err(ApiError::Status(status)),
+        _ => Ok(()),
+    }
+}
+
 /// NYT subscription token
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SubscriptionToken {
     /// Token extract from nyt-s HTTP header
     Header(String),
@@ -106,63 +350,505 @@ pub enum SubscriptionToken {
     Cookie(String),
 }
 
+impl SubscriptionToken {
+    /// Read the NYT-S cookie from a cookies.txt file in the Netscape format written by curl, wget,
+    /// and various browser extensions
+    pub fn from_cookie_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read cookie file {}", path.display()))?;
+        contents
+            .lines()
+            // HttpOnly cookies are written with a prefix that makes them look like comments
+            .map(|line| line.strip_prefix("#HttpOnly_").unwrap_or(line))
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(|line| {
+                // domain, include subdomains, path, secure, expiry, name, value
+                let fields: Vec<&str> = line.split('\t').collect();
+                match fields[..] {
+                    [domain, _, _, _, _, "NYT-S", value]
+                        if domain.trim_start_matches('.').ends_with("nytimes.com") =>
+                    {
+                        Some(value.trim())
+                    }
+                    _ => None,
+                }
+            })
+            .find(|value| !value.is_empty())
+            .map(|value| Self::Cookie(value.to_string()))
+            .with_context(|| format!("No NYT-S cookie for nytimes.com in {}", path.display()))
+    }
+
+    /// Header that presents the token to the NYT API
+    fn header(&self) -> Result<(HeaderName, HeaderValue)> {
+        Ok(match self {
+            Self::Cookie(cookie) => (
+                header::COOKIE,
+                HeaderValue::from_str(&format!("NYT-S={cookie}"))?,
+            ),
+            Self::Header(header) => (HeaderName::from_static("nyt-s"), header.parse()?),
+        })
+    }
+}
+
+/// Subscription token in use, shared between clones of a client so that a refreshed token is
+/// picked up by all of them
+#[derive(Debug)]
+struct TokenState {
+    provider: Arc<dyn AuthProvider>,
+    token: SubscriptionToken,
+    header: (HeaderName, HeaderValue),
+}
+
+/// Policy for retrying requests that fail due to transient server or network errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts per request, including the first one
+    pub max_attempts: u32,
+    /// Delay before the first retry. The delay doubles with each subsequent retry.
+    pub base_delay: time::Duration,
+    /// Upper bound on the delay between retries
+    pub max_delay: time::Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: time::Duration::from_millis(500),
+            max_delay: time::Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries
+    #[must_use]
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Delay before making the given retry attempt, starting from 1 for the first retry. Uses
+    /// "full jitter", picking a random delay up to the exponential backoff ceiling so that
+    /// concurrent requests don't retry in lockstep.
+    fn delay(&self, retry: u32) -> time::Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(2_u32.saturating_pow(retry.saturating_sub(1)))
+            .min(self.max_delay);
+        ceiling.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+}
+
+/// Returns true if the given response indicates a transient failure worth retrying
+fn is_transient(result: &reqwest::Result<reqwest::Response>) -> bool {
+    match result {
+        Ok(response) => {
+            response.status().is_server_error()
+                || response.status() == StatusCode::TOO_MANY_REQUESTS
+        }
+        Err(e) => e.is_timeout() || e.is_connect(),
+    }
+}
+
+/// Parse the `Retry-After` header of a response, which may either be a number of seconds or an
+/// HTTP date
+fn retry_after(response: &reqwest::Response) -> Option<time::Duration> {
+    let value = response.headers().get(header::RETRY_AFTER)?.to_str().ok()?;
+    if let Ok(secs) = value.trim().parse::<u64>() {
+        return Some(time::Duration::from_secs(secs));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
 /// An HTTP client with a rate-limiting wrapper
 #[derive(Debug, Clone)]
 pub struct RateLimitedClient {
     client: reqwest::Client,
-    governor: Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
+    governor: Arc<HostRateLimiter>,
+    maintenance: Arc<MaintenancePause>,
     n_requests: Arc<AtomicU32>,
+    n_not_modified: Arc<AtomicU32>,
+    retry_policy: RetryPolicy,
+    cache: Option<ResponseCache>,
+    api_base: String,
+    auth: Arc<RwLock<TokenState>>,
+    /// Number of consecutive responses rejecting the subscription token
+    auth_failures: Arc<AtomicU32>,
+    max_auth_failures: u32,
 }
 
 impl RateLimitedClient {
     const API_BASE: &'static str = "https://www.nytimes.com/svc/crosswords";
-    const PUZZLE_INFO_ENDPOINT: &'static str =
-        "/v3/36569100/puzzles.json?publish_type=daily&date_start={start_date}&date_end={end_date}";
+    const DEFAULT_MAX_AUTH_FAILURES: u32 = 5;
+    const DEFAULT_MAX_MAINTENANCE_PAUSE: time::Duration = time::Duration::from_secs(3600);
+    const PUZZLE_INFO_ENDPOINT: &'static str = concat!(
+        "/v3/36569100/puzzles.json?publish_type={publish_type}",
+        "&date_start={start_date}&date_end={end_date}"
+    );
     const PUZZLE_STATS_ENDPOINT: &'static str = "/v6/game/{id}.json";
+    const PUZZLE_CONTENT_ENDPOINT: &'static str = "/v6/puzzle/{publish_type}/{date}.json";
+    const STATS_AND_STREAKS_ENDPOINT: &'static str =
+        "/v3/36569100/stats-and-streaks.json?date_start=2014-01-01&start_on_monday=true";
 
-    /// Construct a new `RateLimitedClient`
+    /// Construct a new `RateLimitedClient` that uses a fixed subscription token
     ///
     /// # Arguments
     ///
     /// * `nyt_s` - NYT subscription token extracted from web browser
-    /// * `quota` - Outgoing request quota in requests per second
-    pub fn new(nyt_token: SubscriptionToken, quota: NonZeroU32) -> Self {
+    /// * `quota` - Outgoing request quota in requests per second, applied separately to each host
+    /// * `proxy` - Proxy to send all requests through. If `None`, the proxy is taken from the
+    ///   `HTTPS_PROXY`/`HTTP_PROXY`/`ALL_PROXY` environment variables, if set.
+    pub fn new(
+        nyt_token: SubscriptionToken,
+        quota: NonZeroU32,
+        proxy: Option<reqwest::Proxy>,
+    ) -> Self {
+        Self::with_auth_provider(Arc::new(StaticToken(nyt_token)), quota, proxy)
+            .expect("Invalid subscription token")
+    }
+
+    /// Construct a new `RateLimitedClient` that gets its subscription token from the given
+    /// provider. The provider is asked for a replacement whenever the token is rejected. See
+    /// `new` for the other arguments.
+    pub fn with_auth_provider(
+        provider: Arc<dyn AuthProvider>,
+        quota: NonZeroU32,
+        proxy: Option<reqwest::Proxy>,
+    ) -> Result<Self> {
+        let token = provider.token()?;
+        let header = token
+            .header()
+            .context("Subscription token isn't a valid header value")?;
+
         let mut headers = HeaderMap::new();
         headers.insert(header::ACCEPT, "application/json".parse().unwrap());
         headers.insert(header::DNT, "1".parse().unwrap());
-        match nyt_token {
-            SubscriptionToken::Cookie(cookie) => headers.insert(
-                header::COOKIE,
-                HeaderValue::from_str(&format!("NYT-S={}", cookie)).unwrap(),
-            ),
-            SubscriptionToken::Header(header) => headers.insert("nyt-s", header.parse().unwrap()),
-        };
 
-        let client = reqwest::ClientBuilder::new()
+        let mut builder = reqwest::ClientBuilder::new()
             .user_agent("Scraping personal stats")
             .default_headers(headers)
-            .timeout(time::Duration::from_secs(10))
-            .build()
-            .unwrap();
-        let governor = Arc::new(RateLimiter::direct(Quota::per_second(quota)));
+            .timeout(time::Duration::from_secs(10));
+        // An explicit proxy disables reqwest's default lookup of the proxy environment variables
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(proxy);
+        }
+        let client = builder.build().unwrap();
+        let governor = Arc::new(HostRateLimiter::new(quota));
         let n_requests = Arc::new(AtomicU32::new(0));
 
-        Self {
+        Ok(Self {
             client,
             governor,
+            maintenance: Arc::new(MaintenancePause::new(Self::DEFAULT_MAX_MAINTENANCE_PAUSE)),
             n_requests,
+            n_not_modified: Arc::new(AtomicU32::new(0)),
+            retry_policy: RetryPolicy::default(),
+            cache: None,
+            api_base: Self::API_BASE.to_string(),
+            auth: Arc::new(RwLock::new(TokenState {
+                provider,
+                token,
+                header,
+            })),
+            auth_failures: Arc::new(AtomicU32::new(0)),
+            max_auth_failures: Self::DEFAULT_MAX_AUTH_FAILURES,
+        })
+    }
+
+    /// Stop sending requests after the given number of consecutive responses rejecting the
+    /// subscription token, since every later request would be rejected too. Once tripped,
+    /// requests from this client and its clones fail immediately with
+    /// `ApiError::AuthCircuitOpen`. Zero disables the check.
+    #[must_use]
+    pub fn with_max_auth_failures(mut self, max_auth_failures: u32) -> Self {
+        self.max_auth_failures = max_auth_failures;
+        self
+    }
+
+    /// Pause all requests from this client and its clones while the API is down for maintenance
+    /// (HTTP 503), resuming once it is back. Requests fail as usual once an outage has lasted
+    /// `max_pause`. Zero disables pausing, so maintenance responses are retried like any other
+    /// server error.
+    #[must_use]
+    pub fn with_max_maintenance_pause(mut self, max_pause: time::Duration) -> Self {
+        self.maintenance = Arc::new(MaintenancePause::new(max_pause));
+        self
+    }
+
+    /// Configured request quota in requests per second to each host
+    pub fn quota(&self) -> NonZeroU32 {
+        self.governor.quota()
+    }
+
+    /// Change the request quota for every request made from now on. Clones of this client share
+    /// the change.
+    pub fn set_quota(&self, quota: NonZeroU32) {
+        self.governor.set_quota(quota);
+    }
+
+    /// Time left until requests resume, if they are paused for API maintenance
+    pub fn maintenance_remaining(&self) -> Option<time::Duration> {
+        self.maintenance.remaining()
+    }
+
+    /// Whether requests have been stopped because the subscription token was repeatedly rejected
+    pub fn auth_circuit_open(&self) -> bool {
+        self.max_auth_failures > 0
+            && self.auth_failures.load(Ordering::SeqCst) >= self.max_auth_failures
+    }
+
+    /// Send requests to the given base URL instead of nytimes.com, e.g. to point the client at a
+    /// mock server in tests. Endpoint paths are appended to it as-is.
+    #[must_use]
+    pub fn with_base_url<S: Into<String>>(mut self, api_base: S) -> Self {
+        self.api_base = api_base.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Send requests using the given pre-built HTTP client. The subscription token is still added
+    /// to each request, but the client must add any other default headers itself.
+    #[must_use]
+    pub fn with_http_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Save API responses to the given cache and revalidate them with conditional requests on
+    /// later runs, so that unchanged data isn't downloaded again
+    #[must_use]
+    pub fn with_cache(mut self, cache: ResponseCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Set the policy used to retry requests that fail due to transient errors
+    #[must_use]
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Make a rate-limited GET request, retrying transient failures according to the client's
+    /// `RetryPolicy`. If the server responds that we are being rate-limited, the quota is
+    /// temporarily lowered for all clones of this client.
+    async fn get<T: IntoUrl + Send>(&self, url: T) -> Result<reqwest::Response, ApiError> {
+        self.get_with_headers(url, HeaderMap::new()).await
+    }
+
+    /// Make a rate-limited GET request with additional headers. See `get`.
+    async fn get_with_headers<T: IntoUrl + Send>(
+        &self,
+        url: T,
+        headers: HeaderMap,
+    ) -> Result<reqwest::Response, ApiError> {
+        if self.auth_circuit_open() {
+            return Err(ApiError::AuthCircuitOpen {
+                failures: self.auth_failures.load(Ordering::SeqCst),
+            });
+        }
+        let url = url.into_url()?;
+        let governor = self.governor.for_host(url.host_str().unwrap_or_default());
+        let mut attempt = 1;
+        let mut refreshed = false;
+        loop {
+            let (token, (auth_name, auth_value)) = {
+                let auth = self.auth.read().unwrap();
+                (auth.token.clone(), auth.header.clone())
+            };
+            self.maintenance.until_ready().await;
+            governor.until_ready().await;
+            self.n_requests.fetch_add(1, Ordering::Relaxed);
+            let result = self
+                .client
+                .get(url.clone())
+                .headers(headers.clone())
+                .header(auth_name, auth_value)
+                .send()
+                .await;
+            let mut delay = self.retry_policy.delay(attempt);
+            match &result {
+                Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    let retry_after = retry_after(response);
+                    governor.on_rate_limited(retry_after);
+                    delay = delay.max(retry_after.unwrap_or_default());
+                }
+                Ok(response)
+                    if response.status().is_success()
+                        || response.status() == StatusCode::NOT_MODIFIED =>
+                {
+                    governor.on_success();
+                    self.maintenance.on_success();
+                }
+                Ok(response)
+                    if response.status() == StatusCode::SERVICE_UNAVAILABLE
+                        && self.maintenance.on_maintenance(retry_after(response)) =>
+                {
+                    // Maintenance pauses don't count as attempts
+                    continue;
+                }
+                _ => (),
+            }
+            if attempt >= self.retry_policy.max_attempts || !is_transient(&result) {
+                let response = result?;
+                match response.status() {
+                    StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                        if !refreshed && self.refresh_token(&token) {
+                            refreshed = true;
+                            continue;
+                        }
+                        self.auth_failures.fetch_add(1, Ordering::SeqCst);
+                    }
+                    status if status.is_success() => self.auth_failures.store(0, Ordering::SeqCst),
+                    _ => (),
+                }
+                return Ok(response);
+            }
+            match &result {
+                Ok(response) => warn!(
+                    "Request to {} failed with {}. Retrying in {:?}",
+                    url,
+                    response.status(),
+                    delay
+                ),
+                Err(e) => warn!("Request to {} failed: {}. Retrying in {:?}", url, e, delay),
+            }
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Replace the given rejected token with a new one from the client's `AuthProvider`. Returns
+    /// whether a different token is now in use, in which case the request should be retried.
+    fn refresh_token(&self, rejected: &SubscriptionToken) -> bool {
+        let mut auth = self.auth.write().unwrap();
+        if auth.token != *rejected {
+            // Another request already refreshed the token
+            return true;
+        }
+        let refreshed = auth.provider.refresh().and_then(|token| {
+            token
+                .filter(|token| token != rejected)
+                .map(|token| Ok((token.header()?, token)))
+                .transpose()
+        });
+        match refreshed {
+            Ok(Some((header, token))) => {
+                info!(
+                    "Refreshed subscription token from {}",
+                    auth.provider.describe()
+                );
+                auth.token = token;
+                auth.header = header;
+                true
+            }
+            Ok(None) => false,
+            Err(e) => {
+                warn!(
+                    "Failed to refresh subscription token from {}: {:#}",
+                    auth.provider.describe(),
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    /// Make a rate-limited GET request and parse the JSON response. If a cache is configured, a
+    /// previously-saved response is revalidated with a conditional request and reused if the
+    /// server reports that it hasn't changed. `date` is the date of the puzzle the response
+    /// belongs to, used to partition the cache.
+    async fn get_json<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        date: Option<NaiveDate>,
+    ) -> Result<T, ApiError> {
+        let Some(cache) = &self.cache else {
+            let response = self.get(url).await?;
+            check_status(&response)?;
+            return Ok(serde_json::from_str(&response.text().await?)?);
+        };
+
+        let cached = cache.get(url, date);
+        if let Some(cached) = cached.as_ref().filter(|c| cache.is_fresh(c)) {
+            debug!("Using cached response for {}", url);
+            return Ok(serde_json::from_str(&cached.body)?);
+        }
+        let mut headers = HeaderMap::new();
+        if let Some(cached) = &cached {
+            let validators = [
+                (header::IF_NONE_MATCH, &cached.etag),
+                (header::IF_MODIFIED_SINCE, &cached.last_modified),
+            ];
+            for (name, value) in validators {
+                if let Some(value) = value.as_deref().and_then(|v| v.parse().ok()) {
+                    headers.insert(name, value);
+                }
+            }
         }
+
+        let response = self.get_with_headers(url, headers).await?;
+        let now = Utc::now().timestamp();
+        let body = match cached {
+            Some(mut cached) if response.status() == StatusCode::NOT_MODIFIED => {
+                debug!("{} not modified, using cached response", url);
+                self.n_not_modified.fetch_add(1, Ordering::Relaxed);
+                cached.fetched_unix = now;
+                if let Err(e) = cache.put(&cached, date) {
+                    warn!("Failed to update cached response for {}: {:#}", url, e);
+                }
+                cached.body
+            }
+            _ => {
+                check_status(&response)?;
+                let header_value = |name| {
+                    response
+                        .headers()
+                        .get(name)
+                        .and_then(|v| v.to_str().ok())
+                        .map(str::to_string)
+                };
+                let etag = header_value(header::ETAG);
+                let last_modified = header_value(header::LAST_MODIFIED);
+                let body = response.text().await?;
+                let entry = CachedResponse {
+                    url: url.to_string(),
+                    etag,
+                    last_modified,
+                    fetched_unix: now,
+                    body,
+                };
+                if let Err(e) = cache.put(&entry, date) {
+                    warn!("Failed to cache response for {}: {:#}", url, e);
+                }
+                entry.body
+            }
+        };
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    /// Check that the subscription token is accepted by the NYT API by making a single cheap
+    /// authenticated request. Returns `ApiError::Unauthorized` if the token is invalid or expired.
+    pub async fn validate_token(&self) -> Result<(), ApiError> {
+        let url = self.api_url(Self::STATS_AND_STREAKS_ENDPOINT);
+        let response = self.get(&url).await?;
+        check_status(&response)
     }
 
-    /// Make a rate-limited GET request
-    async fn get<T: IntoUrl + Send>(&self, url: T) -> reqwest::Result<reqwest::Response> {
-        self.governor.until_ready().await;
-        self.n_requests.fetch_add(1, Ordering::Relaxed);
-        self.client.get(url).send().await
+    /// Make a request to the given endpoint without the response cache and return the response
+    /// as untyped JSON, e.g. to check its structure
+    pub async fn get_raw(&self, endpoint: &Endpoint) -> Result<serde_json::Value, ApiError> {
+        let response = self.get(self.api_url(&endpoint.path())).await?;
+        check_status(&response)?;
+        Ok(serde_json::from_str(&response.text().await?)?)
     }
 
-    fn api_url(endpoint: &str) -> String {
-        [Self::API_BASE, endpoint].join("")
+    fn api_url(&self, endpoint: &str) -> String {
+        [self.api_base.as_str(), endpoint].join("")
     }
 
     /// Return the number of HTTP requests made. This count includes requests made using cloned
@@ -170,30 +856,171 @@ impl RateLimitedClient {
     pub fn n_requests(&self) -> u32 {
         self.n_requests.load(Ordering::SeqCst)
     }
+
+    /// Return the number of requests answered with 304 Not Modified, for which the cached
+    /// response was reused
+    pub fn n_not_modified(&self) -> u32 {
+        self.n_not_modified.load(Ordering::SeqCst)
+    }
+}
+
+/// An NYT API endpoint used by this crate, along with the parameters of a request to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endpoint {
+    /// Ids and metadata of the puzzles published within a range of dates
+    PuzzleInfo {
+        publish_type: PublishType,
+        start: NaiveDate,
+        end: NaiveDate,
+    },
+    /// Solve stats of a single puzzle
+    PuzzleStats { id: u32 },
+    /// Grid and clues of a single puzzle
+    PuzzleContent {
+        publish_type: PublishType,
+        date: NaiveDate,
+    },
+    /// Official streaks and lifetime stats
+    StatsAndStreaks,
+}
+
+impl Endpoint {
+    /// Short name of the endpoint, e.g. for file names
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PuzzleInfo { .. } => "puzzle_info",
+            Self::PuzzleStats { .. } => "puzzle_stats",
+            Self::PuzzleContent { .. } => "puzzle_content",
+            Self::StatsAndStreaks => "stats_and_streaks",
+        }
+    }
+
+    /// Path of the request relative to the API base URL
+    fn path(&self) -> String {
+        match self {
+            Self::PuzzleInfo {
+                publish_type,
+                start,
+                end,
+            } => RateLimitedClient::PUZZLE_INFO_ENDPOINT
+                .replace("{publish_type}", publish_type.as_str())
+                .replace("{start_date}", &start.format("%Y-%m-%d").to_string())
+                .replace("{end_date}", &end.format("%Y-%m-%d").to_string()),
+            Self::PuzzleStats { id } => {
+                RateLimitedClient::PUZZLE_STATS_ENDPOINT.replace("{id}", &id.to_string())
+            }
+            Self::PuzzleContent { publish_type, date } => {
+                RateLimitedClient::PUZZLE_CONTENT_ENDPOINT
+                    .replace("{publish_type}", publish_type.as_str())
+                    .replace("{date}", &date.format("%Y-%m-%d").to_string())
+            }
+            Self::StatsAndStreaks => RateLimitedClient::STATS_AND_STREAKS_ENDPOINT.to_string(),
+        }
+    }
 }
 
-/// Get the crossword puzzle id for each crossword in the provided range. This id is needed to
-/// further query for solve stats.
+/// Get the crossword puzzle id for each crossword of the given type in the provided range. This id
+/// is needed to further query for solve stats.
 ///
 /// Returns a `HashMap` mapping `NaiveDate` dates to `u32` ids.
 pub async fn get_puzzle_ids(
     client: &RateLimitedClient,
+    publish_type: PublishType,
     start: NaiveDate,
     end: NaiveDate,
-) -> Result<HashMap<NaiveDate, u32>> {
-    let endpoint = RateLimitedClient::PUZZLE_INFO_ENDPOINT
-        .replace("{start_date}", &start.format("%Y-%m-%d").to_string())
-        .replace("{end_date}", &end.format("%Y-%m-%d").to_string());
-    let url = RateLimitedClient::api_url(&endpoint);
-    let response: PuzzleInfoResponse = client.get(&url).await?.json().await?;
-    Ok(response
-        .results
+) -> Result<HashMap<NaiveDate, u32>, ApiError> {
+    Ok(get_puzzle_info(client, publish_type, start, end)
+        .await?
         .into_iter()
-        .map(|metadata| (metadata.print_date, metadata.puzzle_id))
+        .map(|(date, info)| (date, info.puzzle_id))
         .collect())
 }
 
-/// Get solve statistics for the crossword with the given id
+/// Get the id, author, editor, and title of each crossword of the given type in the provided range.
+/// Responses that stop short of the end of the range are followed up with requests for the
+/// remaining dates.
+///
+/// Returns a `HashMap` mapping `NaiveDate` dates to `PuzzleInfo`.
+pub async fn get_puzzle_info(
+    client: &RateLimitedClient,
+    publish_type: PublishType,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<HashMap<NaiveDate, PuzzleInfo>, ApiError> {
+    let mut info = HashMap::new();
+    let mut page_start = start;
+    loop {
+        let endpoint = Endpoint::PuzzleInfo {
+            publish_type,
+            start: page_start,
+            end,
+        };
+        let url = client.api_url(&endpoint.path());
+        let response: PuzzleInfoResponse = match client.get_json(&url, Some(page_start)).await {
+            Ok(response) => response,
+            // A follow-up request past the last released puzzle may fail outright
+            Err(e) if !info.is_empty() => {
+                debug!(
+                    "Stopping at {} after follow-up request failed: {}",
+                    page_start, e
+                );
+                break;
+            }
+            Err(e) => return Err(e),
+        };
+        let Some(last) = response.results.iter().map(|m| m.print_date).max() else {
+            break;
+        };
+        info.extend(
+            response
+                .results
+                .into_iter()
+                .map(|metadata| (metadata.print_date, metadata.into())),
+        );
+        // The server caps the number of results per response. If the response stopped short of
+        // the end of the range, it may have been truncated, so ask for the rest. This costs one
+        // extra request when the range ends with unreleased puzzles.
+        if last >= end || last < page_start {
+            break;
+        }
+        debug!("Response for {} to {} ended at {}", page_start, end, last);
+        page_start = last + chrono::Duration::days(1);
+    }
+    Ok(info)
+}
+
+/// Get the grid and clues of the crossword of the given type published on the given date
+pub async fn get_puzzle_content(
+    client: &RateLimitedClient,
+    publish_type: PublishType,
+    date: NaiveDate,
+) -> Result<PuzzleContent, ApiError> {
+    let url = client.api_url(&Endpoint::PuzzleContent { publish_type, date }.path());
+    let response: PuzzleContentResponse = client.get_json(&url, Some(date)).await?;
+    // The body only ever seems to hold a single puzzle
+    response
+        .body
+        .into_iter()
+        .next()
+        .ok_or(ApiError::Status(StatusCode::NOT_FOUND))
+}
+
+/// Get the current and longest streaks for the daily crossword, as tracked by the NYT
+pub async fn get_streaks(client: &RateLimitedClient) -> Result<Streaks, ApiError> {
+    Ok(get_stats_and_streaks(client).await?.streaks)
+}
+
+/// Get the streaks and solve counts for the daily crossword, as tracked by the NYT
+pub async fn get_stats_and_streaks(
+    client: &RateLimitedClient,
+) -> Result<StatsAndStreaks, ApiError> {
+    let url = client.api_url(&Endpoint::StatsAndStreaks.path());
+    let response: StatsAndStreaksResponse = client.get_json(&url, None).await?;
+    Ok(response.results)
+}
+
+/// Get solve statistics for the crossword with the given id, published on the given date
 ///
 /// Returns a `Result` containing the statistics. If the provided `Option` is `None`, the puzzle
 /// was unsolved. Note that one must check the `SolvedPuzzleStats.cheated` field to see if a solved
@@ -201,9 +1028,22 @@ pub async fn get_puzzle_ids(
 pub async fn get_solve_stats(
     client: &RateLimitedClient,
     puzzle_id: u32,
-) -> Result<Option<SolvedPuzzleStats>> {
-    let endpoint = RateLimitedClient::PUZZLE_STATS_ENDPOINT.replace("{id}", &puzzle_id.to_string());
-    let url = RateLimitedClient::api_url(&endpoint);
-    let response: PuzzleStatsResponse = client.get(&url).await?.json().await?;
-    Ok(response.collect_stats())
+    date: NaiveDate,
+) -> Result<Option<SolvedPuzzleStats>, ApiError> {
+    Ok(get_solve_progress(client, puzzle_id, date).await?.solve)
+}
+
+/// Get the progress on the crossword with the given id, published on the given date: its solve
+/// statistics if it was solved, and when it was opened even if it wasn't
+pub async fn get_solve_progress(
+    client: &RateLimitedClient,
+    puzzle_id: u32,
+    date: NaiveDate,
+) -> Result<SolveProgress, ApiError> {
+    let url = client.api_url(&Endpoint::PuzzleStats { id: puzzle_id }.path());
+    let response: PuzzleStatsResponse = client.get_json(&url, Some(date)).await?;
+    Ok(SolveProgress {
+        solve: response.collect_stats(),
+        opened: response.firsts.and_then(|firsts| firsts.opened),
+    })
 }
@@ -0,0 +1,276 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checks of live NYT API responses against the structure this crate expects, to catch silent API
+//! changes before they break a run.
+//!
+//! A schema maps the path of each field to its JSON type. Paths join object keys with `.` and mark
+//! array elements with `[]`, e.g. `results[].puzzle_id`. Types ending in `?` may be missing or
+//! null. The expected schemas live in `schemas/` and only cover the fields that are parsed.
+
+use crate::api_client::{Endpoint, PublishType, RateLimitedClient};
+use anyhow::{Context, Result};
+use chrono::{naive::NaiveDate, Duration};
+use log::warn;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Field paths mapped to JSON types
+pub type Schema = BTreeMap<String, String>;
+
+/// Expected schema of the responses of each endpoint, by `Endpoint::name`
+const EXPECTED: [(&str, &str); 4] = [
+    ("puzzle_info", include_str!("../schemas/puzzle_info.json")),
+    ("puzzle_stats", include_str!("../schemas/puzzle_stats.json")),
+    (
+        "puzzle_content",
+        include_str!("../schemas/puzzle_content.json"),
+    ),
+    (
+        "stats_and_streaks",
+        include_str!("../schemas/stats_and_streaks.json"),
+    ),
+];
+
+/// Expected schema of the responses of the given endpoint
+pub fn expected(endpoint: &Endpoint) -> Result<Schema> {
+    let (_, json) = EXPECTED
+        .iter()
+        .find(|(name, _)| *name == endpoint.name())
+        .with_context(|| format!("No expected schema for {}", endpoint.name()))?;
+    serde_json::from_str(json)
+        .with_context(|| format!("Malformed expected schema for {}", endpoint.name()))
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Schema of a response. Fields seen with different types keep the first type other than null.
+#[must_use]
+pub fn observe(response: &Value) -> Schema {
+    fn walk(value: &Value, path: &str, schema: &mut Schema) {
+        if !path.is_empty() {
+            let kind = type_name(value);
+            let entry = schema
+                .entry(path.to_string())
+                .or_insert_with(|| kind.into());
+            if entry == "null" {
+                *entry = kind.into();
+            }
+        }
+        match value {
+            Value::Array(elements) => {
+                let path = format!("{path}[]");
+                for element in elements {
+                    walk(element, &path, schema);
+                }
+            }
+            Value::Object(fields) => {
+                for (key, field) in fields {
+                    let path = if path.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{path}.{key}")
+                    };
+                    walk(field, &path, schema);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    let mut schema = Schema::new();
+    walk(response, "", &mut schema);
+    schema
+}
+
+/// Path of the object or array holding the field at the given path, if it isn't top-level
+fn parent(path: &str) -> Option<&str> {
+    path.strip_suffix("[]")
+        .or_else(|| path.rfind('.').map(|i| &path[..i]))
+}
+
+/// How a response differs from its expected schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DriftKind {
+    /// A required field is missing
+    Missing { expected: String },
+    /// A field has a different type than expected
+    Changed { expected: String, actual: String },
+    /// A field that isn't in the expected schema. Only reported for the outermost new field.
+    Added { actual: String },
+}
+
+/// A difference between a response and its expected schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Drift {
+    pub endpoint: &'static str,
+    pub path: String,
+    pub kind: DriftKind,
+}
+
+impl Drift {
+    /// Whether the difference can break parsing, as opposed to a new field
+    #[must_use]
+    pub fn is_breaking(&self) -> bool {
+        !matches!(self.kind, DriftKind::Added { .. })
+    }
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            DriftKind::Missing { expected } => write!(
+                f,
+                "- {} {}: missing, expected {}",
+                self.endpoint, self.path, expected
+            ),
+            DriftKind::Changed { expected, actual } => write!(
+                f,
+                "! {} {}: {}, expected {}",
+                self.endpoint, self.path, actual, expected
+            ),
+            DriftKind::Added { actual } => {
+                write!(f, "+ {} {}: {}", self.endpoint, self.path, actual)
+            }
+        }
+    }
+}
+
+/// Differences between the observed schema of a response and the expected one
+#[must_use]
+pub fn compare(endpoint: &'static str, expected: &Schema, observed: &Schema) -> Vec<Drift> {
+    // Fields of a container that is absent or null can't be checked
+    let present = |path: &str| {
+        parent(path).is_none_or(|parent| observed.get(parent).is_some_and(|kind| kind != "null"))
+    };
+    let drift = |path: &str, kind| Drift {
+        endpoint,
+        path: path.to_string(),
+        kind,
+    };
+
+    let mut drifts = Vec::new();
+    for (path, spec) in expected {
+        let kind = spec.trim_end_matches('?');
+        let optional = kind.len() < spec.len();
+        match observed.get(path) {
+            None if !optional && present(path) => drifts.push(drift(
+                path,
+                DriftKind::Missing {
+                    expected: spec.clone(),
+                },
+            )),
+            Some(actual) if actual != kind && !(optional && actual == "null") => {
+                drifts.push(drift(
+                    path,
+                    DriftKind::Changed {
+                        expected: spec.clone(),
+                        actual: actual.clone(),
+                    },
+                ))
+            }
+            _ => (),
+        }
+    }
+    for (path, actual) in observed {
+        let parent_expected = parent(path).is_none_or(|parent| expected.contains_key(parent));
+        if parent_expected && !expected.contains_key(path) {
+            drifts.push(drift(
+                path,
+                DriftKind::Added {
+                    actual: actual.clone(),
+                },
+            ));
+        }
+    }
+    drifts
+}
+
+/// A live response from an endpoint, along with how it differs from the expected schema
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub endpoint: Endpoint,
+    pub observed: Schema,
+    pub drifts: Vec<Drift>,
+}
+
+async fn check(client: &RateLimitedClient, endpoint: Endpoint) -> Result<(Check, Value)> {
+    let response = client
+        .get_raw(&endpoint)
+        .await
+        .with_context(|| format!("Failed to fetch {}", endpoint.name()))?;
+    let observed = observe(&response);
+    let drifts = compare(endpoint.name(), &expected(&endpoint)?, &observed);
+    Ok((
+        Check {
+            endpoint,
+            observed,
+            drifts,
+        },
+        response,
+    ))
+}
+
+/// Fetch one live response from each endpoint and compare it against its expected schema. The
+/// puzzle-specific endpoints are checked with the latest daily puzzle published in the week up to
+/// `today`.
+pub async fn check_all(client: &RateLimitedClient, today: NaiveDate) -> Result<Vec<Check>> {
+    let (info, response) = check(
+        client,
+        Endpoint::PuzzleInfo {
+            publish_type: PublishType::Daily,
+            start: today - Duration::days(6),
+            end: today,
+        },
+    )
+    .await?;
+    // Parse just enough of the response to find a puzzle, so that checks of the other endpoints
+    // still run if the rest of it has drifted
+    let latest = response["results"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|puzzle| {
+            let date: NaiveDate = puzzle["print_date"].as_str()?.parse().ok()?;
+            let id = u32::try_from(puzzle["puzzle_id"].as_u64()?).ok()?;
+            Some((date, id))
+        })
+        .max();
+    let mut checks = vec![info];
+    if let Some((date, id)) = latest {
+        checks.push(check(client, Endpoint::PuzzleStats { id }).await?.0);
+        let content = Endpoint::PuzzleContent {
+            publish_type: PublishType::Daily,
+            date,
+        };
+        checks.push(check(client, content).await?.0);
+    } else {
+        warn!(
+            "No puzzles published since {}, so puzzle endpoints were not checked",
+            today
+        );
+    }
+    checks.push(check(client, Endpoint::StatsAndStreaks).await?.0);
+    Ok(checks)
+}
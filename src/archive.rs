@@ -0,0 +1,154 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Local archive of puzzle grids and clues, saved as ipuz files
+
+use crate::api_client::{self, PublishType, PuzzleContent, RateLimitedClient};
+use crate::{PuzzleStats, RecordKey};
+use anyhow::{Context, Result};
+use futures::future;
+use log::error;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// Counts of what happened to each puzzle during an archive run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ArchiveSummary {
+    /// Puzzles newly saved to the archive
+    pub saved: u32,
+    /// Puzzles that were already archived
+    pub skipped: u32,
+    /// Puzzles whose content could not be retrieved or saved
+    pub errors: u32,
+}
+
+/// Path of the archived puzzle for the given record within the given directory. Puzzles other
+/// than the daily crossword have their publish type appended to the file name.
+#[must_use]
+pub fn puzzle_path(dir: &Path, (date, publish_type): RecordKey) -> PathBuf {
+    match publish_type {
+        PublishType::Daily => dir.join(format!("{date}.ipuz")),
+        other => dir.join(format!("{date}-{other}.ipuz")),
+    }
+}
+
+/// Convert a puzzle to an ipuz document. Descriptive fields are taken from the given record.
+#[must_use]
+pub fn to_ipuz(content: &PuzzleContent, record: &PuzzleStats) -> Value {
+    let width = content.dimensions.width;
+    let rows = content.cells.chunks(width.max(1));
+    let puzzle: Vec<Vec<Value>> = rows
+        .clone()
+        .map(|row| {
+            row.iter()
+                .map(|cell| {
+                    if cell.answer.is_none() {
+                        return json!("#");
+                    }
+                    let label = cell.label.as_deref().map_or(json!(0), |label| {
+                        label
+                            .parse::<u32>()
+                            .map_or_else(|_| json!(label), |n| json!(n))
+                    });
+                    match cell.kind {
+                        Some(2) => json!({ "cell": label, "style": { "shapebg": "circle" } }),
+                        Some(3) => json!({ "cell": label, "style": { "highlight": true } }),
+                        _ => label,
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    let solution: Vec<Vec<Value>> = rows
+        .map(|row| {
+            row.iter()
+                .map(|cell| json!(cell.answer.as_deref().unwrap_or("#")))
+                .collect()
+        })
+        .collect();
+    let clues = |direction: &str| -> Vec<Value> {
+        content
+            .clues
+            .iter()
+            .filter(|clue| clue.direction == direction)
+            .map(|clue| {
+                let text = clue.text.first().map_or("", |text| text.plain.as_str());
+                json!([clue.label, text])
+            })
+            .collect()
+    };
+
+    let title = record
+        .title
+        .clone()
+        .unwrap_or_else(|| record.date.format("%A, %B %-d, %Y").to_string());
+
+    json!({
+        "version": "http://ipuz.org/v2",
+        "kind": ["http://ipuz.org/crossword#1"],
+        "publisher": "The New York Times",
+        "date": record.date.format("%m/%d/%Y").to_string(),
+        "title": title,
+        "author": record.author,
+        "editor": record.editor,
+        "block": "#",
+        "empty": 0,
+        "dimensions": { "width": width, "height": content.dimensions.height },
+        "puzzle": puzzle,
+        "solution": solution,
+        "clues": { "Across": clues("Across"), "Down": clues("Down") },
+    })
+}
+
+/// Save the grid and clues of each of the given puzzles to the given directory. Puzzles that are
+/// already archived are skipped.
+pub async fn archive(
+    client: &RateLimitedClient,
+    records: &[PuzzleStats],
+    dir: &Path,
+) -> Result<ArchiveSummary> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create archive directory {}", dir.display()))?;
+    let mut summary = ArchiveSummary::default();
+    let missing: Vec<&PuzzleStats> = records
+        .iter()
+        .filter(|record| !puzzle_path(dir, record.key()).exists())
+        .collect();
+    summary.skipped = u32::try_from(records.len() - missing.len())?;
+
+    let fetches = missing.into_iter().map(|record| async move {
+        let content =
+            api_client::get_puzzle_content(client, record.publish_type, record.date).await;
+        (record, content)
+    });
+    for (record, content) in future::join_all(fetches).await {
+        let saved = content.map_err(anyhow::Error::from).and_then(|content| {
+            let path = puzzle_path(dir, record.key());
+            let file = File::create(&path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            serde_json::to_writer(file, &to_ipuz(&content, record))?;
+            Ok(())
+        });
+        match saved {
+            Ok(()) => summary.saved += 1,
+            Err(e) => {
+                error!("Failed to archive puzzle for {}: {:#}", record.date, e);
+                summary.errors += 1;
+            }
+        }
+    }
+    Ok(summary)
+}
@@ -0,0 +1,89 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sources of the NYT subscription token
+
+use crate::api_client::SubscriptionToken;
+#[cfg(feature = "browser-cookies")]
+use crate::browser::{self, Browser};
+use anyhow::Result;
+use std::fmt;
+use std::path::PathBuf;
+
+/// A source of the NYT subscription token. The client asks its provider for a token when it is
+/// built, and again whenever the NYT API rejects the token in use.
+pub trait AuthProvider: fmt::Debug + Send + Sync {
+    /// Short description of where the token comes from, for logging
+    fn describe(&self) -> String;
+
+    /// Get the current token
+    fn token(&self) -> Result<SubscriptionToken>;
+
+    /// Get a replacement for a token that was rejected. Returns `None` if the provider has no way
+    /// of getting a different token.
+    ///
+    /// By default, the provider is asked for its current token again, which picks up a token that
+    /// has been renewed at the source since it was last read.
+    fn refresh(&self) -> Result<Option<SubscriptionToken>> {
+        self.token().map(Some)
+    }
+}
+
+/// A fixed token, e.g. one passed on the command line or in an environment variable
+#[derive(Debug, Clone)]
+pub struct StaticToken(pub SubscriptionToken);
+
+impl AuthProvider for StaticToken {
+    fn describe(&self) -> String {
+        "the provided token".to_string()
+    }
+
+    fn token(&self) -> Result<SubscriptionToken> {
+        Ok(self.0.clone())
+    }
+
+    fn refresh(&self) -> Result<Option<SubscriptionToken>> {
+        Ok(None)
+    }
+}
+
+/// The NYT-S cookie in a cookies.txt file, re-read whenever the token is rejected
+#[derive(Debug, Clone)]
+pub struct CookieFile(pub PathBuf);
+
+impl AuthProvider for CookieFile {
+    fn describe(&self) -> String {
+        format!("cookie file {}", self.0.display())
+    }
+
+    fn token(&self) -> Result<SubscriptionToken> {
+        SubscriptionToken::from_cookie_file(&self.0)
+    }
+}
+
+/// The NYT-S cookie in a local browser profile, re-read whenever the token is rejected
+#[cfg(feature = "browser-cookies")]
+#[derive(Debug, Clone, Copy)]
+pub struct BrowserCookies(pub Browser);
+
+#[cfg(feature = "browser-cookies")]
+impl AuthProvider for BrowserCookies {
+    fn describe(&self) -> String {
+        format!("{:?} cookies", self.0)
+    }
+
+    fn token(&self) -> Result<SubscriptionToken> {
+        browser::subscription_token(self.0)
+    }
+}
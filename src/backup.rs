@@ -0,0 +1,58 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rotating backups of the database file, taken before it is overwritten
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Path of the `n`th most recent backup of the database at the given path, e.g. `data.csv.bak.1`
+#[must_use]
+pub fn backup_path(db_path: &Path, n: usize) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push(format!(".bak.{n}"));
+    path.into()
+}
+
+/// Copy the database at the given path to `<path>.bak.1`, shifting older backups up by one and
+/// deleting any beyond `keep`. Does nothing if `keep` is zero or the database doesn't exist yet.
+pub fn rotate(db_path: &Path, keep: usize) -> Result<()> {
+    if keep == 0 || !db_path.exists() {
+        return Ok(());
+    }
+    let oldest = backup_path(db_path, keep);
+    if oldest.exists() {
+        fs::remove_file(&oldest)
+            .with_context(|| format!("Failed to remove {}", oldest.display()))?;
+    }
+    for n in (1..keep).rev() {
+        let from = backup_path(db_path, n);
+        if from.exists() {
+            let to = backup_path(db_path, n + 1);
+            fs::rename(&from, &to).with_context(|| {
+                format!("Failed to move {} to {}", from.display(), to.display())
+            })?;
+        }
+    }
+    let newest = backup_path(db_path, 1);
+    fs::copy(db_path, &newest).with_context(|| {
+        format!(
+            "Failed to back up {} to {}",
+            db_path.display(),
+            newest.display()
+        )
+    })?;
+    Ok(())
+}
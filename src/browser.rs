@@ -0,0 +1,146 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Extract the NYT-S cookie from a local browser profile so it doesn't have to be copied out of the
+//! browser's developer tools by hand
+
+use crate::api_client::SubscriptionToken;
+use anyhow::{Context, Result};
+use log::debug;
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Browsers whose cookie stores can be read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Browser {
+    Firefox,
+    /// Google Chrome or Chromium. Only works if the cookie is stored unencrypted, which is not the
+    /// default on most platforms.
+    Chrome,
+}
+
+impl Browser {
+    /// Directories that may contain browser profiles, relative to the user's home directory
+    fn profile_roots(self) -> Vec<PathBuf> {
+        let home = env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+        let appdata = env::var_os("APPDATA")
+            .map(PathBuf::from)
+            .unwrap_or_default();
+        let local_appdata = env::var_os("LOCALAPPDATA")
+            .map(PathBuf::from)
+            .unwrap_or_default();
+        match self {
+            Self::Firefox => vec![
+                home.join(".mozilla/firefox"),
+                home.join("snap/firefox/common/.mozilla/firefox"),
+                home.join("Library/Application Support/Firefox/Profiles"),
+                appdata.join("Mozilla/Firefox/Profiles"),
+            ],
+            Self::Chrome => vec![
+                home.join(".config/google-chrome"),
+                home.join(".config/chromium"),
+                home.join("Library/Application Support/Google/Chrome"),
+                local_appdata.join("Google/Chrome/User Data"),
+            ],
+        }
+    }
+
+    /// Paths of the cookie database within a profile directory
+    fn cookie_files(self) -> &'static [&'static str] {
+        match self {
+            Self::Firefox => &["cookies.sqlite"],
+            Self::Chrome => &["Network/Cookies", "Cookies"],
+        }
+    }
+
+    /// Find all cookie databases belonging to this browser
+    fn cookie_databases(self) -> Vec<PathBuf> {
+        let mut databases = Vec::new();
+        for root in self.profile_roots() {
+            let Ok(profiles) = fs::read_dir(&root) else {
+                continue;
+            };
+            for profile in profiles.flatten() {
+                for file in self.cookie_files() {
+                    let path = profile.path().join(file);
+                    if path.is_file() {
+                        databases.push(path);
+                    }
+                }
+            }
+        }
+        databases
+    }
+
+    fn query(self) -> &'static str {
+        match self {
+            Self::Firefox => {
+                "SELECT value, 0 FROM moz_cookies WHERE name = 'NYT-S' \
+                 AND host LIKE '%nytimes.com' ORDER BY lastAccessed DESC LIMIT 1"
+            }
+            Self::Chrome => {
+                "SELECT value, length(encrypted_value) FROM cookies WHERE name = 'NYT-S' \
+                 AND host_key LIKE '%nytimes.com' ORDER BY last_access_utc DESC LIMIT 1"
+            }
+        }
+    }
+}
+
+/// Read the NYT-S cookie from a single cookie database. The database is copied first, since
+/// browsers keep it locked while running.
+fn read_cookie(browser: Browser, path: &Path) -> Result<Option<String>> {
+    let dir = tempfile::tempdir()?;
+    let copy = dir.path().join("cookies.sqlite");
+    fs::copy(path, &copy).with_context(|| format!("Failed to copy {}", path.display()))?;
+    // Recent writes may still be in the write-ahead log
+    let mut wal = path.as_os_str().to_owned();
+    wal.push("-wal");
+    if Path::new(&wal).exists() {
+        fs::copy(&wal, dir.path().join("cookies.sqlite-wal"))?;
+    }
+
+    let connection = Connection::open_with_flags(&copy, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let row: Option<(String, i64)> = connection
+        .query_row(browser.query(), [], |row| Ok((row.get(0)?, row.get(1)?)))
+        .optional()?;
+    match row {
+        Some((value, _)) if !value.is_empty() => Ok(Some(value)),
+        Some((_, encrypted_len)) if encrypted_len > 0 => anyhow::bail!(
+            "The NYT-S cookie in {} is encrypted by the browser and can't be read. \
+             Use Firefox or pass the token explicitly instead.",
+            path.display()
+        ),
+        _ => Ok(None),
+    }
+}
+
+/// Find the NYT-S cookie in the given browser's local cookie store
+pub fn subscription_token(browser: Browser) -> Result<SubscriptionToken> {
+    let databases = browser.cookie_databases();
+    if databases.is_empty() {
+        anyhow::bail!("Couldn't find a {:?} profile with a cookie store", browser);
+    }
+    for database in databases {
+        debug!("Looking for NYT-S cookie in {}", database.display());
+        if let Some(cookie) = read_cookie(browser, &database)? {
+            return Ok(SubscriptionToken::Cookie(cookie));
+        }
+    }
+    anyhow::bail!(
+        "No NYT-S cookie found in any {:?} profile. Log in to nytimes.com in the browser first.",
+        browser
+    )
+}
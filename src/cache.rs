@@ -0,0 +1,273 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! On-disk store of API responses and their validators, used to make conditional requests.
+//!
+//! Responses are partitioned into `YYYY/MM` directories by the date of the puzzle they belong to,
+//! with an `index.json` in each partition listing its responses. Responses that don't belong to a
+//! single puzzle are kept in `undated`.
+
+use anyhow::{Context, Result};
+use chrono::{naive::NaiveDate, Datelike, Months, Utc};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Partition for responses that don't belong to a single puzzle
+const UNDATED: &str = "undated";
+/// Name of the index file within each partition
+const INDEX_FILE: &str = "index.json";
+
+/// A response body saved along with the headers needed to revalidate it
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CachedResponse {
+    pub url: String,
+    /// Value of the `ETag` header, sent back as `If-None-Match`
+    pub etag: Option<String>,
+    /// Value of the `Last-Modified` header, sent back as `If-Modified-Since`
+    pub last_modified: Option<String>,
+    /// Unix time at which the response was received or last revalidated
+    pub fetched_unix: i64,
+    pub body: String,
+}
+
+/// When a cached response can be used without checking with the server first
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Freshness {
+    /// Always revalidate cached responses with a conditional request
+    #[default]
+    Revalidate,
+    /// Use cached responses younger than the given age as-is, and revalidate older ones
+    MaxAge(Duration),
+    /// Use any cached response as-is. Only URLs that aren't cached are requested.
+    Always,
+}
+
+/// Entry in a partition's index
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct IndexEntry {
+    pub url: String,
+    /// Name of the response's file within the partition
+    pub file: String,
+    /// Date of the puzzle the response belongs to
+    pub date: Option<NaiveDate>,
+    /// Unix time at which the response was received or last revalidated
+    pub fetched_unix: i64,
+}
+
+/// Counts of what was removed by `ResponseCache::prune`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PruneSummary {
+    /// Month partitions removed
+    pub months: usize,
+    /// Responses removed
+    pub responses: usize,
+}
+
+/// Directory of cached responses, one JSON file per URL
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    dir: PathBuf,
+    freshness: Freshness,
+    /// Serializes updates to the partition indexes
+    index_lock: Arc<Mutex<()>>,
+}
+
+/// 64-bit FNV-1a hash. Used instead of `DefaultHasher`, whose output may change between Rust
+/// releases, so that file names stay stable.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+impl ResponseCache {
+    /// Open the cache at the given directory, creating it if needed
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create cache directory {}", dir.display()))?;
+        Ok(Self {
+            dir,
+            freshness: Freshness::default(),
+            index_lock: Arc::new(Mutex::new(())),
+        })
+    }
+
+    /// Set when cached responses can be used without a request
+    #[must_use]
+    pub fn with_freshness(mut self, freshness: Freshness) -> Self {
+        self.freshness = freshness;
+        self
+    }
+
+    /// Whether the given cached response can be used without revalidating it
+    #[must_use]
+    pub fn is_fresh(&self, response: &CachedResponse) -> bool {
+        match self.freshness {
+            Freshness::Revalidate => false,
+            Freshness::MaxAge(max_age) => {
+                let age = Utc::now().timestamp().saturating_sub(response.fetched_unix);
+                u64::try_from(age).is_ok_and(|age| age < max_age.as_secs())
+            }
+            Freshness::Always => true,
+        }
+    }
+
+    /// Directory holding the responses for puzzles published in the given date's month
+    fn partition(&self, date: Option<NaiveDate>) -> PathBuf {
+        match date {
+            Some(date) => self
+                .dir
+                .join(format!("{:04}", date.year()))
+                .join(format!("{:02}", date.month())),
+            None => self.dir.join(UNDATED),
+        }
+    }
+
+    fn file_name(url: &str) -> String {
+        format!("{:016x}.json", fnv1a(url.as_bytes()))
+    }
+
+    /// Look up the saved response for the given URL, which belongs to the puzzle published on the
+    /// given date. Unreadable entries are treated as missing.
+    #[must_use]
+    pub fn get(&self, url: &str, date: Option<NaiveDate>) -> Option<CachedResponse> {
+        let file_name = Self::file_name(url);
+        // Caches written before responses were partitioned keep every file at the top level
+        let file = File::open(self.partition(date).join(&file_name))
+            .or_else(|_| File::open(self.dir.join(&file_name)))
+            .ok()?;
+        match serde_json::from_reader::<_, CachedResponse>(file) {
+            // Guard against hash collisions
+            Ok(cached) if cached.url == url => Some(cached),
+            Ok(_) => None,
+            Err(e) => {
+                debug!("Ignoring unreadable cache entry for {}: {}", url, e);
+                None
+            }
+        }
+    }
+
+    /// Save a response for the puzzle published on the given date, replacing any previous entry
+    /// for the same URL
+    pub fn put(&self, response: &CachedResponse, date: Option<NaiveDate>) -> Result<()> {
+        let partition = self.partition(date);
+        fs::create_dir_all(&partition)
+            .with_context(|| format!("Failed to create {}", partition.display()))?;
+        let file_name = Self::file_name(&response.url);
+        let path = partition.join(&file_name);
+        let file =
+            File::create(&path).with_context(|| format!("Failed to create {}", path.display()))?;
+        serde_json::to_writer(file, response)?;
+        // Drop any copy left over from before responses were partitioned
+        let _ = fs::remove_file(self.dir.join(&file_name));
+
+        let _guard = self.index_lock.lock().unwrap();
+        let mut index = read_index(&partition)?;
+        index.retain(|entry| entry.url != response.url);
+        index.push(IndexEntry {
+            url: response.url.clone(),
+            file: file_name,
+            date,
+            fetched_unix: response.fetched_unix,
+        });
+        index.sort_by(|a, b| (a.date, &a.url).cmp(&(b.date, &b.url)));
+        let index_path = partition.join(INDEX_FILE);
+        let file = File::create(&index_path)
+            .with_context(|| format!("Failed to create {}", index_path.display()))?;
+        serde_json::to_writer(file, &index)?;
+        Ok(())
+    }
+
+    /// Index entries for the responses saved for puzzles published within the given range,
+    /// inclusive. Only the partitions of the months in the range are read.
+    pub fn entries(&self, start: NaiveDate, end: NaiveDate) -> Result<Vec<IndexEntry>> {
+        let mut entries = Vec::new();
+        let mut month = start
+            .with_day(1)
+            .expect("First of the month is a valid date");
+        while month <= end {
+            entries.extend(
+                read_index(&self.partition(Some(month)))?
+                    .into_iter()
+                    .filter(|entry| entry.date.is_some_and(|d| d >= start && d <= end)),
+            );
+            let Some(next) = month.checked_add_months(Months::new(1)) else {
+                break;
+            };
+            month = next;
+        }
+        Ok(entries)
+    }
+
+    /// Delete the partitions of all but the given number of latest months. Undated responses are
+    /// kept.
+    pub fn prune(&self, keep_latest: usize) -> Result<PruneSummary> {
+        let mut months = Vec::new();
+        for year in numbered_dirs(&self.dir)? {
+            for month in numbered_dirs(&year)? {
+                months.push(month);
+            }
+        }
+        // Zero-padded names sort chronologically
+        months.sort();
+        let mut summary = PruneSummary::default();
+        let n_remove = months.len().saturating_sub(keep_latest);
+        for month in &months[..n_remove] {
+            summary.responses += fs::read_dir(month)?
+                .filter_map(Result::ok)
+                .filter(|entry| entry.file_name() != INDEX_FILE)
+                .count();
+            fs::remove_dir_all(month)
+                .with_context(|| format!("Failed to remove {}", month.display()))?;
+            summary.months += 1;
+            if let Some(year) = month.parent() {
+                // Only succeeds once the year is empty
+                let _ = fs::remove_dir(year);
+            }
+        }
+        Ok(summary)
+    }
+}
+
+/// Read the index of the given partition. A missing index is treated as empty.
+fn read_index(partition: &Path) -> Result<Vec<IndexEntry>> {
+    let path = partition.join(INDEX_FILE);
+    match File::open(&path) {
+        Ok(file) => serde_json::from_reader(file)
+            .with_context(|| format!("Malformed cache index {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e).with_context(|| format!("Failed to open {}", path.display())),
+    }
+}
+
+/// Subdirectories of the given directory whose names are all digits, i.e. year or month partitions
+fn numbered_dirs(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let entry = entry?;
+        let is_numbered = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| !name.is_empty() && name.bytes().all(|b| b.is_ascii_digit()));
+        if is_numbered && entry.file_type()?.is_dir() {
+            dirs.push(entry.path());
+        }
+    }
+    Ok(dirs)
+}
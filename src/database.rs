@@ -12,93 +12,823 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::PuzzleStats;
+use crate::api_client::PublishType;
+use crate::backup;
+use crate::leaderboard::{Leaderboard, PersonalBest};
+use crate::merge::{Conflict, MergeSummary, Precedence};
+use crate::schema::{Schema, SCHEMA_VERSION};
+use crate::timezone::HomeTimezone;
+use crate::{PuzzleStats, RecordKey};
 use anyhow::{Context, Result};
-use chrono::naive::NaiveDate;
-use log::{error, warn};
+use chrono::{naive::NaiveDate, Datelike, Weekday};
+use log::{info, warn};
 use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
-use std::io::Read;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::ops::RangeBounds;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Where a `Database` persists its records. `Database` keeps every record in memory and only
+/// reads from the store when opened and writes to it when flushed.
+pub trait StorageBackend: fmt::Debug + Send {
+    /// Read every stored record
+    fn load(&self) -> Result<HashMap<RecordKey, PuzzleStats>>;
+
+    /// Store the given records, sorted by key. Every record in the database is passed on each
+    /// save.
+    fn save(&self, records: &[&PuzzleStats]) -> Result<()>;
+}
+
+/// Which record to keep when a loaded database has more than one record for the same puzzle
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DuplicatePolicy {
+    /// Keep the first record read
+    KeepFirst,
+    /// Keep the last record read
+    #[default]
+    KeepLast,
+    /// Keep the record with the most columns filled in, falling back to the last record read
+    KeepMostComplete,
+    /// Fail to load the database
+    Error,
+}
+
+impl DuplicatePolicy {
+    /// Choose between a record that was already read and a later duplicate of it
+    pub(crate) fn choose(self, earlier: PuzzleStats, later: PuzzleStats) -> Result<PuzzleStats> {
+        match self {
+            Self::KeepFirst => Ok(earlier),
+            Self::KeepLast => Ok(later),
+            Self::KeepMostComplete => {
+                if earlier.filled_columns() > later.filled_columns() {
+                    Ok(earlier)
+                } else {
+                    Ok(later)
+                }
+            }
+            Self::Error => {
+                let (date, publish_type) = later.key();
+                anyhow::bail!("Duplicate {} record for {}", publish_type, date)
+            }
+        }
+    }
+}
+
+/// When the fields of a CSV file are quoted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum QuoteStyle {
+    /// Only fields that contain the delimiter, a quote, or a line break
+    #[default]
+    Necessary,
+    /// Every field
+    Always,
+    /// Every field that isn't a number
+    NonNumeric,
+}
+
+impl From<QuoteStyle> for csv::QuoteStyle {
+    fn from(style: QuoteStyle) -> Self {
+        match style {
+            QuoteStyle::Necessary => Self::Necessary,
+            QuoteStyle::Always => Self::Always,
+            QuoteStyle::NonNumeric => Self::NonNumeric,
+        }
+    }
+}
+
+/// How the fields of a CSV file are separated and quoted when it's written. Files are read with
+/// whichever delimiter their header uses; see `csv_reader`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvDialect {
+    pub delimiter: u8,
+    pub quote_style: QuoteStyle,
+}
+
+impl Default for CsvDialect {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote_style: QuoteStyle::default(),
+        }
+    }
+}
+
+impl CsvDialect {
+    /// Builder of CSV writers that use the dialect
+    #[must_use]
+    pub fn writer(&self) -> csv::WriterBuilder {
+        let mut builder = csv::WriterBuilder::new();
+        builder
+            .delimiter(self.delimiter)
+            .quote_style(self.quote_style.into());
+        builder
+    }
+}
+
+/// Parse a CSV delimiter: `tab` or a single ASCII character, e.g. `;`
+pub fn parse_delimiter(s: &str) -> Result<u8> {
+    match s.as_bytes() {
+        _ if s.eq_ignore_ascii_case("tab") => Ok(b'\t'),
+        [delimiter] if delimiter.is_ascii() && *delimiter != b'"' && *delimiter != b'\n' => {
+            Ok(*delimiter)
+        }
+        _ => anyhow::bail!(
+            "Invalid delimiter {:?}: expected `tab` or a single character",
+            s
+        ),
+    }
+}
+
+/// Reader of a CSV file that uses whichever delimiter its header does: a comma if the header has
+/// one, and otherwise a tab or semicolon if it has one of those. Rows may have fewer fields than
+/// the header.
+pub fn csv_reader<R: Read>(reader: R) -> Result<csv::Reader<BufReader<R>>> {
+    let mut reader = BufReader::new(reader);
+    let buffered = reader.fill_buf()?;
+    let header = buffered.split(|&b| b == b'\n').next().unwrap_or_default();
+    let delimiter = [b',', b'\t', b';']
+        .into_iter()
+        .find(|delimiter| header.contains(delimiter))
+        .unwrap_or(b',');
+    Ok(csv::ReaderBuilder::new()
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_reader(reader))
+}
+
+/// Records stored in a CSV file, one row per record
+#[derive(Debug, Clone)]
+pub struct CsvStorage {
+    path: PathBuf,
+    duplicates: DuplicatePolicy,
+    dialect: CsvDialect,
+}
+
+impl CsvStorage {
+    #[must_use]
+    pub fn new<T: Into<PathBuf>>(path: T) -> Self {
+        Self {
+            path: path.into(),
+            duplicates: DuplicatePolicy::default(),
+            dialect: CsvDialect::default(),
+        }
+    }
+
+    /// Set which record to keep if the file has more than one record for the same puzzle
+    #[must_use]
+    pub fn with_duplicate_policy(mut self, policy: DuplicatePolicy) -> Self {
+        self.duplicates = policy;
+        self
+    }
+
+    /// Set how the file is written
+    #[must_use]
+    pub fn with_dialect(mut self, dialect: CsvDialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+}
+
+impl StorageBackend for CsvStorage {
+    fn load(&self) -> Result<HashMap<RecordKey, PuzzleStats>> {
+        let file = File::open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        deserialize_records(file, self.duplicates)
+            .with_context(|| format!("Failed to load {}", self.path.display()))
+    }
+
+    fn save(&self, records: &[&PuzzleStats]) -> Result<()> {
+        let file = File::create(&self.path)
+            .with_context(|| format!("Failed to create {}", self.path.display()))?;
+        write_csv_as(BufWriter::new(file), records, self.dialect)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+/// Records stored in an SQLite database. Requires the `sqlite` feature.
+#[derive(Debug, Clone)]
+pub struct SqliteStorage(pub PathBuf);
+
+impl StorageBackend for SqliteStorage {
+    fn load(&self) -> Result<HashMap<RecordKey, PuzzleStats>> {
+        #[cfg(feature = "sqlite")]
+        return crate::sqlite::load(&self.0);
+        #[cfg(not(feature = "sqlite"))]
+        return Err(sqlite_unsupported(&self.0));
+    }
+
+    #[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+    fn save(&self, records: &[&PuzzleStats]) -> Result<()> {
+        #[cfg(feature = "sqlite")]
+        return crate::sqlite::save(&self.0, records.iter().copied());
+        #[cfg(not(feature = "sqlite"))]
+        return Err(sqlite_unsupported(&self.0));
+    }
+}
+
+/// Records kept in memory, e.g. for tests. Clones share the same records, so a clone can be kept
+/// to inspect what a `Database` saved.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage(pub Arc<Mutex<HashMap<RecordKey, PuzzleStats>>>);
+
+impl StorageBackend for MemoryStorage {
+    fn load(&self) -> Result<HashMap<RecordKey, PuzzleStats>> {
+        Ok(self.0.lock().unwrap().clone())
+    }
+
+    fn save(&self, records: &[&PuzzleStats]) -> Result<()> {
+        *self.0.lock().unwrap() = records
+            .iter()
+            .map(|record| (record.key(), (*record).clone()))
+            .collect();
+        Ok(())
+    }
+}
+
+/// Storage for the database at the given path: SQLite if the path ends in `.sqlite`, `.sqlite3`,
+/// or `.db`, and CSV otherwise. The duplicate policy only applies to CSV, since SQLite can't store
+/// duplicates.
+#[must_use]
+pub fn storage_for_path(path: &Path, duplicates: DuplicatePolicy) -> Box<dyn StorageBackend> {
+    if is_sqlite(path) {
+        Box::new(SqliteStorage(path.to_path_buf()))
+    } else {
+        Box::new(CsvStorage::new(path).with_duplicate_policy(duplicates))
+    }
+}
 
 #[derive(Debug)]
 pub struct Database {
-    records: HashMap<NaiveDate, PuzzleStats>,
-    filepath: PathBuf,
+    records: HashMap<RecordKey, PuzzleStats>,
+    storage: Box<dyn StorageBackend>,
+    /// Path of the database file, if the records are stored in one
+    path: Option<PathBuf>,
+    /// Number of backups of the database file to keep
+    backups: usize,
+    /// Whether the database file has been backed up since it was opened
+    backed_up: AtomicBool,
+    /// Whether the records changed since they were loaded or last written
+    dirty: AtomicBool,
+    /// Path the personal bests are persisted to, if any
+    bests_path: Option<PathBuf>,
+    /// Per-weekday personal bests, persisted next to the database so they don't need to be
+    /// recomputed from the full history on every run
+    bests: Leaderboard,
 }
 
 impl Database {
     /// Create a new database at the given path
     #[must_use]
     pub fn new<T: Into<PathBuf>>(out_path: T) -> Self {
+        let path = out_path.into();
         Self {
             records: HashMap::new(),
-            filepath: out_path.into(),
+            storage: storage_for_path(&path, DuplicatePolicy::default()),
+            bests_path: Some(bests_path(&path)),
+            path: Some(path),
+            backups: 0,
+            backed_up: AtomicBool::new(false),
+            // Written even if no records are added, so that the file exists afterwards
+            dirty: AtomicBool::new(true),
+            bests: Leaderboard::default(),
         }
     }
 
-    /// Load a database from file
+    /// Load a database from file, keeping the last of any duplicate records
     pub fn from_file<T: AsRef<Path>>(path: T) -> Result<Self> {
+        Self::from_file_with_policy(path, DuplicatePolicy::default())
+    }
+
+    /// Load a database from file, resolving any duplicate records with the given policy. The
+    /// personal bests saved next to it are rebuilt from the records if they don't match them.
+    pub fn from_file_with_policy<T: AsRef<Path>>(
+        path: T,
+        duplicates: DuplicatePolicy,
+    ) -> Result<Self> {
         let path = path.as_ref();
-        let file =
-            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
-        let records = deserialize_records(file)?;
+        let records = load_records_with_policy(path, duplicates)?;
+        let bests_path = bests_path(path);
+        let loaded = if bests_path.exists() {
+            Some(Leaderboard::load(&bests_path)?)
+        } else {
+            None
+        };
+        let bests = match loaded {
+            Some(bests) if bests.matches(&records) => bests,
+            loaded => {
+                if loaded.is_some() {
+                    warn!(
+                        "Personal bests in {} don't match the database; rebuilding them",
+                        bests_path.display()
+                    );
+                }
+                Leaderboard::from_records(records.values())
+            }
+        };
+        Ok(Self {
+            records,
+            storage: storage_for_path(path, duplicates),
+            path: Some(path.to_path_buf()),
+            backups: 0,
+            backed_up: AtomicBool::new(false),
+            dirty: AtomicBool::new(false),
+            bests_path: Some(bests_path),
+            bests,
+        })
+    }
+
+    /// Load a database from the given storage. Personal bests are computed from the records rather
+    /// than persisted.
+    pub fn from_storage(storage: Box<dyn StorageBackend>) -> Result<Self> {
+        let records = storage.load()?;
+        let bests = Leaderboard::from_records(records.values());
         Ok(Self {
             records,
-            filepath: path.to_path_buf(),
+            storage,
+            path: None,
+            backups: 0,
+            backed_up: AtomicBool::new(false),
+            dirty: AtomicBool::new(false),
+            bests_path: None,
+            bests,
         })
     }
 
+    /// Write the database file with the given delimiter and quoting if it's a CSV file. Only
+    /// affects writing, since the records have already been read.
+    #[must_use]
+    pub fn with_csv_dialect(mut self, dialect: CsvDialect) -> Self {
+        if let Some(path) = self.path.as_ref().filter(|path| !is_sqlite(path)) {
+            self.storage = Box::new(CsvStorage::new(path).with_dialect(dialect));
+        }
+        self
+    }
+
+    /// Keep up to `backups` copies of the database file, rotated as `<path>.bak.1` (the most
+    /// recent) to `<path>.bak.N`. The file is backed up once, before it is first overwritten.
+    #[must_use]
+    pub fn with_backups(mut self, backups: usize) -> Self {
+        self.backups = backups;
+        self
+    }
+
+    /// Copy of every record, in no particular order. Prefer `iter` unless the records need to
+    /// outlive the database.
     #[must_use]
     pub fn records(&self) -> Vec<PuzzleStats> {
-        self.records.values().copied().collect()
+        self.records.values().cloned().collect()
+    }
+
+    /// Every record, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = &PuzzleStats> {
+        self.records.values()
+    }
+
+    /// Records of puzzles published within the given range of dates, of any kind, in no
+    /// particular order
+    pub fn range<R: RangeBounds<NaiveDate>>(&self, dates: R) -> impl Iterator<Item = &PuzzleStats> {
+        self.iter()
+            .filter(move |record| dates.contains(&record.date))
+    }
+
+    /// Records of puzzles published on the given day of the week, of any kind, in no particular
+    /// order
+    pub fn by_weekday(&self, weekday: Weekday) -> impl Iterator<Item = &PuzzleStats> {
+        self.iter()
+            .filter(move |record| record.date.weekday() == weekday)
+    }
+
+    /// Records of solved puzzles, with or without aids, in no particular order
+    pub fn solved(&self) -> impl Iterator<Item = &PuzzleStats> {
+        self.iter().filter(|record| record.is_solved())
+    }
+
+    /// Serialize every record as a JSON array, sorted by date and kind. Missing values are written
+    /// as `null`, so the output can be read back with `from_json` without losing any columns.
+    pub fn to_json(&self) -> Result<String> {
+        let mut sorted = self.records.values().collect::<Vec<&PuzzleStats>>();
+        sorted.sort_unstable_by_key(|s| s.key());
+        Ok(serde_json::to_string_pretty(&sorted)?)
+    }
+
+    /// Create a database at the given path holding the records in the given JSON, either an array
+    /// of records or one record per line. If a puzzle appears more than once, the last record wins.
+    pub fn from_json<T: Into<PathBuf>>(json: &str, out_path: T) -> Result<Self> {
+        let records: Vec<PuzzleStats> = if json.trim_start().starts_with('[') {
+            serde_json::from_str(json).context("Malformed JSON records")?
+        } else {
+            json.lines()
+                .enumerate()
+                .filter(|(_, line)| !line.trim().is_empty())
+                .map(|(i, line)| {
+                    serde_json::from_str(line)
+                        .with_context(|| format!("Malformed record on line {}", i + 1))
+                })
+                .collect::<Result<_>>()?
+        };
+        let mut db = Self::new(out_path);
+        for record in records {
+            db.add(record);
+        }
+        Ok(db)
     }
 
     #[must_use]
-    pub fn get(&self, date: NaiveDate) -> Option<PuzzleStats> {
-        self.records.get(&date).copied()
+    pub fn get(&self, date: NaiveDate, publish_type: PublishType) -> Option<&PuzzleStats> {
+        self.records.get(&(date, publish_type))
     }
 
-    pub fn contains(&self, date: NaiveDate) -> bool {
-        self.records.contains_key(&date)
+    pub fn contains(&self, date: NaiveDate, publish_type: PublishType) -> bool {
+        self.records.contains_key(&(date, publish_type))
     }
 
-    /// Add record to database. If a record already exists for the given date, it will be
-    /// overwritten
-    pub fn add(&mut self, puzzle: PuzzleStats) {
-        self.records.insert(puzzle.date, puzzle);
+    /// Per-weekday personal bests
+    #[must_use]
+    pub fn personal_bests(&self) -> &Leaderboard {
+        &self.bests
     }
 
-    /// Write database to file
-    pub fn flush(&self) -> Result<()> {
-        let mut writer = csv::Writer::from_path(&self.filepath)?;
-        let mut sorted = self.records.values().copied().collect::<Vec<PuzzleStats>>();
-        sorted.sort_unstable_by_key(|s| s.date);
+    /// Add record to database. If a record already exists for the same puzzle, the new record
+    /// replaces it, except that anything the new record is missing is kept from the existing one.
+    /// In particular, an unsolved record never replaces a solve. See `PuzzleStats::fill_from`.
+    ///
+    /// Returns the new personal best if the record set one
+    pub fn add(&mut self, mut puzzle: PuzzleStats) -> Option<PersonalBest> {
+        if let Some(existing) = self.records.get(&puzzle.key()) {
+            if puzzle.fill_from(existing) {
+                warn!(
+                    "{} was reported unsolved after being solved in {}; keeping the existing solve",
+                    crate::diff::format_key(puzzle.key()),
+                    crate::analytics::format_secs(existing.solve_time_secs.map(f64::from))
+                );
+            }
+        }
+        if self.records.get(&puzzle.key()) == Some(&puzzle) {
+            return None;
+        }
+        self.dirty.store(true, Ordering::Relaxed);
+        let date = puzzle.date;
+        // Personal bests only cover the daily crossword
+        let replaced_best = puzzle.publish_type == PublishType::Daily && self.bests.is_best(date);
+        if replaced_best {
+            self.records.insert(puzzle.key(), puzzle);
+            // The record holding the personal best changed, so the previous runner-up may be the
+            // best now
+            let weekday = date.weekday();
+            self.bests.rebuild_weekday(weekday, self.records.values());
+            return self.bests.get(weekday).filter(|best| best.date == date);
+        }
+        let best = self.bests.update(&puzzle);
+        self.records.insert(puzzle.key(), puzzle);
+        best
+    }
 
-        // The csv crate's Writer will add a header row using struct fieldnames by default
-        for record in sorted {
-            writer.serialize(record)?;
+    /// Merge records from another database into this one, using the given rule to choose between
+    /// two solves of the same puzzle
+    pub fn merge<I: IntoIterator<Item = PuzzleStats>>(
+        &mut self,
+        other: I,
+        precedence: Precedence,
+    ) -> MergeSummary {
+        let mut summary = MergeSummary::default();
+        for record in other {
+            let merged = match self.get(record.date, record.publish_type) {
+                None => {
+                    summary.added += 1;
+                    record
+                }
+                Some(existing) => {
+                    let conflict = Conflict::between(existing, &record);
+                    let chosen = precedence.choose(existing.clone(), record);
+                    let replaced = chosen != *existing;
+                    if let Some(mut conflict) = conflict {
+                        conflict.replaced = replaced;
+                        summary.conflicts.push(conflict);
+                    }
+                    if !replaced {
+                        summary.kept += 1;
+                        continue;
+                    }
+                    summary.replaced += 1;
+                    chosen
+                }
+            };
+            self.add(merged);
         }
+        summary
+    }
+
+    /// Recompute `solved_on_pub_day` for every record using the given home time zone
+    pub fn derive_solved_on_pub_day(&mut self, home: HomeTimezone) {
+        for record in self.records.values_mut() {
+            let before = record.solved_on_pub_day;
+            record.derive_solved_on_pub_day(home);
+            if record.solved_on_pub_day != before {
+                self.dirty.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Recompute `percent_rank` for every record from the solves in the database
+    pub fn derive_percent_ranks(&mut self) {
+        let ranks = crate::analytics::percent_ranks(self.records.values());
+        for record in self.records.values_mut() {
+            let rank = ranks.get(&record.key()).copied();
+            if record.percent_rank != rank {
+                record.percent_rank = rank;
+                self.dirty.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Whether the records changed since they were loaded or last written, so that the next flush
+    /// will write them
+    #[must_use]
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.load(Ordering::Relaxed)
+    }
+
+    /// Write database to its storage, unless nothing changed since it was loaded or last written
+    pub fn flush(&self) -> Result<()> {
+        if !self.is_dirty() {
+            return Ok(());
+        }
+        if let Some(path) = &self.path {
+            // Later flushes in the same session would otherwise replace the backup of the file as
+            // it was before the session with a partial one
+            if self.backups > 0 && !self.backed_up.load(Ordering::Relaxed) {
+                backup::rotate(path, self.backups)?;
+                self.backed_up.store(true, Ordering::Relaxed);
+            }
+        }
+        if let Some(path) = &self.bests_path {
+            self.bests.save(path)?;
+        }
+        let mut sorted = self.records.values().collect::<Vec<&PuzzleStats>>();
+        sorted.sort_unstable_by_key(|s| s.key());
+        self.storage.save(&sorted)?;
+        self.dirty.store(false, Ordering::Relaxed);
         Ok(())
     }
+
+    /// Flush the database and close it, returning any error writing it. Changes to a database
+    /// that's dropped without being closed or flushed are lost.
+    pub fn close(self) -> Result<()> {
+        let result = self.flush();
+        // Already reported, so dropping it shouldn't warn about it
+        self.dirty.store(false, Ordering::Relaxed);
+        result
+    }
 }
 
 impl Drop for Database {
     fn drop(&mut self) {
-        if let Err(e) = self.flush() {
-            error!("Error flushing database: {}", e);
+        if self.is_dirty() {
+            warn!("Database dropped with unsaved changes. Use Database::close to save them.");
         }
     }
 }
 
-fn deserialize_records<R: Read>(reader: R) -> Result<HashMap<NaiveDate, PuzzleStats>> {
-    let reader = csv::Reader::from_reader(reader);
-    let mut records = HashMap::new();
-    for record in reader.into_deserialize() {
-        let record: PuzzleStats = record.with_context(|| "Malformed record")?;
-        if records.insert(record.date, record).is_some() {
-            warn!("Duplicate record in loaded database for {}", record.date);
+/// Write records as CSV in the canonical format of a CSV database, so that the same records always
+/// produce the same file and diffs between versions of it only show changed records: a header row
+/// even if there are no records, one row per record sorted by date and then kind of puzzle, empty
+/// cells for missing values, quotes only where needed, and a newline after every row
+pub fn write_csv<W: Write>(writer: W, records: &[&PuzzleStats]) -> Result<()> {
+    write_csv_as(writer, records, CsvDialect::default())
+}
+
+/// Write records in the canonical format of a CSV database, but with the given delimiter and
+/// quoting
+pub fn write_csv_as<W: Write>(
+    writer: W,
+    records: &[&PuzzleStats],
+    dialect: CsvDialect,
+) -> Result<()> {
+    let mut sorted = records.to_vec();
+    sorted.sort_by_key(|record| record.key());
+    let mut writer = dialect
+        .writer()
+        .has_headers(false)
+        .terminator(csv::Terminator::Any(b'\n'))
+        .from_writer(writer);
+    writer.write_record(Schema::current().columns.iter().map(|column| column.name))?;
+    for record in sorted {
+        writer.serialize(record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read the records stored in the database file at the given path without opening it as a
+/// `Database`, e.g. when there's no need to track changes to them. The last of any duplicate
+/// records is kept.
+pub fn load_records<T: AsRef<Path>>(path: T) -> Result<HashMap<RecordKey, PuzzleStats>> {
+    load_records_with_policy(path, DuplicatePolicy::default())
+}
+
+/// Like `load_records`, resolving any duplicate records with the given policy
+pub fn load_records_with_policy<T: AsRef<Path>>(
+    path: T,
+    duplicates: DuplicatePolicy,
+) -> Result<HashMap<RecordKey, PuzzleStats>> {
+    storage_for_path(path.as_ref(), duplicates).load()
+}
+
+/// Read the records stored in the database file at the given path one at a time, for consumers
+/// that only need a single pass over them, e.g. to count or filter records, without holding the
+/// whole database in memory. Unlike `load_records`, duplicate records aren't resolved. SQLite
+/// databases are read in full before the first record is yielded.
+pub fn stream_records<T: AsRef<Path>>(
+    path: T,
+) -> Result<Box<dyn Iterator<Item = Result<PuzzleStats>>>> {
+    let path = path.as_ref();
+    if is_sqlite(path) {
+        let records = load_records(path)?;
+        return Ok(Box::new(records.into_values().map(Ok)));
+    }
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    Ok(Box::new(CsvRecords::new(file)?))
+}
+
+/// Whether the database at the given path is stored in SQLite rather than CSV, going by its
+/// extension
+#[must_use]
+pub fn is_sqlite(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| matches!(ext, "sqlite" | "sqlite3" | "db"))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn sqlite_unsupported(path: &Path) -> anyhow::Error {
+    anyhow::anyhow!(
+        "{} is an SQLite database, but this build doesn't include the sqlite feature",
+        path.display()
+    )
+}
+
+/// Path of the personal bests table stored alongside the database at the given path
+fn bests_path(db_path: &Path) -> PathBuf {
+    db_path.with_extension("bests.csv")
+}
+
+/// Schema version in which a column was added. Only columns added after the first version are
+/// listed.
+fn column_added_in(column: &str) -> u32 {
+    match column {
+        "fetch_failed" => 2,
+        "source" => 4,
+        _ => 1,
+    }
+}
+
+/// Upgrade of CSV rows written with an older schema to the current one. Columns are matched by
+/// name, and columns missing from the file are filled with defaults.
+#[derive(Debug)]
+struct SchemaUpgrade {
+    /// Current columns, in order
+    columns: csv::StringRecord,
+    /// Position of each current column in the file, if present
+    positions: Vec<Option<usize>>,
+}
+
+impl SchemaUpgrade {
+    fn new(file_columns: &csv::StringRecord) -> Self {
+        let columns: csv::StringRecord = Schema::current()
+            .columns
+            .iter()
+            .map(|column| column.name)
+            .collect();
+        let positions: Vec<Option<usize>> = columns
+            .iter()
+            .map(|column| file_columns.iter().position(|c| c == column))
+            .collect();
+        let upgrade = Self { columns, positions };
+        if !file_columns.is_empty() {
+            upgrade.log(file_columns);
         }
+        upgrade
+    }
+
+    /// Version of the schema the file was written with, or 0 if it predates versioned schemas.
+    /// Versions that didn't add a column can't be told apart from the one before them.
+    fn version(&self) -> u32 {
+        let present = |version| {
+            self.columns
+                .iter()
+                .zip(&self.positions)
+                .all(|(column, position)| column_added_in(column) > version || position.is_some())
+        };
+        (1..=SCHEMA_VERSION)
+            .rev()
+            .find(|&version| present(version))
+            .unwrap_or(0)
+    }
+
+    fn log(&self, file_columns: &csv::StringRecord) {
+        for column in file_columns {
+            if !self.columns.iter().any(|c| c == column) {
+                warn!("Ignoring unknown database column {}", column);
+            }
+        }
+        let missing: Vec<&str> = self
+            .columns
+            .iter()
+            .zip(&self.positions)
+            .filter(|(_, position)| position.is_none())
+            .map(|(column, _)| column)
+            .collect();
+        if !missing.is_empty() {
+            info!(
+                "Upgrading database from schema version {} to {}, adding columns {}",
+                self.version(),
+                SCHEMA_VERSION,
+                missing.join(", ")
+            );
+        }
+    }
+
+    /// Rearrange a row into the current columns. Cells missing from the row, e.g. because it
+    /// was written by a version with fewer columns, are filled with defaults too.
+    fn apply(&self, row: &csv::StringRecord) -> csv::StringRecord {
+        let cell = |column| {
+            let i = self.columns.iter().position(|c| c == column)?;
+            row.get(self.positions[i]?)
+        };
+        self.columns
+            .iter()
+            .map(|column| match cell(column).unwrap_or_default() {
+                "" if column == "weekday" => cell("date")
+                    .and_then(|date| date.parse::<NaiveDate>().ok())
+                    .map(|date| date.weekday().to_string())
+                    .unwrap_or_default(),
+                "" if column == "publish_type" => PublishType::default().to_string(),
+                value => value.to_string(),
+            })
+            .collect()
+    }
+}
+
+/// Records read one row at a time from a CSV database, upgraded to the current schema. Duplicate
+/// records are yielded as they appear.
+pub struct CsvRecords<R> {
+    records: csv::StringRecordsIntoIter<BufReader<R>>,
+    upgrade: SchemaUpgrade,
+}
+
+impl<R: Read> CsvRecords<R> {
+    /// Start reading records from a CSV database. Only the header is read up front.
+    pub fn new(reader: R) -> Result<Self> {
+        let mut reader = csv_reader(reader)?;
+        let upgrade = SchemaUpgrade::new(reader.headers()?);
+        Ok(Self {
+            records: reader.into_records(),
+            upgrade,
+        })
+    }
+}
+
+impl<R: Read> Iterator for CsvRecords<R> {
+    type Item = Result<PuzzleStats>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = match self.records.next()? {
+            Ok(row) => row,
+            Err(e) => return Some(Err(anyhow::Error::new(e).context("Malformed record"))),
+        };
+        let line = row.position().map_or(0, csv::Position::line);
+        Some(
+            self.upgrade
+                .apply(&row)
+                .deserialize(Some(&self.upgrade.columns))
+                .with_context(|| format!("Malformed record on line {line}")),
+        )
+    }
+}
+
+fn deserialize_records<R: Read>(
+    reader: R,
+    duplicates: DuplicatePolicy,
+) -> Result<HashMap<RecordKey, PuzzleStats>> {
+    let mut records = HashMap::new();
+    for record in CsvRecords::new(reader)? {
+        let record = record?;
+        let (date, publish_type) = record.key();
+        let record = match records.remove(&record.key()) {
+            Some(earlier) => {
+                let chosen = duplicates.choose(earlier, record)?;
+                warn!(
+                    "Duplicate {} record in loaded database for {}, resolved with {:?}",
+                    publish_type, date, duplicates
+                );
+                chosen
+            }
+            None => record,
+        };
+        records.insert(record.key(), record);
     }
 
     Ok(records)
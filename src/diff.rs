@@ -0,0 +1,132 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::api_client::PublishType;
+use crate::{PuzzleStats, RecordKey};
+use chrono::naive::NaiveDate;
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+/// A single column that differs between two versions of a record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: String,
+    pub new: String,
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.field, self.old, self.new)
+    }
+}
+
+/// Difference between two databases for a single puzzle
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Added(PuzzleStats),
+    Removed(PuzzleStats),
+    Changed {
+        key: RecordKey,
+        fields: Vec<FieldChange>,
+    },
+}
+
+impl Change {
+    #[must_use]
+    pub fn key(&self) -> RecordKey {
+        match self {
+            Self::Added(record) | Self::Removed(record) => record.key(),
+            Self::Changed { key, .. } => *key,
+        }
+    }
+
+    #[must_use]
+    pub fn date(&self) -> NaiveDate {
+        self.key().0
+    }
+}
+
+/// Label a record by its date, and also its publish type unless it's a daily crossword
+pub(crate) fn format_key((date, publish_type): RecordKey) -> String {
+    match publish_type {
+        PublishType::Daily => date.to_string(),
+        other => format!("{date} ({other})"),
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added(record) => write!(f, "+ {}", format_key(record.key())),
+            Self::Removed(record) => write!(f, "- {}", format_key(record.key())),
+            Self::Changed { key, fields } => {
+                write!(f, "~ {}", format_key(*key))?;
+                for field in fields {
+                    write!(f, "\n    {field}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::Null => "empty".to_string(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// List the columns whose values differ between two versions of a record
+#[must_use]
+pub fn field_changes(old: &PuzzleStats, new: &PuzzleStats) -> Vec<FieldChange> {
+    let (Ok(Value::Object(old)), Ok(Value::Object(new))) =
+        (serde_json::to_value(old), serde_json::to_value(new))
+    else {
+        unreachable!("PuzzleStats always serializes to an object");
+    };
+    old.iter()
+        .filter_map(|(field, old_value)| {
+            let new_value = new.get(field).unwrap_or(&Value::Null);
+            (old_value != new_value).then(|| FieldChange {
+                field: field.clone(),
+                old: format_value(old_value),
+                new: format_value(new_value),
+            })
+        })
+        .collect()
+}
+
+/// Compare two sets of records. Changes are ordered by date.
+#[must_use]
+pub fn diff(
+    old: &HashMap<RecordKey, PuzzleStats>,
+    new: &HashMap<RecordKey, PuzzleStats>,
+) -> Vec<Change> {
+    let keys: BTreeSet<&RecordKey> = old.keys().chain(new.keys()).collect();
+    keys.into_iter()
+        .filter_map(|key| match (old.get(key), new.get(key)) {
+            (None, Some(record)) => Some(Change::Added(record.clone())),
+            (Some(record), None) => Some(Change::Removed(record.clone())),
+            (Some(old_record), Some(new_record)) => {
+                let fields = field_changes(old_record, new_record);
+                (!fields.is_empty()).then_some(Change::Changed { key: *key, fields })
+            }
+            (None, None) => None,
+        })
+        .collect()
+}
@@ -0,0 +1,159 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Weekly summary of daily crossword solves, sent through the configured notifiers
+
+use crate::analytics::format_secs;
+use crate::api_client::PublishType;
+use crate::database::Database;
+use chrono::{naive::NaiveDate, Datelike, Duration, Weekday};
+use serde::Serialize;
+use std::fmt;
+
+/// Number of earlier weeks each day's solve time is compared against
+const AVERAGE_WEEKS: i64 = 4;
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// The daily crossword of one day of the week
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DigestDay {
+    pub date: NaiveDate,
+    pub solve_time_secs: Option<u32>,
+    pub cheated: bool,
+    /// Mean unassisted solve time of the same weekday over the previous four weeks
+    pub average_secs: Option<f64>,
+}
+
+/// Solves of the daily crossword over a week, Monday to Sunday
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WeeklyDigest {
+    pub week_start: NaiveDate,
+    pub days: Vec<DigestDay>,
+    pub current_streak: u32,
+}
+
+/// Monday of the week before the one containing the given date, i.e. the start of the most recent
+/// complete week
+#[must_use]
+pub fn previous_week(today: NaiveDate) -> NaiveDate {
+    let monday = today - Duration::days(today.weekday().num_days_from_monday().into());
+    monday - Duration::weeks(1)
+}
+
+impl WeeklyDigest {
+    /// Summarize the week starting on the given Monday, with the streak as of `today`
+    #[must_use]
+    pub fn new(database: &Database, week_start: NaiveDate, today: NaiveDate) -> Self {
+        let clean_time = |date| {
+            database
+                .get(date, PublishType::Daily)
+                .filter(|r| !r.cheated.unwrap_or(false))
+                .and_then(|r| r.solve_time_secs)
+        };
+        let days = (0..7)
+            .map(|offset| {
+                let date = week_start + Duration::days(offset);
+                let record = database.get(date, PublishType::Daily);
+                let earlier: Vec<u32> = (1..=AVERAGE_WEEKS)
+                    .filter_map(|weeks| clean_time(date - Duration::weeks(weeks)))
+                    .collect();
+                #[allow(clippy::cast_precision_loss)]
+                let average_secs = (!earlier.is_empty()).then(|| {
+                    earlier.iter().map(|&secs| f64::from(secs)).sum::<f64>() / earlier.len() as f64
+                });
+                DigestDay {
+                    date,
+                    solve_time_secs: record.and_then(|r| r.solve_time_secs),
+                    cheated: record.is_some_and(|r| r.cheated.unwrap_or(false)),
+                    average_secs,
+                }
+            })
+            .collect();
+        Self {
+            week_start,
+            days,
+            current_streak: crate::current_streak(database, today),
+        }
+    }
+
+    /// One character per day showing its solve time relative to the rest of the week, with a dot
+    /// for unsolved days. Assisted solves are shown as dots too, since they're left out of the
+    /// averages the days are compared to.
+    #[must_use]
+    pub fn sparkline(&self) -> String {
+        let times: Vec<Option<u32>> = self
+            .days
+            .iter()
+            .map(|day| day.solve_time_secs.filter(|_| !day.cheated))
+            .collect();
+        sparkline(&times)
+    }
+}
+
+/// Render values as a line of block characters scaled between the smallest and largest value
+#[must_use]
+pub fn sparkline(values: &[Option<u32>]) -> String {
+    let present = values.iter().flatten();
+    let (Some(&min), Some(&max)) = (present.clone().min(), present.max()) else {
+        return "·".repeat(values.len());
+    };
+    let top = SPARK_LEVELS.len() - 1;
+    values
+        .iter()
+        .map(|value| match value {
+            None => '·',
+            Some(_) if max == min => SPARK_LEVELS[top / 2],
+            Some(value) => {
+                let level = u64::from(value - min) * top as u64 / u64::from(max - min);
+                SPARK_LEVELS[usize::try_from(level).unwrap_or(top)]
+            }
+        })
+        .collect()
+}
+
+impl fmt::Display for DigestDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let weekday: Weekday = self.date.weekday();
+        write!(f, "{weekday} ")?;
+        let Some(secs) = self.solve_time_secs else {
+            return write!(f, "-");
+        };
+        write!(f, "{}", format_secs(Some(f64::from(secs))))?;
+        if self.cheated {
+            write!(f, " (assisted)")?;
+        } else if let Some(average) = self.average_secs {
+            let delta = f64::from(secs) - average;
+            let sign = if delta < 0.0 { "-" } else { "+" };
+            write!(
+                f,
+                ", {}{} vs 4-week average {}",
+                sign,
+                format_secs(Some(delta.abs())),
+                format_secs(Some(average))
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for WeeklyDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "📅 Crossword week of {}", self.week_start)?;
+        for day in &self.days {
+            writeln!(f, "{day}")?;
+        }
+        writeln!(f, "{}", self.sparkline())?;
+        write!(f, "Current streak: {} days", self.current_streak)
+    }
+}
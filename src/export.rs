@@ -0,0 +1,214 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::database::CsvDialect;
+use crate::PuzzleStats;
+use anyhow::Result;
+use chrono::{naive::NaiveDate, Datelike};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// Period to aggregate records over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Rollup {
+    /// ISO 8601 week, starting on Monday
+    Week,
+    /// Calendar month
+    Month,
+}
+
+impl Rollup {
+    /// Label and first day of the period containing the given date
+    fn period(self, date: NaiveDate) -> (String, NaiveDate) {
+        match self {
+            Self::Week => {
+                let week = date.iso_week();
+                let start =
+                    NaiveDate::from_isoywd_opt(week.year(), week.week(), chrono::Weekday::Mon)
+                        .expect("ISO week of a valid date is valid");
+                (format!("{}-W{:02}", week.year(), week.week()), start)
+            }
+            Self::Month => {
+                let start = date
+                    .with_day(1)
+                    .expect("First of the month is a valid date");
+                (start.format("%Y-%m").to_string(), start)
+            }
+        }
+    }
+}
+
+/// File format of an export
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    #[default]
+    Csv,
+    /// A single JSON array, with missing values as `null`
+    Json,
+    /// One JSON object per line, with missing values as `null`
+    Jsonl,
+    /// Parquet, with the column types of the database schema. Only individual records can be
+    /// exported in this format.
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+/// How unsolved puzzles, and other missing values, appear in an export
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Unsolved {
+    /// Leave missing values as empty cells
+    #[default]
+    Empty,
+    /// Write missing values as `NA`, as expected by R
+    Na,
+    /// Leave out unsolved puzzles, and periods without any solves when rolling up
+    Omit,
+}
+
+/// Aggregate stats for all puzzles published within a period
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RollupRow {
+    pub period: String,
+    pub period_start: NaiveDate,
+    /// Number of puzzles in the database for the period
+    pub puzzles: u32,
+    /// Number of puzzles solved, with or without aids
+    pub solved: u32,
+    /// Number of puzzles solved using check or reveal
+    pub cheated: u32,
+    /// Mean solve time of unassisted solves
+    pub mean_solve_secs: Option<f64>,
+    /// Fastest unassisted solve time
+    pub best_solve_secs: Option<u32>,
+    /// Puzzle date of the fastest unassisted solve
+    pub best_date: Option<NaiveDate>,
+}
+
+impl RollupRow {
+    fn new(period: String, period_start: NaiveDate) -> Self {
+        Self {
+            period,
+            period_start,
+            puzzles: 0,
+            solved: 0,
+            cheated: 0,
+            mean_solve_secs: None,
+            best_solve_secs: None,
+            best_date: None,
+        }
+    }
+}
+
+/// Aggregate the given records into one row per period, ordered chronologically
+pub fn rollup<'a, I: IntoIterator<Item = &'a PuzzleStats>>(
+    records: I,
+    rollup: Rollup,
+) -> Vec<RollupRow> {
+    let mut rows: BTreeMap<NaiveDate, (RollupRow, u64, u32)> = BTreeMap::new();
+    for record in records {
+        let (label, start) = rollup.period(record.date);
+        let (row, clean_secs, clean_count) = rows
+            .entry(start)
+            .or_insert_with(|| (RollupRow::new(label, start), 0, 0));
+        row.puzzles += 1;
+        if !record.is_solved() {
+            continue;
+        }
+        row.solved += 1;
+        if record.cheated.unwrap_or(false) {
+            row.cheated += 1;
+            continue;
+        }
+        if let Some(secs) = record.solve_time_secs {
+            *clean_secs += u64::from(secs);
+            *clean_count += 1;
+            if row.best_solve_secs.is_none_or(|best| secs < best) {
+                row.best_solve_secs = Some(secs);
+                row.best_date = Some(record.date);
+            }
+        }
+    }
+
+    rows.into_values()
+        .map(|(mut row, clean_secs, clean_count)| {
+            #[allow(clippy::cast_precision_loss)]
+            if clean_count > 0 {
+                let mean = clean_secs as f64 / f64::from(clean_count);
+                row.mean_solve_secs = Some((mean * 10.0).round() / 10.0);
+            }
+            row
+        })
+        .collect()
+}
+
+/// Write rows as CSV with a header row, separated and quoted as given
+pub fn write_csv<W: Write, T: Serialize>(writer: W, rows: &[T], dialect: CsvDialect) -> Result<()> {
+    let mut writer = dialect.writer().from_writer(writer);
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write rows as a JSON array
+pub fn write_json<W: Write, T: Serialize>(mut writer: W, rows: &[T]) -> Result<()> {
+    serde_json::to_writer_pretty(&mut writer, rows)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+/// Write rows as JSON, one object per line
+pub fn write_jsonl<W: Write, T: Serialize>(mut writer: W, rows: &[T]) -> Result<()> {
+    for row in rows {
+        serde_json::to_writer(&mut writer, row)?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Write rows as CSV with a header row, separated and quoted as given, replacing empty cells with
+/// the given placeholder
+pub fn write_csv_filled<W: Write, T: Serialize>(
+    writer: W,
+    rows: &[T],
+    placeholder: &str,
+    dialect: CsvDialect,
+) -> Result<()> {
+    // Let csv lay out the fields first, so that the header and field order match `write_csv`
+    let mut buffer = Vec::new();
+    write_csv(&mut buffer, rows, CsvDialect::default())?;
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(buffer.as_slice());
+
+    let mut writer = dialect.writer().from_writer(writer);
+    for (i, record) in reader.records().enumerate() {
+        let record = record?;
+        if i == 0 {
+            writer.write_record(&record)?;
+        } else {
+            writer.write_record(record.iter().map(|field| {
+                if field.is_empty() {
+                    placeholder
+                } else {
+                    field
+                }
+            }))?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,186 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Archive-completion goals, e.g. solving every Monday crossword since 2018, and progress toward
+//! them
+//!
+//! Goals are stored as JSON alongside the database and only cover daily crosswords.
+
+use crate::api_client::PublishType;
+use crate::PuzzleStats;
+use anyhow::{Context, Result};
+use chrono::{naive::NaiveDate, Datelike, Weekday};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Number of remaining dates listed by the default `Display` of `GoalProgress`. The alternate form
+/// lists all of them.
+const REMAINING_SHOWN: usize = 10;
+
+/// A set of daily crosswords to solve
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Goal {
+    pub name: String,
+    /// Days of the week to solve. Every day if empty.
+    #[serde(default)]
+    pub weekdays: Vec<Weekday>,
+    pub start: NaiveDate,
+    /// Last date to solve. Open-ended goals grow as new puzzles are published.
+    #[serde(default)]
+    pub end: Option<NaiveDate>,
+    /// Whether only solves without check or reveal count
+    #[serde(default)]
+    pub unassisted: bool,
+}
+
+/// Path of the goals stored alongside the database at the given path
+#[must_use]
+pub fn goals_path(db_path: &Path) -> PathBuf {
+    db_path.with_extension("goals.json")
+}
+
+/// Read the goals stored at the given path, or none if the file doesn't exist
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Goal>> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let json = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&json).with_context(|| format!("Malformed goals in {}", path.display()))
+}
+
+/// Write goals to the given path, replacing the ones stored there
+pub fn save<P: AsRef<Path>>(path: P, goals: &[Goal]) -> Result<()> {
+    let path = path.as_ref();
+    std::fs::write(path, serde_json::to_string_pretty(goals)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+impl Goal {
+    /// Dates of the puzzles the goal covers, up to `today` for open-ended goals
+    pub fn dates(&self, today: NaiveDate) -> impl Iterator<Item = NaiveDate> + '_ {
+        let end = self.end.map_or(today, |end| end.min(today));
+        self.start
+            .iter_days()
+            .take_while(move |date| *date <= end)
+            .filter(|date| self.weekdays.is_empty() || self.weekdays.contains(&date.weekday()))
+    }
+
+    fn counts(&self, record: &PuzzleStats) -> bool {
+        record.publish_type == PublishType::Daily
+            && record.is_solved()
+            && !(self.unassisted && record.cheated.unwrap_or(false))
+    }
+
+    /// Progress toward the goal as of `today`
+    #[must_use]
+    pub fn progress<'a, I: IntoIterator<Item = &'a PuzzleStats>>(
+        &self,
+        records: I,
+        today: NaiveDate,
+    ) -> GoalProgress {
+        let solved: HashSet<NaiveDate> = records
+            .into_iter()
+            .filter(|r| self.counts(r))
+            .map(|r| r.date)
+            .collect();
+        let (done, remaining): (Vec<NaiveDate>, Vec<NaiveDate>) =
+            self.dates(today).partition(|date| solved.contains(date));
+        GoalProgress {
+            goal: self.clone(),
+            solved: done.len(),
+            remaining,
+        }
+    }
+}
+
+impl fmt::Display for Goal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: every ", self.name)?;
+        if self.weekdays.is_empty() {
+            write!(f, "day")?;
+        } else {
+            let days: Vec<String> = self.weekdays.iter().map(ToString::to_string).collect();
+            write!(f, "{}", days.join("/"))?;
+        }
+        write!(f, " from {}", self.start)?;
+        if let Some(end) = self.end {
+            write!(f, " to {end}")?;
+        }
+        if self.unassisted {
+            write!(f, " without check or reveal")?;
+        }
+        Ok(())
+    }
+}
+
+/// Progress toward a goal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoalProgress {
+    pub goal: Goal,
+    /// Number of puzzles of the goal that are solved
+    pub solved: usize,
+    /// Dates of the puzzles of the goal that are left to solve, in order
+    pub remaining: Vec<NaiveDate>,
+}
+
+impl GoalProgress {
+    /// Number of puzzles the goal covers so far
+    #[must_use]
+    pub fn total(&self) -> usize {
+        self.solved + self.remaining.len()
+    }
+
+    #[must_use]
+    pub fn percent(&self) -> f64 {
+        if self.total() == 0 {
+            return 100.0;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let percent = self.solved as f64 * 100.0 / self.total() as f64;
+        percent
+    }
+}
+
+impl fmt::Display for GoalProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}/{} solved ({:.1}%)",
+            self.goal,
+            self.solved,
+            self.total(),
+            self.percent()
+        )?;
+        if self.remaining.is_empty() {
+            return write!(f, ", complete");
+        }
+        let shown = if f.alternate() {
+            self.remaining.len()
+        } else {
+            REMAINING_SHOWN
+        };
+        write!(f, "\n  Remaining:")?;
+        for date in self.remaining.iter().take(shown) {
+            write!(f, " {date}")?;
+        }
+        if self.remaining.len() > shown {
+            write!(f, " and {} more", self.remaining.len() - shown)?;
+        }
+        Ok(())
+    }
+}
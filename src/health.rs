@@ -0,0 +1,186 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! At-a-glance summary of the state of a database
+
+use crate::api_client::PublishType;
+use crate::run_status::RunStatus;
+use crate::PuzzleStats;
+use chrono::{naive::NaiveDate, DateTime, Datelike, SecondsFormat, Utc, Weekday};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Record counts for a single day of the week
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WeekdayCount {
+    pub records: usize,
+    pub solved: usize,
+}
+
+/// Summary of the state of a database
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Health {
+    /// Number of records of any publish type
+    pub records: usize,
+    /// Earliest and latest dates of the daily crosswords in the database
+    pub coverage: Option<(NaiveDate, NaiveDate)>,
+    /// Number of complete daily crossword records within `coverage`
+    pub complete: usize,
+    /// Number of days within `coverage`
+    pub total_days: usize,
+    /// Counts of daily crossword records, keyed by days from Monday
+    pub by_weekday: BTreeMap<u32, WeekdayCount>,
+    /// When the database was last written
+    pub last_updated: Option<DateTime<Utc>>,
+    /// Number of records whose stats couldn't be fetched in the latest attempt. These are retried
+    /// by `--only errors`.
+    pub pending_retries: usize,
+    /// Number of records of puzzles that were started but aren't known to be solved. These are
+    /// refreshed by `--only in-progress`.
+    pub in_progress: usize,
+    /// Status of the latest run, if a status file was found
+    pub run_status: Option<RunStatus>,
+}
+
+impl Health {
+    /// Summarize the given records of a database, which was last written at the given time
+    #[must_use]
+    pub fn new<'a, I: IntoIterator<Item = &'a PuzzleStats>>(
+        records: I,
+        last_updated: Option<DateTime<Utc>>,
+    ) -> Self {
+        let mut health = Self {
+            last_updated,
+            ..Self::default()
+        };
+        for record in records {
+            health.records += 1;
+            if record.fetch_failed.unwrap_or(false) {
+                health.pending_retries += 1;
+            }
+            if record.is_in_progress() {
+                health.in_progress += 1;
+            }
+            if record.publish_type != PublishType::Daily {
+                continue;
+            }
+            let count = health
+                .by_weekday
+                .entry(record.date.weekday().num_days_from_monday())
+                .or_default();
+            count.records += 1;
+            if record.is_solved() {
+                count.solved += 1;
+            }
+            if record.is_complete() {
+                health.complete += 1;
+            }
+            health.coverage = Some(match health.coverage {
+                Some((first, last)) => (first.min(record.date), last.max(record.date)),
+                None => (record.date, record.date),
+            });
+        }
+        if let Some((first, last)) = health.coverage {
+            health.total_days = first.iter_days().take_while(|date| *date <= last).count();
+        }
+        health
+    }
+
+    /// Attach the status of the latest run
+    #[must_use]
+    pub fn with_run_status(mut self, run_status: Option<RunStatus>) -> Self {
+        self.run_status = run_status;
+        self
+    }
+
+    /// Percentage of days within `coverage` that have a complete record
+    #[must_use]
+    pub fn percent_complete(&self) -> f64 {
+        if self.total_days == 0 {
+            return 0.0;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let percent = self.complete as f64 * 100.0 / self.total_days as f64;
+        percent
+    }
+}
+
+fn format_unix(secs: i64) -> String {
+    DateTime::from_timestamp(secs, 0).map_or_else(
+        || secs.to_string(),
+        |t| t.to_rfc3339_opts(SecondsFormat::Secs, true),
+    )
+}
+
+impl fmt::Display for Health {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Records: {}", self.records)?;
+        match self.coverage {
+            Some((first, last)) => writeln!(
+                f,
+                "Coverage: {} to {}, {:.1}% complete ({}/{} days)",
+                first,
+                last,
+                self.percent_complete(),
+                self.complete,
+                self.total_days
+            )?,
+            None => writeln!(f, "Coverage: no daily crosswords")?,
+        }
+        match self.last_updated {
+            Some(time) => writeln!(
+                f,
+                "Last updated: {}",
+                time.to_rfc3339_opts(SecondsFormat::Secs, true)
+            )?,
+            None => writeln!(f, "Last updated: unknown")?,
+        }
+        writeln!(f, "Pending retries: {}", self.pending_retries)?;
+        writeln!(f, "In progress: {}", self.in_progress)?;
+        if !self.by_weekday.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "By weekday:")?;
+            for (day, count) in &self.by_weekday {
+                let weekday = Weekday::try_from(u8::try_from(*day).unwrap_or_default())
+                    .unwrap_or(Weekday::Mon);
+                writeln!(
+                    f,
+                    "  {}  {:>5} records {:>5} solved",
+                    weekday, count.records, count.solved
+                )?;
+            }
+        }
+        if let Some(status) = &self.run_status {
+            writeln!(f)?;
+            writeln!(
+                f,
+                "Latest run: {:?} as of {} ({} of {} fetched: {} solved, {} unsolved, {} errors)",
+                status.phase,
+                format_unix(status.updated_unix),
+                status.solved + status.unsolved + status.errors,
+                status.total,
+                status.solved,
+                status.unsolved,
+                status.errors
+            )?;
+            if let Some(error) = &status.last_error {
+                writeln!(f, "  Error: {error}")?;
+            }
+            if let Some(next_run) = status.next_run_unix {
+                writeln!(f, "  Next run: {}", format_unix(next_run))?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,448 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Import of solve times kept elsewhere: another tracker's spreadsheet or data takeout, a database
+//! written by this crate, or crosswords solved in Across Lite
+//!
+//! The format of an import is detected from its path and header unless given:
+//!
+//! * `database` - a CSV or SQLite database written by this crate, including by older versions.
+//!   Detected by its `date`, `weekday`, and `solve_time_secs` columns, or an SQLite file extension.
+//! * `puz` - a .puz file, or a directory of them. The date is read from the file name, as
+//!   YYYY-MM-DD or YYYYMMDD. See the `puz` module for what's read from the file.
+//! * `tracker` - any other CSV file with a header row
+//!
+//! Only a `date` column is required in a tracker CSV. Recognized columns are, case-insensitively:
+//!
+//! * `date` - YYYY-MM-DD or MM/DD/YYYY
+//! * `solve_time_secs`, `solve_time`, or `time` - seconds, or MM:SS or H:MM:SS
+//! * `cheated` or `assisted` - true/false, yes/no, or 1/0
+//! * `puzzle_id`
+//! * `publish_type` - defaults to daily
+//!
+//! Other columns are ignored. Columns with other names can be mapped to these fields with a
+//! `ColumnMapping`.
+//!
+//! Every imported record has its `source` set to the format and file name it was read from.
+
+use crate::api_client::PublishType;
+use crate::{database, puz, PuzzleStats, RecordKey};
+use anyhow::{bail, Context, Result};
+use chrono::naive::NaiveDate;
+use clap::ValueEnum;
+use csv::StringRecord;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+
+/// Format of an import
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// A database written by this crate
+    Database,
+    /// Another tracker's CSV export
+    Tracker,
+    /// An Across Lite .puz file, or a directory of them
+    Puz,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.to_possible_value().expect("No skipped variants");
+        write!(f, "{}", value.get_name())
+    }
+}
+
+/// Field of a tracker CSV that a column can be mapped to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Field {
+    Date,
+    SolveTime,
+    Cheated,
+    PuzzleId,
+    PublishType,
+}
+
+/// Mapping of a column of a tracker CSV to a field, given as `<field>=<column>`, e.g.
+/// `solve-time=Duration`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnMapping {
+    pub field: Field,
+    pub column: String,
+}
+
+impl FromStr for ColumnMapping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (field, column) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected <field>=<column>, got {s:?}"))?;
+        Ok(Self {
+            field: Field::from_str(field.trim(), true)?,
+            column: column.trim().to_string(),
+        })
+    }
+}
+
+/// A row or file that couldn't be imported
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RowIssue {
+    /// Line number in a CSV input, counting the header as line 1
+    pub line: Option<u64>,
+    /// File in a directory input
+    pub file: Option<PathBuf>,
+    pub message: String,
+}
+
+impl RowIssue {
+    fn line(line: u64, message: String) -> Self {
+        Self {
+            line: Some(line),
+            file: None,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for RowIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}: ", file.display())?;
+        }
+        if let Some(line) = self.line {
+            write!(f, "line {line}: ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Records read from an import, along with the rows that were skipped
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Import {
+    pub records: Vec<PuzzleStats>,
+    pub issues: Vec<RowIssue>,
+}
+
+/// Positions of the recognized columns in the header
+#[derive(Debug, Clone, Copy, Default)]
+struct Columns {
+    date: usize,
+    solve_time: Option<usize>,
+    cheated: Option<usize>,
+    puzzle_id: Option<usize>,
+    publish_type: Option<usize>,
+}
+
+impl Columns {
+    fn from_header(header: &StringRecord, map: &[ColumnMapping]) -> Result<Self> {
+        let position = |name: &str| {
+            header
+                .iter()
+                .position(|h| h.trim().eq_ignore_ascii_case(name))
+        };
+        let find = |field: Field, names: &[&str]| match map.iter().find(|m| m.field == field) {
+            Some(mapping) => position(&mapping.column)
+                .map(Some)
+                .with_context(|| format!("Import has no column {:?}", mapping.column)),
+            None => Ok(names.iter().find_map(|name| position(name))),
+        };
+        Ok(Self {
+            date: find(Field::Date, &["date"])?.context("Import has no date column")?,
+            solve_time: find(Field::SolveTime, &["solve_time_secs", "solve_time", "time"])?,
+            cheated: find(Field::Cheated, &["cheated", "assisted"])?,
+            puzzle_id: find(Field::PuzzleId, &["puzzle_id"])?,
+            publish_type: find(Field::PublishType, &["publish_type"])?,
+        })
+    }
+}
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(value, "%m/%d/%Y"))
+        .map_err(|_| format!("invalid date {value:?}"))
+}
+
+/// Parse a duration given in seconds, MM:SS, or H:MM:SS
+fn parse_solve_time(value: &str) -> Result<u32, String> {
+    let invalid = || format!("invalid solve time {value:?}");
+    let mut secs = 0_u32;
+    let parts: Vec<&str> = value.split(':').collect();
+    if parts.len() > 3 {
+        return Err(invalid());
+    }
+    for (i, part) in parts.iter().enumerate() {
+        let n: u32 = part.trim().parse().map_err(|_| invalid())?;
+        // Minutes and seconds after the first part must be below 60
+        if i > 0 && n >= 60 {
+            return Err(invalid());
+        }
+        secs = secs
+            .checked_mul(60)
+            .and_then(|s| s.checked_add(n))
+            .ok_or_else(invalid)?;
+    }
+    Ok(secs)
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "y" | "1" => Ok(true),
+        "false" | "no" | "n" | "0" => Ok(false),
+        _ => Err(format!("invalid boolean {value:?}")),
+    }
+}
+
+/// Validate and normalize a single row
+fn parse_row(row: &StringRecord, columns: Columns) -> Result<PuzzleStats, String> {
+    let field = |i: Option<usize>| {
+        i.and_then(|i| row.get(i))
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+    };
+    let date = parse_date(field(Some(columns.date)).ok_or("missing date")?)?;
+    let mut record = PuzzleStats::empty(date);
+    record.solve_time_secs = field(columns.solve_time)
+        .map(parse_solve_time)
+        .transpose()?;
+    record.cheated = Some(
+        field(columns.cheated)
+            .map(parse_bool)
+            .transpose()?
+            .unwrap_or(false),
+    );
+    record.puzzle_id = field(columns.puzzle_id)
+        .map(|v| v.parse().map_err(|_| format!("invalid puzzle id {v:?}")))
+        .transpose()?;
+    if let Some(value) = field(columns.publish_type) {
+        record.publish_type = PublishType::from_str(value, true)
+            .map_err(|_| format!("invalid publish type {value:?}"))?;
+    }
+    Ok(record)
+}
+
+/// Read and validate the rows of a tracker CSV. See `read_tracker`.
+pub fn read<R: Read>(reader: R) -> Result<Import> {
+    read_tracker(reader, &[])
+}
+
+/// Read and validate the rows of a tracker CSV, with columns mapped to fields by name in addition
+/// to the recognized names. Rows are validated in parallel, and every invalid row is reported
+/// rather than stopping at the first. If a puzzle appears more than once, the last row wins and
+/// the earlier ones are reported.
+pub fn read_tracker<R: Read>(reader: R, map: &[ColumnMapping]) -> Result<Import> {
+    let mut reader = database::csv_reader(reader)?;
+    let columns = Columns::from_header(reader.headers()?, map)?;
+    let mut rows = Vec::new();
+    let mut issues = Vec::new();
+    for (i, row) in reader.records().enumerate() {
+        match row {
+            Ok(row) => rows.push(row),
+            // Rows are counted after the header
+            Err(e) => issues.push(RowIssue::line(i as u64 + 2, e.to_string())),
+        }
+    }
+
+    let n_threads = thread::available_parallelism().map_or(1, usize::from);
+    let chunk_size = rows.len().div_ceil(n_threads).max(1);
+    let parsed: Vec<(u64, Result<PuzzleStats, String>)> = thread::scope(|scope| {
+        let handles: Vec<_> = rows
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|row| {
+                            let line = row.position().map_or(0, csv::Position::line);
+                            (line, parse_row(row, columns))
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("Import validation panicked"))
+            .collect()
+    });
+
+    let mut records: HashMap<RecordKey, (u64, PuzzleStats)> = HashMap::new();
+    for (line, result) in parsed {
+        match result {
+            Ok(record) => {
+                if let Some((previous, _)) = records.insert(record.key(), (line, record)) {
+                    issues.push(RowIssue::line(
+                        previous,
+                        format!("superseded by the row on line {line}"),
+                    ));
+                }
+            }
+            Err(message) => issues.push(RowIssue::line(line, message)),
+        }
+    }
+    issues.sort_by_key(|issue| issue.line);
+    let mut records: Vec<PuzzleStats> = records.into_values().map(|(_, r)| r).collect();
+    records.sort_unstable_by_key(PuzzleStats::key);
+    Ok(Import { records, issues })
+}
+
+/// Date in a file name, as YYYY-MM-DD or YYYYMMDD
+fn date_in_name(name: &str) -> Option<NaiveDate> {
+    [("%Y-%m-%d", 10), ("%Y%m%d", 8)]
+        .into_iter()
+        .find_map(|(format, len)| {
+            (0..name.len().saturating_sub(len - 1))
+                .filter_map(|start| name.get(start..start + len))
+                .find_map(|candidate| NaiveDate::parse_from_str(candidate, format).ok())
+        })
+}
+
+/// Read a .puz file, or every .puz file in a directory. Unreadable files are reported, as are
+/// earlier files of a puzzle that appears more than once.
+fn read_puz(path: &Path) -> Result<Import> {
+    let mut files = if path.is_dir() {
+        std::fs::read_dir(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<std::io::Result<Vec<_>>>()?
+            .into_iter()
+            .filter(|file| {
+                file.extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("puz"))
+            })
+            .collect()
+    } else {
+        vec![path.to_path_buf()]
+    };
+    files.sort();
+
+    let mut records: HashMap<RecordKey, (PathBuf, PuzzleStats)> = HashMap::new();
+    let mut issues = Vec::new();
+    for file in files {
+        let name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let record = date_in_name(&name)
+            .context("No date in the file name")
+            .and_then(|date| {
+                let puzzle = puz::parse(&std::fs::read(&file)?)?;
+                let mut record = PuzzleStats::empty(date);
+                record.title = puzzle.title;
+                record.author = puzzle.author;
+                record.percent_filled = Some(puzzle.percent_filled);
+                if puzzle.solved {
+                    record.solve_time_secs = puzzle.elapsed_secs;
+                }
+                record.cheated = Some(puzzle.solved && puzzle.assisted);
+                record.source = Some(format!("{}:{name}", Format::Puz));
+                Ok(record)
+            });
+        match record {
+            Ok(record) => {
+                if let Some((previous, _)) = records.insert(record.key(), (file.clone(), record)) {
+                    issues.push(RowIssue {
+                        line: None,
+                        file: Some(previous),
+                        message: format!("superseded by {name}"),
+                    });
+                }
+            }
+            Err(e) => issues.push(RowIssue {
+                line: None,
+                file: Some(file),
+                message: format!("{e:#}"),
+            }),
+        }
+    }
+    let mut records: Vec<PuzzleStats> = records.into_values().map(|(_, r)| r).collect();
+    records.sort_unstable_by_key(PuzzleStats::key);
+    Ok(Import { records, issues })
+}
+
+/// Detect the format of an import from its path and, for CSV files, its header
+pub fn detect(path: &Path) -> Result<Format> {
+    if path.is_dir()
+        || path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("puz"))
+    {
+        return Ok(Format::Puz);
+    }
+    if database::is_sqlite(path) {
+        return Ok(Format::Database);
+    }
+    let file =
+        std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut reader = database::csv_reader(file)?;
+    let header = reader.headers()?;
+    let is_database = ["date", "weekday", "solve_time_secs"]
+        .iter()
+        .all(|column| header.iter().any(|h| h == *column));
+    Ok(if is_database {
+        Format::Database
+    } else {
+        Format::Tracker
+    })
+}
+
+/// Read the import at the given path, in the given format or the detected one. Mappings imply a
+/// tracker CSV. Records without a source are given one made of the format and file name.
+///
+/// Returns the format read along with the import
+pub fn read_path(
+    path: &Path,
+    format: Option<Format>,
+    map: &[ColumnMapping],
+) -> Result<(Format, Import)> {
+    let format = match format {
+        Some(format) => format,
+        None if !map.is_empty() => Format::Tracker,
+        None => detect(path)?,
+    };
+    if !map.is_empty() && format != Format::Tracker {
+        bail!("Column mappings only apply to tracker imports, not {format} imports");
+    }
+    let mut import = match format {
+        Format::Database => {
+            let mut records: Vec<PuzzleStats> =
+                database::load_records(path)?.into_values().collect();
+            records.sort_unstable_by_key(PuzzleStats::key);
+            Import {
+                records,
+                issues: Vec::new(),
+            }
+        }
+        Format::Tracker => {
+            let file = std::fs::File::open(path)
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+            read_tracker(file, map)?
+        }
+        Format::Puz => read_puz(path)?,
+    };
+    let name = path.file_name().map_or_else(
+        || path.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    );
+    for record in &mut import.records {
+        if record.source.is_none() {
+            record.source = Some(format!("{format}:{name}"));
+        }
+    }
+    Ok((format, import))
+}
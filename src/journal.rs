@@ -0,0 +1,123 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Append-only journal of what has been done to a database, one JSON object per line
+
+use crate::logger::RunSummary;
+use anyhow::{Context, Result};
+use chrono::naive::NaiveDate;
+use serde::Serialize;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Something done to the database
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JournalEntry {
+    /// A fetch run completed
+    Run {
+        unix: i64,
+        #[serde(flatten)]
+        summary: RunSummary,
+    },
+    /// A copy of the database was saved
+    Snapshot {
+        unix: i64,
+        path: PathBuf,
+        /// Number of records in the database at the time
+        records: usize,
+    },
+    /// The weekly digest of the week starting on the given Monday was sent
+    Digest { unix: i64, week_start: NaiveDate },
+}
+
+/// Path of the journal stored alongside the database at the given path
+#[must_use]
+pub fn journal_path(db_path: &Path) -> PathBuf {
+    db_path.with_extension("journal.jsonl")
+}
+
+/// Append an entry to the journal at the given path, creating it if needed
+pub fn append(path: &Path, entry: &JournalEntry) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+    // A single write keeps concurrent appends from interleaving
+    file.write_all(&line)
+        .with_context(|| format!("Failed to write to {}", path.display()))?;
+    Ok(())
+}
+
+/// Entries of the journal at the given path as JSON, skipping malformed lines. A missing journal
+/// has no entries.
+fn read_entries(path: &Path) -> Result<Vec<serde_json::Value>> {
+    let journal = match fs::read_to_string(path) {
+        Ok(journal) => journal,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    Ok(journal
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Start of the latest week whose digest was recorded in the journal at the given path, if any
+pub fn last_digest(path: &Path) -> Result<Option<NaiveDate>> {
+    Ok(read_entries(path)?
+        .into_iter()
+        .filter(|entry| entry["event"] == "digest")
+        .filter_map(|entry| entry["week_start"].as_str()?.parse().ok())
+        .max())
+}
+
+/// Totals of the fetch runs recorded in a journal
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FetchHistory {
+    pub runs: u32,
+    /// Number of puzzles whose stats were fetched or failed to be fetched
+    pub puzzles: u64,
+    /// Number of puzzles whose stats couldn't be fetched, even after retries
+    pub errors: u64,
+}
+
+impl FetchHistory {
+    /// Fraction of puzzles whose stats couldn't be fetched, or `None` if no puzzles were fetched
+    #[must_use]
+    pub fn failure_rate(&self) -> Option<f64> {
+        #[allow(clippy::cast_precision_loss)]
+        (self.puzzles > 0).then(|| self.errors as f64 / self.puzzles as f64)
+    }
+}
+
+/// Totals of the fetch runs recorded in the journal at the given path
+pub fn fetch_history(path: &Path) -> Result<FetchHistory> {
+    let count = |entry: &serde_json::Value, field: &str| entry[field].as_u64().unwrap_or(0);
+    Ok(read_entries(path)?
+        .iter()
+        .filter(|entry| entry["event"] == "run")
+        .fold(FetchHistory::default(), |history, entry| FetchHistory {
+            runs: history.runs + 1,
+            puzzles: history.puzzles
+                + count(entry, "solved")
+                + count(entry, "unsolved")
+                + count(entry, "errors"),
+            errors: history.errors + count(entry, "errors"),
+        }))
+}
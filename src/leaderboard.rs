@@ -0,0 +1,144 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::api_client::PublishType;
+use crate::{PuzzleStats, RecordKey};
+use anyhow::{Context, Result};
+use chrono::{naive::NaiveDate, Datelike, Weekday};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Fastest unassisted solve for a given weekday
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PersonalBest {
+    pub weekday: Weekday,
+    pub date: NaiveDate,
+    pub solve_time_secs: u32,
+}
+
+impl PersonalBest {
+    /// The personal best the given record would set, if it is an unassisted solve of a daily
+    /// crossword
+    fn from_record(record: &PuzzleStats) -> Option<Self> {
+        if record.cheated != Some(false) || record.publish_type != PublishType::Daily {
+            return None;
+        }
+        Some(Self {
+            weekday: record.date.weekday(),
+            date: record.date,
+            solve_time_secs: record.solve_time_secs?,
+        })
+    }
+}
+
+/// Per-weekday personal records, maintained incrementally as records are added
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Leaderboard {
+    bests: HashMap<Weekday, PersonalBest>,
+}
+
+impl Leaderboard {
+    /// Build a leaderboard by scanning the given records
+    pub fn from_records<'a, I: IntoIterator<Item = &'a PuzzleStats>>(records: I) -> Self {
+        let mut leaderboard = Self::default();
+        for record in records {
+            leaderboard.update(record);
+        }
+        leaderboard
+    }
+
+    /// Load a leaderboard previously written with `save`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let reader = csv::Reader::from_path(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut leaderboard = Self::default();
+        for best in reader.into_deserialize() {
+            let best: PersonalBest = best.with_context(|| "Malformed personal best")?;
+            leaderboard.bests.insert(best.weekday, best);
+        }
+        Ok(leaderboard)
+    }
+
+    /// Whether every personal best is held by an unassisted solve of the same time in the given
+    /// records. A leaderboard loaded from file may not be, e.g. after the database was edited by
+    /// hand, merged, imported or repaired.
+    #[must_use]
+    pub fn matches(&self, records: &HashMap<RecordKey, PuzzleStats>) -> bool {
+        self.bests.values().all(|best| {
+            records
+                .get(&(best.date, PublishType::Daily))
+                .and_then(PersonalBest::from_record)
+                == Some(*best)
+        })
+    }
+
+    /// Write the leaderboard to file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut writer = csv::Writer::from_path(path)?;
+        for best in self.iter() {
+            writer.serialize(best)?;
+        }
+        Ok(())
+    }
+
+    /// Personal best for the given weekday, if there is one
+    #[must_use]
+    pub fn get(&self, weekday: Weekday) -> Option<PersonalBest> {
+        self.bests.get(&weekday).copied()
+    }
+
+    /// Personal bests ordered by weekday, starting on Monday
+    pub fn iter(&self) -> impl Iterator<Item = PersonalBest> + '_ {
+        let mut bests: Vec<PersonalBest> = self.bests.values().copied().collect();
+        bests.sort_by_key(|best| best.weekday.num_days_from_monday());
+        bests.into_iter()
+    }
+
+    /// Update the leaderboard with the given record.
+    ///
+    /// Returns the new personal best if the record set one.
+    pub fn update(&mut self, record: &PuzzleStats) -> Option<PersonalBest> {
+        let candidate = PersonalBest::from_record(record)?;
+        match self.bests.get(&candidate.weekday) {
+            Some(best) if best.solve_time_secs <= candidate.solve_time_secs => None,
+            _ => {
+                self.bests.insert(candidate.weekday, candidate);
+                Some(candidate)
+            }
+        }
+    }
+
+    /// Returns true if the given date currently holds the personal best for its weekday
+    #[must_use]
+    pub fn is_best(&self, date: NaiveDate) -> bool {
+        self.get(date.weekday())
+            .is_some_and(|best| best.date == date)
+    }
+
+    /// Recompute the personal best for a single weekday from the given records
+    pub fn rebuild_weekday<'a, I: IntoIterator<Item = &'a PuzzleStats>>(
+        &mut self,
+        weekday: Weekday,
+        records: I,
+    ) {
+        self.bests.remove(&weekday);
+        for record in records {
+            if record.date.weekday() == weekday {
+                self.update(record);
+            }
+        }
+    }
+}
@@ -12,22 +12,67 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod analytics;
 pub mod api_client;
+#[cfg(feature = "schema-check")]
+pub mod api_schema;
+pub mod archive;
+pub mod auth;
+pub mod backup;
+#[cfg(feature = "browser-cookies")]
+pub mod browser;
+pub mod cache;
 pub mod database;
+pub mod diff;
+pub mod digest;
+#[cfg(feature = "exporters")]
+pub mod export;
+pub mod goals;
+pub mod health;
+pub mod import;
+pub mod journal;
+pub mod leaderboard;
 pub mod logger;
+pub mod merge;
+pub mod metrics;
+pub mod notify;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+pub mod plan;
+pub mod puz;
+pub mod rate_limit;
+pub mod repair;
+#[cfg(feature = "exporters")]
+pub mod report;
+pub mod reprocess;
+pub mod run_status;
+pub mod schema;
 pub mod search;
+pub mod snapshot;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod streaks;
+pub mod timezone;
+pub mod usage;
+pub mod verify;
+pub mod watchdog;
 
-use api_client::SolvedPuzzleStats;
+use api_client::{PublishType, PuzzleInfo, SolvedPuzzleStats};
 use chrono::{naive::NaiveDate, Datelike, Duration, Weekday};
 use database::Database;
 use serde::{Deserialize, Serialize};
 use std::cmp;
+use std::collections::HashMap;
 
 // Size of each block of dates to fetch metadata about. Currently hard-coded to match the expected
-// server response with no validation that this is correct.
+// server response. If the server returns fewer dates than requested, `api_client::get_puzzle_info`
+// requests the rest, so a smaller server-side page size costs extra requests but no data.
 pub const DAY_STEP: i64 = 100;
 
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Deserialize, Serialize)]
+/// Uniquely identifies a record: the publication date and the kind of puzzle
+pub type RecordKey = (NaiveDate, PublishType);
+
+#[derive(Debug, Clone, Hash, PartialEq, Deserialize, Serialize)]
 pub struct PuzzleStats {
     pub date: NaiveDate,
     /// id used to identify a puzzle to NYT server
@@ -39,6 +84,38 @@ pub struct PuzzleStats {
     opened_unix: Option<u32>,
     solved_unix: Option<u32>,
     pub cheated: Option<bool>,
+    /// Whether the puzzle was solved between its release and the end of its publication day in
+    /// the solver's home time zone. Derived from `solved_unix`; see `timezone::solved_on_pub_day`.
+    #[serde(default)]
+    pub solved_on_pub_day: Option<bool>,
+    /// Constructor(s) of the puzzle
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub editor: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Percentage of the grid filled in as of when the puzzle's id was looked up. Useful for
+    /// tracking puzzles that were started but not finished.
+    #[serde(default)]
+    pub percent_filled: Option<u8>,
+    /// Whether the puzzle had earned a gold star as of when the puzzle's id was looked up
+    #[serde(default)]
+    pub gold_star: Option<bool>,
+    /// Percentage of earlier unassisted solves of the same weekday that were slower than this one.
+    /// Only filled in when enabled; see `analytics::percent_ranks`.
+    #[serde(default)]
+    pub percent_rank: Option<u8>,
+    /// Kind of crossword. Databases written by older versions only hold daily crosswords.
+    #[serde(default)]
+    pub publish_type: PublishType,
+    /// Set when the stats for this puzzle couldn't be fetched in the latest attempt
+    #[serde(default)]
+    pub fetch_failed: Option<bool>,
+    /// Where an imported record came from, e.g. `tracker:spreadsheet.csv`. Empty for records
+    /// fetched from the NYT.
+    #[serde(default)]
+    pub source: Option<String>,
 }
 
 impl PuzzleStats {
@@ -52,7 +129,17 @@ impl PuzzleStats {
             solve_time_secs: solve_stats.map(|s| s.solve_time),
             opened_unix: solve_stats.and_then(|s| s.opened),
             solved_unix: solve_stats.and_then(|s| s.solved),
-            cheated: Some(false),
+            cheated: Some(solve_stats.is_some_and(|s| s.cheated)),
+            solved_on_pub_day: None,
+            author: None,
+            editor: None,
+            title: None,
+            percent_filled: None,
+            gold_star: None,
+            percent_rank: None,
+            publish_type: PublishType::Daily,
+            fetch_failed: None,
+            source: None,
         }
     }
 
@@ -67,49 +154,177 @@ impl PuzzleStats {
             opened_unix: None,
             solved_unix: None,
             cheated: Some(false),
+            solved_on_pub_day: None,
+            author: None,
+            editor: None,
+            title: None,
+            percent_filled: None,
+            gold_star: None,
+            percent_rank: None,
+            publish_type: PublishType::Daily,
+            fetch_failed: None,
+            source: None,
         }
     }
 
+    /// Key identifying the puzzle this record is for
+    #[must_use]
+    pub fn key(&self) -> RecordKey {
+        (self.date, self.publish_type)
+    }
+
     /// Returns true if there is no more information to fetch for the given record because it has
     /// already been completed, with or without cheats, and all expected fields are filled.
     pub fn is_complete(&self) -> bool {
-        self.puzzle_id.is_some()
-            && (self.solve_time_secs.is_some() || self.cheated.unwrap_or(false))
+        self.puzzle_id.is_some() && self.is_solved()
+    }
+
+    /// Returns true if the puzzle is known to have been solved, with or without cheats
+    pub fn is_solved(&self) -> bool {
+        self.solve_time_secs.is_some() || self.cheated.unwrap_or(false)
     }
 
-    /// Update the given record with information from the given `SolvedPuzzleStats`
+    /// Returns true if the puzzle was started, i.e. opened or partly filled in, but isn't known to
+    /// be solved
+    #[must_use]
+    pub fn is_in_progress(&self) -> bool {
+        !self.is_solved()
+            && (self.opened_unix.is_some() || self.percent_filled.is_some_and(|p| p > 0))
+    }
+
+    /// Number of columns of the record that aren't empty
+    #[must_use]
+    pub fn filled_columns(&self) -> usize {
+        let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(self) else {
+            unreachable!("PuzzleStats always serializes to an object");
+        };
+        fields.values().filter(|v| !v.is_null()).count()
+    }
+
+    /// Update the given record with information from the given `SolvedPuzzleStats`. Solve times
+    /// are kept for assisted solves too, so check `cheated` before comparing times.
     pub fn update_stats(&mut self, stats: SolvedPuzzleStats) {
-        if stats.cheated {
-            self.cheated = Some(true);
-            self.solve_time_secs = None;
-        } else {
-            self.cheated = Some(false);
-            self.solve_time_secs = Some(stats.solve_time);
-        }
+        self.cheated = Some(stats.cheated);
+        self.solve_time_secs = Some(stats.solve_time);
         self.opened_unix = stats.opened;
         self.solved_unix = stats.solved;
     }
+
+    /// Update the given record with the id and metadata fields from the given `PuzzleInfo`
+    pub fn update_info(&mut self, info: PuzzleInfo) {
+        self.puzzle_id = Some(info.puzzle_id);
+        self.author = info.author;
+        self.editor = info.editor;
+        self.title = info.title;
+        self.percent_filled = info.percent_filled;
+        self.gold_star = Some(info.gold_star);
+    }
+
+    /// Fill in what this newer record of the same puzzle is missing from an existing one, so that
+    /// a fetch that comes back with less information, e.g. an API hiccup reporting a solved puzzle
+    /// as unsolved, doesn't throw away what was already known. An unsolved record takes all the
+    /// solve columns of a solved one, along with its source, or when an unsolved one was opened.
+    /// Empty descriptive columns are filled in. The fetch status is always the newer record's.
+    ///
+    /// Returns whether the existing solve was kept over an unsolved newer record
+    pub fn fill_from(&mut self, existing: &Self) -> bool {
+        let kept_solve = existing.is_solved() && !self.is_solved();
+        if kept_solve {
+            self.solve_time_secs = existing.solve_time_secs;
+            self.opened_unix = existing.opened_unix;
+            self.solved_unix = existing.solved_unix;
+            self.cheated = existing.cheated;
+            self.solved_on_pub_day = existing.solved_on_pub_day;
+            self.percent_rank = existing.percent_rank;
+            self.source.clone_from(&existing.source);
+        } else if !self.is_solved() {
+            // Once opened, a puzzle stays opened
+            self.opened_unix = self.opened_unix.or(existing.opened_unix);
+        }
+        self.puzzle_id = self.puzzle_id.or(existing.puzzle_id);
+        self.author = self.author.take().or_else(|| existing.author.clone());
+        self.editor = self.editor.take().or_else(|| existing.editor.clone());
+        self.title = self.title.take().or_else(|| existing.title.clone());
+        self.percent_filled = self.percent_filled.or(existing.percent_filled);
+        self.gold_star = self.gold_star.or(existing.gold_star);
+        kept_solve
+    }
+
+    /// Fill in `solved_on_pub_day` from the solve timestamp, if there is one
+    pub fn derive_solved_on_pub_day(&mut self, home: timezone::HomeTimezone) {
+        self.solved_on_pub_day = self
+            .solved_unix
+            .map(|solved| timezone::solved_on_pub_day(self.date, solved, home));
+    }
 }
 
-/// Get records within the given range, inclusive, that are missing ids, including for days that
-/// are not present in the database. The results are split into chunks no more than
-/// `max_chunk_duration` long for convenience, as the NYT id APIs allow batched lookup of ids.
+/// Get records of the given publish type within the given range, inclusive, that are missing ids,
+/// including for days that are not present in the database. The results are split into chunks no
+/// more than `max_chunk_duration` long for convenience, as the NYT id APIs allow batched lookup of
+/// ids.
 #[must_use]
 pub fn get_days_without_ids_chunked(
     database: &Database,
+    publish_type: PublishType,
     start: NaiveDate,
     end: NaiveDate,
     max_chunk_duration: Duration,
 ) -> Vec<Vec<PuzzleStats>> {
+    chunk_days_without_ids(
+        |date| database.get(date, publish_type),
+        publish_type,
+        start,
+        end,
+        max_chunk_duration,
+    )
+}
+
+/// Same as `get_days_without_ids_chunked`, but plans from the given records rather than a
+/// `Database`, for callers that keep records in their own storage. Dates missing from `records`
+/// are treated as not yet fetched. If several records share a date, the last one is used.
+#[must_use]
+pub fn get_records_without_ids_chunked<'a, I>(
+    records: I,
+    publish_type: PublishType,
+    start: NaiveDate,
+    end: NaiveDate,
+    max_chunk_duration: Duration,
+) -> Vec<Vec<PuzzleStats>>
+where
+    I: IntoIterator<Item = &'a PuzzleStats>,
+{
+    let by_date: HashMap<NaiveDate, &PuzzleStats> = records
+        .into_iter()
+        .filter(|r| r.publish_type == publish_type)
+        .map(|r| (r.date, r))
+        .collect();
+    chunk_days_without_ids(
+        |date| by_date.get(&date).copied(),
+        publish_type,
+        start,
+        end,
+        max_chunk_duration,
+    )
+}
+
+fn chunk_days_without_ids<'a, F>(
+    get: F,
+    publish_type: PublishType,
+    start: NaiveDate,
+    end: NaiveDate,
+    max_chunk_duration: Duration,
+) -> Vec<Vec<PuzzleStats>>
+where
+    F: Fn(NaiveDate) -> Option<&'a PuzzleStats>,
+{
     let mut chunks: Vec<Vec<PuzzleStats>> = Vec::new();
     let mut current_start = start;
     while current_start <= end {
         // Find next date in given range on or after current_start that does not have a cached id
-        // in the database
         current_start = match current_start
             .iter_days()
             .take_while(|date| *date <= end)
-            .find(|date| match database.get(*date) {
+            .find(|date| match get(*date) {
                 Some(record) => record.puzzle_id.is_none(),
                 None => true,
             }) {
@@ -122,15 +337,17 @@ pub fn get_days_without_ids_chunked(
             .iter_days()
             .take_while(|date| *date <= current_end)
             .filter_map(|date| {
-                if let Some(record) = database.get(date) {
+                if let Some(record) = get(date) {
                     if record.puzzle_id.is_none() {
-                        Some(record)
+                        Some(record.clone())
                     } else {
                         None
                     }
                 } else {
-                    // The date does not exist in the database at all
-                    Some(PuzzleStats::empty(date))
+                    // The date does not exist in the records at all
+                    let mut record = PuzzleStats::empty(date);
+                    record.publish_type = publish_type;
+                    Some(record)
                 }
             })
             .collect();
@@ -141,19 +358,107 @@ pub fn get_days_without_ids_chunked(
     chunks
 }
 
-/// Get records from database that have a cached puzzle id but aren't known to be solved
+/// Get records of the given publish type from database that have a cached puzzle id but aren't
+/// known to be solved
+#[must_use]
+pub fn get_cached_unsolved_records(
+    database: &Database,
+    publish_type: PublishType,
+    start: NaiveDate,
+) -> Vec<&PuzzleStats> {
+    get_records_to_refresh(database, publish_type, start, Refresh::Unsolved)
+}
+
+/// Which cached records a fetch run refreshes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Refresh {
+    /// Records that aren't known to be solved
+    Unsolved,
+    /// Records that are already solved, e.g. to pick up changes to past solves
+    Solved,
+    /// Records whose stats couldn't be fetched in the latest attempt
+    Errors,
+    /// Records of puzzles that were started but aren't known to be solved, to pick up solves
+    /// finished since the last run without refreshing every unsolved puzzle
+    InProgress,
+    /// Every record, complete or not, e.g. to overwrite stats recorded before an API change
+    All,
+}
+
+impl Refresh {
+    /// Whether the given record should be refreshed
+    #[must_use]
+    pub fn matches(self, record: &PuzzleStats) -> bool {
+        match self {
+            Self::Unsolved => !record.is_solved(),
+            Self::Solved => record.is_solved(),
+            Self::Errors => record.fetch_failed.unwrap_or(false),
+            Self::InProgress => record.is_in_progress(),
+            Self::All => true,
+        }
+    }
+}
+
+/// Get records of the given publish type from database with a cached puzzle id that match the
+/// given filter
+#[must_use]
+pub fn get_records_to_refresh(
+    database: &Database,
+    publish_type: PublishType,
+    start: NaiveDate,
+    refresh: Refresh,
+) -> Vec<&PuzzleStats> {
+    database
+        .range(start..)
+        .filter(|r| r.publish_type == publish_type && r.puzzle_id.is_some() && refresh.matches(r))
+        .collect()
+}
+
+/// Measure how much of the daily crossword archive within the given range, inclusive, has been
+/// completed.
+///
+/// Returns the number of complete records and the total number of days in the range.
 #[must_use]
-pub fn get_cached_unsolved_records(database: &Database, start: NaiveDate) -> Vec<PuzzleStats> {
-    let mut records = database.records();
-    records.retain(|r| !r.is_complete() && r.puzzle_id.is_some() && r.date >= start);
-    records
+pub fn archive_completion(database: &Database, start: NaiveDate, end: NaiveDate) -> (usize, usize) {
+    let total = start.iter_days().take_while(|date| *date <= end).count();
+    let complete = database
+        .range(start..=end)
+        .filter(|r| r.publish_type == PublishType::Daily && r.is_complete())
+        .count();
+    (complete, total)
+}
+
+/// Count the consecutive daily crosswords solved, counting back from `today`. An unsolved puzzle
+/// for `today` doesn't break the streak, since there may still be time to solve it.
+///
+/// This approximates the NYT's own streak, which also requires that each puzzle be solved on the
+/// day it was published.
+#[must_use]
+pub fn current_streak(database: &Database, today: NaiveDate) -> u32 {
+    let solved = |date| {
+        database
+            .get(date, PublishType::Daily)
+            .is_some_and(|r| r.is_solved())
+    };
+    let mut date = if solved(today) {
+        today
+    } else {
+        today - Duration::days(1)
+    };
+    let mut streak = 0;
+    while solved(date) {
+        streak += 1;
+        date -= Duration::days(1);
+    }
+    streak
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use anyhow::Result;
-    use api_client::SolvedPuzzleStats;
+    use api_client::{SolvedPuzzleStats, SubscriptionToken};
+    use database::{MemoryStorage, StorageBackend};
     use std::default::Default;
     use tempfile::NamedTempFile;
 
@@ -161,11 +466,8 @@ mod tests {
     /// Test get_days_without_ids_chunked
     /// TODO: add more test coverage
     fn days_without_ids() -> Result<()> {
-        fn contains_date(haystack: &Vec<Vec<PuzzleStats>>, date: NaiveDate) -> bool {
-            haystack
-                .into_iter()
-                .flatten()
-                .any(|record| record.date == date)
+        fn contains_date(haystack: &[Vec<PuzzleStats>], date: NaiveDate) -> bool {
+            haystack.iter().flatten().any(|record| record.date == date)
         }
 
         let file = NamedTempFile::new()?;
@@ -216,7 +518,19 @@ mod tests {
         let start = NaiveDate::from_ymd_opt(2020, 1, 1).unwrap();
         let end = NaiveDate::from_ymd_opt(2020, 1, 11).unwrap();
 
-        let chunks = get_days_without_ids_chunked(&db, start, end, Duration::days(5));
+        let chunks =
+            get_days_without_ids_chunked(&db, PublishType::Daily, start, end, Duration::days(5));
+        // Planning from a plain slice of records should match planning from the database
+        assert_eq!(
+            get_records_without_ids_chunked(
+                db.iter(),
+                PublishType::Daily,
+                start,
+                end,
+                Duration::days(5)
+            ),
+            chunks
+        );
         assert!(
             contains_date(&chunks, empty_date),
             "Empty record should be returned"
@@ -253,12 +567,282 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    /// Assisted solves should keep their solve time but stay flagged as cheated
+    fn update_stats_keeps_assisted_time() {
+        let date = NaiveDate::from_ymd_opt(2020, 1, 1).unwrap();
+        let mut record = PuzzleStats::new(date, 1, None);
+        record.update_stats(SolvedPuzzleStats {
+            solve_time: 600,
+            cheated: true,
+            ..Default::default()
+        });
+        assert_eq!(record.solve_time_secs, Some(600));
+        assert_eq!(record.cheated, Some(true));
+        assert!(record.is_solved());
+    }
+
+    #[test]
+    /// Personal bests should be tracked as records are added and replaced
+    fn personal_bests() -> Result<()> {
+        let storage = MemoryStorage::default();
+        let mut db = Database::from_storage(Box::new(storage.clone()))?;
+        let solve = |date, solve_time, cheated| {
+            PuzzleStats::new(
+                date,
+                1,
+                Some(SolvedPuzzleStats {
+                    solve_time,
+                    cheated,
+                    ..Default::default()
+                }),
+            )
+        };
+        let monday = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let next_monday = NaiveDate::from_ymd_opt(2024, 1, 8).unwrap();
+
+        assert!(db.add(solve(monday, 600, false)).is_some());
+        assert!(db.add(solve(next_monday, 300, true)).is_none());
+        assert_eq!(
+            db.add(solve(next_monday, 500, false)).unwrap().date,
+            next_monday
+        );
+        // Replacing the record holding the personal best falls back to the runner-up
+        db.add(solve(next_monday, 500, true));
+        assert_eq!(db.personal_bests().get(Weekday::Mon).unwrap().date, monday);
+        db.flush()?;
+        assert_eq!(storage.load()?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    /// Personal bests saved next to a database are rebuilt if the records changed without them
+    fn stale_personal_bests() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("data.csv");
+        let monday = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let solve = |solve_time| {
+            PuzzleStats::new(
+                monday,
+                1,
+                Some(SolvedPuzzleStats {
+                    solve_time,
+                    ..Default::default()
+                }),
+            )
+        };
+        let mut db = Database::new(&path);
+        db.add(solve(600));
+        db.close()?;
+        // The records are rewritten without updating the personal bests, like a hand edit would
+        database::CsvStorage::new(&path).save(&[&solve(700)])?;
+
+        let mut db = Database::from_file(&path)?;
+        assert_eq!(
+            db.personal_bests()
+                .get(Weekday::Mon)
+                .unwrap()
+                .solve_time_secs,
+            700
+        );
+        assert!(db.add(solve(650)).is_some());
+        Ok(())
+    }
+
+    #[test]
+    /// Databases should only be written when their records change
+    fn dirty_tracking() -> Result<()> {
+        let storage = MemoryStorage::default();
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let mut db = Database::from_storage(Box::new(storage.clone()))?;
+        assert!(!db.is_dirty());
+        db.add(PuzzleStats::empty(date));
+        assert!(db.is_dirty());
+        db.flush()?;
+        assert!(!db.is_dirty());
+
+        // Re-adding an identical record or re-deriving unchanged columns leaves it clean
+        db.add(PuzzleStats::empty(date));
+        db.derive_percent_ranks();
+        assert!(!db.is_dirty());
+        storage.0.lock().unwrap().clear();
+        db.close()?;
+        assert!(storage.load()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn database_queries() -> Result<()> {
+        let mut db = Database::from_storage(Box::new(MemoryStorage::default()))?;
+        let monday = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        for day in 0..14 {
+            let date = monday + Duration::days(day);
+            let solve = (day % 2 == 0).then(SolvedPuzzleStats::default);
+            db.add(PuzzleStats::new(date, 1, solve));
+        }
+        let sorted_dates = |records: Vec<&PuzzleStats>| {
+            let mut dates: Vec<u32> = records.iter().map(|r| r.date.day()).collect();
+            dates.sort_unstable();
+            dates
+        };
+
+        assert_eq!(db.iter().count(), 14);
+        let range = monday + Duration::days(2)..=monday + Duration::days(4);
+        assert_eq!(sorted_dates(db.range(range).collect()), [3, 4, 5]);
+        assert_eq!(db.range(..monday).count(), 0);
+        assert_eq!(sorted_dates(db.by_weekday(Weekday::Wed).collect()), [3, 10]);
+        assert_eq!(
+            sorted_dates(db.solved().filter(|r| r.date.day() < 6).collect()),
+            [1, 3, 5]
+        );
+        Ok(())
+    }
+
+    #[test]
+    /// A refetch that comes back with less information doesn't downgrade an existing record
+    fn merge_on_update() -> Result<()> {
+        let mut db = Database::from_storage(Box::new(MemoryStorage::default()))?;
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let mut solved = PuzzleStats::new(
+            date,
+            1,
+            Some(SolvedPuzzleStats {
+                solve_time: 300,
+                ..Default::default()
+            }),
+        );
+        solved.author = Some("Author".to_string());
+        db.add(solved);
+
+        let mut refetch = PuzzleStats::new(date, 1, None);
+        refetch.fetch_failed = Some(true);
+        db.add(refetch);
+        let record = db.get(date, PublishType::Daily).unwrap();
+        assert_eq!(record.solve_time_secs, Some(300));
+        assert_eq!(record.author.as_deref(), Some("Author"));
+        assert_eq!(record.fetch_failed, Some(true));
+
+        db.add(PuzzleStats::new(
+            date,
+            1,
+            Some(SolvedPuzzleStats {
+                solve_time: 200,
+                ..Default::default()
+            }),
+        ));
+        let record = db.get(date, PublishType::Daily).unwrap();
+        assert_eq!(record.solve_time_secs, Some(200));
+        assert_eq!(record.fetch_failed, None);
+        Ok(())
+    }
+
+    #[test]
+    /// A forced refresh refetches solved puzzles, but one that comes back unsolved keeps its solve
+    fn force_refresh_keeps_solve() -> Result<()> {
+        use plan::{FetchPlan, WorkItem};
+
+        let mut db = Database::from_storage(Box::new(MemoryStorage::default()))?;
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        db.add(PuzzleStats::new(
+            date,
+            1,
+            Some(SolvedPuzzleStats {
+                solve_time: 300,
+                ..Default::default()
+            }),
+        ));
+        let plan = FetchPlan::new(&db, PublishType::Daily, date, date, None, true);
+        assert_eq!(
+            plan.collect::<Vec<_>>(),
+            [WorkItem::StatsFetch {
+                publish_type: PublishType::Daily,
+                date,
+                id: 1
+            }]
+        );
+
+        db.add(PuzzleStats::new(date, 1, None));
+        let record = db.get(date, PublishType::Daily).unwrap();
+        assert!(record.is_solved());
+        assert_eq!(record.solve_time_secs, Some(300));
+        Ok(())
+    }
+
+    #[test]
+    /// Past years are matched on the same date, and on the same weekday within three days
+    fn on_this_day() -> Result<()> {
+        use analytics::OnThisDay;
+
+        let mut db = Database::from_storage(Box::new(MemoryStorage::default()))?;
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        // 2024-02-29 is a Thursday. 2021-03-01 is a Monday.
+        for (day, secs) in [(date(2023, 2, 28), 300), (date(2023, 3, 2), 400)] {
+            db.add(PuzzleStats::new(
+                day,
+                1,
+                Some(SolvedPuzzleStats {
+                    solve_time: secs,
+                    ..Default::default()
+                }),
+            ));
+        }
+        db.add(PuzzleStats::new(date(2021, 3, 1), 1, None));
+
+        let report = OnThisDay::new(&db, date(2024, 2, 29));
+        let years: Vec<i32> = report.years.iter().map(|y| y.year).collect();
+        assert_eq!(years, [2023]);
+        let past = report.years[0];
+        assert_eq!(past.same_date.unwrap().solve_time_secs, Some(300));
+        assert_eq!(past.same_weekday.unwrap().date, date(2023, 3, 2));
+        assert_eq!(OnThisDay::new(&db, date(2022, 3, 1)).years.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    /// Weekly digests compare each day to the same weekday of the previous four weeks
+    fn weekly_digest() -> Result<()> {
+        let mut db = Database::from_storage(Box::new(MemoryStorage::default()))?;
+        let solve = |date, solve_time, cheated| {
+            PuzzleStats::new(
+                date,
+                1,
+                Some(SolvedPuzzleStats {
+                    solve_time,
+                    cheated,
+                    ..Default::default()
+                }),
+            )
+        };
+        let week_start = NaiveDate::from_ymd_opt(2024, 2, 5).unwrap();
+        for (weeks, secs) in [(1, 400), (2, 600), (3, 9000), (4, 500)] {
+            db.add(solve(week_start - Duration::weeks(weeks), secs, weeks == 3));
+        }
+        for (day, secs) in [(0, 450), (1, 700), (2, 900), (3, 9000), (4, 1500)] {
+            db.add(solve(week_start + Duration::days(day), secs, day == 3));
+        }
+        let sunday = week_start + Duration::days(6);
+        assert_eq!(
+            digest::previous_week(sunday + Duration::days(1)),
+            week_start
+        );
+
+        let digest = digest::WeeklyDigest::new(&db, week_start, sunday);
+        assert_eq!(digest.days[0].average_secs, Some(500.0));
+        assert_eq!(digest.days[1].average_secs, None);
+        // The assisted solve on Thursday doesn't skew the scale
+        assert!(digest.days[3].cheated);
+        assert_eq!(digest.sparkline(), "▁▂▄·█··");
+        assert_eq!(digest.current_streak, 0);
+        assert_eq!(digest::sparkline(&[Some(5), Some(5), None]), "▄▄·");
+        Ok(())
+    }
+
     #[test]
     /// Test get_days_without_ids_chunked
     /// TODO: add more test coverage
     fn test_get_cached_unsolved_records() -> Result<()> {
-        fn contains_date(haystack: &Vec<PuzzleStats>, date: NaiveDate) -> bool {
-            haystack.into_iter().any(|record| record.date == date)
+        fn contains_date(haystack: &[&PuzzleStats], date: NaiveDate) -> bool {
+            haystack.iter().any(|record| record.date == date)
         }
 
         let file = NamedTempFile::new()?;
@@ -306,33 +890,1274 @@ mod tests {
         cheated_unided.puzzle_id = None;
         db.add(cheated_unided);
 
-        assert!(
-            get_cached_unsolved_records(&db, NaiveDate::from_ymd_opt(2020, 1, 5).unwrap())
-                .is_empty()
-        );
-        assert!(
-            get_cached_unsolved_records(&db, NaiveDate::from_ymd_opt(2020, 1, 8).unwrap())
-                .is_empty()
-        );
-        assert!(
-            get_cached_unsolved_records(&db, NaiveDate::from_ymd_opt(2020, 1, 9).unwrap())
-                .is_empty()
-        );
-        assert!(
-            get_cached_unsolved_records(&db, NaiveDate::from_ymd_opt(2020, 1, 10).unwrap())
-                .is_empty()
-        );
+        assert!(get_cached_unsolved_records(
+            &db,
+            PublishType::Daily,
+            NaiveDate::from_ymd_opt(2020, 1, 5).unwrap()
+        )
+        .is_empty());
+        assert!(get_cached_unsolved_records(
+            &db,
+            PublishType::Daily,
+            NaiveDate::from_ymd_opt(2020, 1, 8).unwrap()
+        )
+        .is_empty());
+        assert!(get_cached_unsolved_records(
+            &db,
+            PublishType::Daily,
+            NaiveDate::from_ymd_opt(2020, 1, 9).unwrap()
+        )
+        .is_empty());
+        assert!(get_cached_unsolved_records(
+            &db,
+            PublishType::Daily,
+            NaiveDate::from_ymd_opt(2020, 1, 10).unwrap()
+        )
+        .is_empty());
 
-        let cached_unsolved =
-            get_cached_unsolved_records(&db, NaiveDate::from_ymd_opt(2020, 1, 4).unwrap());
+        let cached_unsolved = get_cached_unsolved_records(
+            &db,
+            PublishType::Daily,
+            NaiveDate::from_ymd_opt(2020, 1, 4).unwrap(),
+        );
         assert!(cached_unsolved.len() == 1);
         assert!(contains_date(&cached_unsolved, unsolved_ided_date));
 
-        let cached_unsolved =
-            get_cached_unsolved_records(&db, NaiveDate::from_ymd_opt(2020, 1, 1).unwrap());
+        let cached_unsolved = get_cached_unsolved_records(
+            &db,
+            PublishType::Daily,
+            NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
+        );
         assert!(cached_unsolved.len() == 1);
         assert!(contains_date(&cached_unsolved, unsolved_ided_date));
 
         Ok(())
     }
+
+    #[test]
+    fn in_progress_records() -> Result<()> {
+        let file = NamedTempFile::new()?;
+        let mut db = Database::new(file.into_temp_path().to_path_buf());
+        let date = |day| NaiveDate::from_ymd_opt(2020, 1, day).unwrap();
+        db.add(PuzzleStats::new(date(1), 1, None));
+        let mut filled = PuzzleStats::new(date(2), 2, None);
+        filled.percent_filled = Some(40);
+        db.add(filled);
+        let mut opened = PuzzleStats::new(date(3), 3, None);
+        opened.opened_unix = Some(1_577_000_000);
+        db.add(opened);
+        let mut solved = PuzzleStats::new(date(4), 4, Some(SolvedPuzzleStats::default()));
+        solved.percent_filled = Some(100);
+        db.add(solved);
+
+        let mut in_progress: Vec<NaiveDate> =
+            get_records_to_refresh(&db, PublishType::Daily, date(1), Refresh::InProgress)
+                .iter()
+                .map(|r| r.date)
+                .collect();
+        in_progress.sort_unstable();
+        assert_eq!(in_progress, vec![date(2), date(3)]);
+
+        // A later fetch that doesn't report the puzzle as opened doesn't forget that it was
+        db.add(PuzzleStats::new(date(3), 3, None));
+        assert!(db
+            .get(date(3), PublishType::Daily)
+            .unwrap()
+            .is_in_progress());
+        Ok(())
+    }
+
+    #[test]
+    fn cache_partitions() -> Result<()> {
+        use cache::{CachedResponse, ResponseCache};
+
+        let dir = tempfile::tempdir()?;
+        let cache = ResponseCache::new(dir.path())?;
+        let response = |url: &str| CachedResponse {
+            url: url.to_string(),
+            etag: None,
+            last_modified: None,
+            fetched_unix: 0,
+            body: "{}".to_string(),
+        };
+        let date = |m, d| NaiveDate::from_ymd_opt(2024, m, d);
+        cache.put(&response("a"), date(1, 5))?;
+        cache.put(&response("b"), date(2, 5))?;
+        cache.put(&response("c"), date(3, 5))?;
+        cache.put(&response("streaks"), None)?;
+        assert!(dir.path().join("2024/02/index.json").exists());
+        assert!(cache.get("a", date(1, 5)).is_some());
+        assert!(cache.get("a", date(2, 5)).is_none());
+
+        let urls = |start, end| -> Result<Vec<String>> {
+            Ok(cache
+                .entries(start, end)?
+                .into_iter()
+                .map(|e| e.url)
+                .collect())
+        };
+        assert_eq!(urls(date(1, 6).unwrap(), date(3, 5).unwrap())?, ["b", "c"]);
+
+        let summary = cache.prune(1)?;
+        assert_eq!((summary.months, summary.responses), (2, 2));
+        assert_eq!(urls(date(1, 1).unwrap(), date(12, 31).unwrap())?, ["c"]);
+        assert!(cache.get("streaks", None).is_some());
+        Ok(())
+    }
+
+    #[cfg(feature = "schema-check")]
+    #[test]
+    /// Only missing fields and changed types break parsing, and fields under a missing optional
+    /// object aren't reported
+    fn api_schema_drift() -> Result<()> {
+        use api_client::Endpoint;
+        use api_schema::DriftKind;
+
+        let expected = api_schema::expected(&Endpoint::PuzzleStats { id: 1 })?;
+        let response = serde_json::json!({
+            "calcs": { "solved": "yes", "percentFilled": 100 },
+            "board": { "cells": [] },
+        });
+        let drifts =
+            api_schema::compare("puzzle_stats", &expected, &api_schema::observe(&response));
+        let summary: Vec<(&str, bool)> = drifts
+            .iter()
+            .map(|d| (d.path.as_str(), d.is_breaking()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("calcs.solved", true),
+                ("board", false),
+                ("calcs.percentFilled", false)
+            ]
+        );
+        assert_eq!(
+            drifts[0].kind,
+            DriftKind::Changed {
+                expected: "boolean?".to_string(),
+                actual: "string".to_string()
+            }
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    /// Parquet exports are framed by the magic bytes around the metadata, and store dates as days
+    /// since the Unix epoch
+    fn parquet_export() -> Result<()> {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let mut solved = PuzzleStats::new(
+            date,
+            1,
+            Some(SolvedPuzzleStats {
+                solve_time: 300,
+                ..Default::default()
+            }),
+        );
+        solved.title = Some("Title".to_string());
+        let unsolved = PuzzleStats::new(date.succ_opt().unwrap(), 2, None);
+        let mut file = Vec::new();
+        parquet::write_parquet(&mut file, [&solved, &unsolved])?;
+
+        assert!(file.starts_with(b"PAR1") && file.ends_with(b"PAR1"));
+        let footer_len = u32::from_le_bytes(file[file.len() - 8..file.len() - 4].try_into()?);
+        let footer = &file[file.len() - 8 - usize::try_from(footer_len)?..file.len() - 8];
+        let contains = |haystack: &[u8], needle: &[u8]| {
+            haystack
+                .windows(needle.len())
+                .any(|window| window == needle)
+        };
+        for column in schema::Schema::current().columns {
+            assert!(contains(footer, column.name.as_bytes()));
+        }
+        // 2024-01-01 and 2024-01-02
+        let days = [19_723_i32.to_le_bytes(), 19_724_i32.to_le_bytes()].concat();
+        assert!(contains(&file, &days));
+        assert!(contains(&file, b"Title"));
+        Ok(())
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_roundtrip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("data.sqlite");
+        let date = NaiveDate::from_ymd_opt(2024, 1, 7).unwrap();
+        let solved = SolvedPuzzleStats {
+            solve_time: 1800,
+            opened: Some(100),
+            solved: Some(1900),
+            cheated: true,
+        };
+        let mut bonus = PuzzleStats::new(date, 2, None);
+        bonus.publish_type = PublishType::Bonus;
+        bonus.title = Some("Bonus".to_string());
+        {
+            let mut db = Database::new(&path);
+            db.add(PuzzleStats::new(date, 1, Some(solved)));
+            db.add(bonus.clone());
+            db.flush()?;
+        }
+        let db = Database::from_file(&path)?;
+        assert_eq!(db.iter().count(), 2);
+        assert_eq!(
+            db.get(date, PublishType::Daily),
+            Some(&PuzzleStats::new(date, 1, Some(solved)))
+        );
+        assert_eq!(db.get(date, PublishType::Bonus), Some(&bonus));
+        Ok(())
+    }
+
+    #[test]
+    /// A fetch plan should survive serialization and resolve to the same work as planning directly
+    fn fetch_plan() -> Result<()> {
+        use plan::{FetchPlan, WorkItem};
+        use std::num::{NonZeroU32, NonZeroUsize};
+
+        let mut db = Database::from_storage(Box::new(MemoryStorage::default()))?;
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let end = NaiveDate::from_ymd_opt(2024, 1, 10).unwrap();
+        let unsolved = NaiveDate::from_ymd_opt(2024, 1, 5).unwrap();
+        db.add(PuzzleStats::new(unsolved, 5, None));
+
+        let plan = FetchPlan::new(&db, PublishType::Daily, start, end, None, false);
+        // One lookup for the whole range, plus refreshing the unsolved puzzle
+        assert_eq!(plan.len(), 2);
+        assert_eq!(plan.max_requests(), 1 + 10 + 1);
+        assert_eq!(
+            plan.to_string(),
+            "daily: 1 metadata request looking up ids, then at most 10 stats requests for the \
+             puzzles found:\n  2024-01-01 to 2024-01-10\n\
+             daily: 1 stats request for puzzles whose ids are known:\n  2024-01-05\n\
+             2 items, making at most 12 requests"
+        );
+        // Dates of different kinds of puzzles aren't merged into one range
+        let mixed: FetchPlan = [
+            (PublishType::Daily, start),
+            (PublishType::Bonus, start.succ_opt().unwrap()),
+        ]
+        .into_iter()
+        .map(|(publish_type, date)| WorkItem::StatsFetch {
+            publish_type,
+            date,
+            id: 1,
+        })
+        .collect();
+        assert_eq!(
+            mixed.to_string(),
+            "daily: 1 stats request for puzzles whose ids are known:\n  2024-01-01\n\
+             bonus: 1 stats request for puzzles whose ids are known:\n  2024-01-02\n\
+             2 items, making at most 2 requests"
+        );
+        let json = serde_json::to_string(&plan)?;
+        assert_eq!(serde_json::from_str::<FetchPlan>(&json)?, plan);
+
+        let (lookups, refreshes) = plan.clone().resolve(&db);
+        assert_eq!(
+            lookups.iter().flatten().count(),
+            9,
+            "Every day but the one with an id should be looked up"
+        );
+        assert_eq!(
+            refreshes,
+            vec![db.get(unsolved, PublishType::Daily).unwrap().clone()]
+        );
+
+        // The lookup is shortened to fit the budget
+        let capped: Vec<WorkItem> = plan.clone().with_budget(6).collect();
+        assert_eq!(
+            capped,
+            vec![WorkItem::IdLookup {
+                publish_type: PublishType::Daily,
+                start,
+                end: NaiveDate::from_ymd_opt(2024, 1, 5).unwrap(),
+            }]
+        );
+
+        let count = NonZeroUsize::new(2).unwrap();
+        assert_eq!(plan.shard(0, count).len(), 1);
+        assert_eq!(plan.shard(1, count).len(), 1);
+
+        // One of the ten puzzles fetched in past runs failed, so each request is expected to be
+        // retried twice a tenth of the time
+        let journal = NamedTempFile::new()?;
+        std::fs::write(
+            journal.path(),
+            "{\"event\":\"run\",\"unix\":1,\"solved\":6,\"unsolved\":3,\"errors\":1}\n\
+             {\"event\":\"digest\",\"unix\":2,\"week_start\":\"2024-01-01\"}\n",
+        )?;
+        let history = journal::fetch_history(journal.path())?;
+        assert_eq!((history.runs, history.puzzles, history.errors), (1, 10, 1));
+        let simulation = plan.simulate(NonZeroU32::new(4).unwrap(), history, 2);
+        let requests: Vec<usize> = simulation.phases.iter().map(|p| p.requests).collect();
+        assert_eq!(requests, [2, 11, 1]);
+        assert!((simulation.total_requests() - 16.4).abs() < 1e-9);
+        assert_eq!(simulation.duration().as_millis(), 4100);
+
+        // Once the unsolved puzzle is refreshed, only the lookup is left to resume, however many
+        // of its dates were fetched
+        let mut remaining = plan.clone();
+        remaining.complete((unsolved, PublishType::Daily));
+        remaining.complete((start, PublishType::Daily));
+        let checkpoint = NamedTempFile::new()?;
+        remaining.save(checkpoint.path())?;
+        let resumed = FetchPlan::load(checkpoint.path())?;
+        assert_eq!(resumed.len(), 1);
+        assert!(matches!(
+            resumed.into_iter().next(),
+            Some(WorkItem::IdLookup { .. })
+        ));
+
+        // Forcing a refresh refreshes complete records too
+        let solved = NaiveDate::from_ymd_opt(2024, 1, 6).unwrap();
+        db.add(PuzzleStats::new(
+            solved,
+            6,
+            Some(SolvedPuzzleStats::default()),
+        ));
+        assert_eq!(
+            FetchPlan::new(&db, PublishType::Daily, start, end, None, false).len(),
+            2
+        );
+        assert_eq!(
+            FetchPlan::new(&db, PublishType::Daily, start, end, None, true).len(),
+            3
+        );
+        Ok(())
+    }
+
+    #[test]
+    /// The database file should be backed up once per session before it's overwritten, keeping
+    /// only the configured number of backups
+    fn backups_rotate() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("data.csv");
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let open = || Database::from_file(&path).map(|db| db.with_backups(2));
+
+        // Nothing to back up before the file exists
+        let mut db = Database::new(&path).with_backups(2);
+        db.add(PuzzleStats::empty(date));
+        db.flush()?;
+        drop(db);
+        assert!(!backup::backup_path(&path, 1).exists());
+        let first = std::fs::read(&path)?;
+
+        let mut db = open()?;
+        db.add(PuzzleStats::empty(date.succ_opt().unwrap()));
+        db.flush()?;
+        db.add(PuzzleStats::empty(date.pred_opt().unwrap()));
+        db.flush()?;
+        drop(db);
+        assert_eq!(std::fs::read(backup::backup_path(&path, 1))?, first);
+        assert!(!backup::backup_path(&path, 2).exists());
+
+        // Unchanged databases aren't written, so they aren't backed up either
+        drop(open()?);
+        assert_eq!(std::fs::read(backup::backup_path(&path, 1))?, first);
+
+        let second = std::fs::read(&path)?;
+        for days in 2..4 {
+            let mut db = open()?;
+            db.add(PuzzleStats::empty(date + Duration::days(days)));
+            db.close()?;
+        }
+        assert_eq!(std::fs::read(backup::backup_path(&path, 2))?, second);
+        assert!(!backup::backup_path(&path, 3).exists());
+        Ok(())
+    }
+
+    #[test]
+    /// CSVs written before the `cheated` column existed, or with rows that stop short of the
+    /// header, load with defaults and are written back with the current columns
+    fn schema_upgrade() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("data.csv");
+        std::fs::write(
+            &path,
+            "date,puzzle_id,solve_time_secs,publish_type,fetch_failed\n\
+             2024-01-01,1,300\n\
+             2024-01-02,2,,bonus,true\n",
+        )?;
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+
+        let mut db = Database::from_file(&path)?;
+        let daily = db.get(date, PublishType::Daily).unwrap();
+        assert_eq!(daily.weekday, Weekday::Mon);
+        assert_eq!(daily.solve_time_secs, Some(300));
+        assert_eq!(daily.cheated, None);
+        let bonus = db
+            .get(date.succ_opt().unwrap(), PublishType::Bonus)
+            .unwrap();
+        assert_eq!(bonus.fetch_failed, Some(true));
+        // Upgraded files are written back with the next change
+        let mut bonus = bonus.clone();
+        bonus.cheated = Some(false);
+        db.add(bonus);
+        db.close()?;
+
+        let columns: Vec<&str> = schema::Schema::current()
+            .columns
+            .iter()
+            .map(|column| column.name)
+            .collect();
+        let written = std::fs::read_to_string(&path)?;
+        assert_eq!(written.lines().next(), Some(columns.join(",").as_str()));
+        assert_eq!(database::load_records(&path)?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    /// Scanning rows should find duplicates, bad weekdays, and impossible values, fixing what can
+    /// be fixed
+    fn repair_rows() -> Result<()> {
+        use repair::Problem;
+        let input = "date,weekday,solve_time_secs,opened_unix,solved_unix\n\
+                     2024-01-01,,300,,\n\
+                     2024-01-02,Fri,0,,\n\
+                     2024-01-03,Wed,300,200,100\n\
+                     2024-01-01,Mon,,,\n";
+        let scanned = repair::scan(
+            input.as_bytes(),
+            database::DuplicatePolicy::KeepMostComplete,
+            false,
+        )?;
+        let problems: Vec<(u64, &Problem, bool)> = scanned
+            .findings
+            .iter()
+            .map(|f| (f.line, &f.problem, f.fixed))
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                (2, &Problem::MissingWeekday, true),
+                (
+                    3,
+                    &Problem::WrongWeekday {
+                        found: Weekday::Fri
+                    },
+                    true
+                ),
+                (3, &Problem::ZeroSolveTime, false),
+                (4, &Problem::SolvedBeforeOpened, false),
+                (5, &Problem::Duplicate { first_line: 2 }, true),
+            ]
+        );
+        let monday = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        assert_eq!(scanned.records.len(), 3);
+        assert_eq!(
+            scanned.records[&(monday, PublishType::Daily)].solve_time_secs,
+            Some(300)
+        );
+        assert_eq!(
+            scanned.records[&(monday.succ_opt().unwrap(), PublishType::Daily)].weekday,
+            Weekday::Tue
+        );
+
+        let cleared = repair::scan(input.as_bytes(), database::DuplicatePolicy::KeepLast, true)?;
+        assert!(cleared.findings.iter().all(|f| f.fixed));
+        assert!(cleared
+            .records
+            .values()
+            .all(|r| r.solve_time_secs != Some(0)));
+        assert_eq!(
+            cleared.records[&(monday, PublishType::Daily)].solve_time_secs,
+            None
+        );
+        Ok(())
+    }
+
+    #[test]
+    /// Databases written with another delimiter should be read back with it
+    fn csv_dialects() -> Result<()> {
+        use database::{CsvDialect, QuoteStyle};
+        let mut record = PuzzleStats::empty(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
+        record.title = Some("Semi; colon".to_string());
+        for (delimiter, quote_style) in [("tab", QuoteStyle::Always), (";", QuoteStyle::Necessary)]
+        {
+            let dialect = CsvDialect {
+                delimiter: database::parse_delimiter(delimiter)?,
+                quote_style,
+            };
+            let mut written = Vec::new();
+            database::write_csv_as(&mut written, &[&record], dialect)?;
+            let records: Vec<PuzzleStats> =
+                database::CsvRecords::new(written.as_slice())?.collect::<Result<_>>()?;
+            assert_eq!(records, [record.clone()]);
+        }
+        assert!(database::parse_delimiter("\"").is_err());
+        assert!(database::parse_delimiter(",,").is_err());
+        Ok(())
+    }
+
+    #[test]
+    /// Streamed records are upgraded row by row, with duplicates and malformed rows passed through
+    fn streamed_records() -> Result<()> {
+        let input = "date,solve_time_secs\n2024-01-01,300\n2024-01-01,200\nnot a date,1\n";
+        let records: Vec<Result<PuzzleStats>> =
+            database::CsvRecords::new(input.as_bytes())?.collect();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[1].as_ref().unwrap().solve_time_secs, Some(200));
+        assert_eq!(records[1].as_ref().unwrap().weekday, Weekday::Mon);
+        assert!(records[2].is_err());
+        Ok(())
+    }
+
+    #[test]
+    /// The same records always produce the same CSV, whatever order they're in
+    fn canonical_csv() -> Result<()> {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let mut bonus = PuzzleStats::empty(date);
+        bonus.publish_type = PublishType::Bonus;
+        let records = [
+            PuzzleStats::empty(date.succ_opt().unwrap()),
+            bonus,
+            PuzzleStats::new(date, 1, None),
+        ];
+
+        let mut forward = Vec::new();
+        database::write_csv(&mut forward, &records.iter().collect::<Vec<_>>())?;
+        let mut backward = Vec::new();
+        database::write_csv(&mut backward, &records.iter().rev().collect::<Vec<_>>())?;
+        assert_eq!(forward, backward);
+        let csv = String::from_utf8(forward)?;
+        let dates: Vec<&str> = csv.lines().skip(1).map(|line| &line[..10]).collect();
+        assert_eq!(dates, ["2024-01-01", "2024-01-01", "2024-01-02"]);
+        assert!(csv.lines().nth(2).unwrap().contains(",bonus,"));
+        assert!(csv.ends_with('\n') && !csv.contains('\r'));
+
+        let mut empty = Vec::new();
+        database::write_csv(&mut empty, &[])?;
+        assert_eq!(
+            String::from_utf8(empty)?,
+            csv.lines().next().unwrap().to_owned() + "\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    /// Duplicate records in a CSV database should be resolved by the chosen policy
+    fn duplicate_policy() -> Result<()> {
+        use database::DuplicatePolicy;
+
+        let file = NamedTempFile::new()?;
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let complete = PuzzleStats::new(date, 1, Some(SolvedPuzzleStats::default()));
+        let sparse = PuzzleStats::empty(date);
+        let mut writer = csv::Writer::from_path(file.path())?;
+        writer.serialize(&complete)?;
+        writer.serialize(&sparse)?;
+        writer.flush()?;
+
+        let load = |policy| database::load_records_with_policy(file.path(), policy);
+        assert_eq!(load(DuplicatePolicy::KeepFirst)?[&complete.key()], complete);
+        assert_eq!(load(DuplicatePolicy::KeepLast)?[&complete.key()], sparse);
+        assert_eq!(
+            load(DuplicatePolicy::KeepMostComplete)?[&complete.key()],
+            complete
+        );
+        assert!(load(DuplicatePolicy::Error).is_err());
+        Ok(())
+    }
+
+    #[test]
+    /// Records should survive a round trip through JSON and JSON Lines with every column intact
+    fn json_roundtrip() -> Result<()> {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 6).unwrap();
+        let mut full = PuzzleStats::new(
+            date,
+            21000,
+            Some(SolvedPuzzleStats {
+                solve_time: 1234,
+                cheated: false,
+                ..Default::default()
+            }),
+        );
+        full.opened_unix = Some(1_704_520_000);
+        full.solved_unix = Some(1_704_521_234);
+        full.solved_on_pub_day = Some(true);
+        full.author = Some("Author, with a comma".to_string());
+        full.editor = Some("Editor".to_string());
+        full.title = Some("\"Quoted\" title".to_string());
+        full.percent_filled = Some(100);
+        full.gold_star = Some(false);
+        full.percent_rank = Some(42);
+        full.fetch_failed = Some(false);
+        let mut bonus = PuzzleStats::empty(date);
+        bonus.publish_type = PublishType::Bonus;
+
+        let file = NamedTempFile::new()?;
+        let path = file.into_temp_path().to_path_buf();
+        let mut db = Database::from_storage(Box::new(MemoryStorage::default()))?;
+        db.add(full.clone());
+        db.add(bonus.clone());
+
+        let json = db.to_json()?;
+        let jsonl = [&full, &bonus]
+            .iter()
+            .map(serde_json::to_string)
+            .collect::<serde_json::Result<Vec<_>>>()?
+            .join("\n");
+        for input in [json, jsonl] {
+            let restored = Database::from_json(&input, &path)?;
+            assert_eq!(restored.get(date, PublishType::Daily), Some(&full));
+            assert_eq!(restored.get(date, PublishType::Bonus), Some(&bonus));
+            assert_eq!(restored.iter().count(), 2);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn import_validation() -> Result<()> {
+        let input = "Date,Time,Assisted,Notes\n\
+                     2024-01-01,5:30,no,\n\
+                     01/02/2024,1:02:03,yes,long one\n\
+                     2024-01-03,abc,no,\n\
+                     not a date,100,no,\n\
+                     2024-01-01,300,,retyped\n";
+        let import = import::read(input.as_bytes())?;
+        let lines: Vec<u64> = import.issues.iter().filter_map(|i| i.line).collect();
+        assert_eq!(lines, [2, 4, 5]);
+        assert_eq!(import.records.len(), 2);
+        assert_eq!(import.records[0].solve_time_secs, Some(300));
+        assert_eq!(import.records[1].solve_time_secs, Some(3723));
+        assert_eq!(import.records[1].cheated, Some(true));
+        Ok(())
+    }
+
+    #[test]
+    /// Weekday aggregates skip assisted and untimed solves and break ties by date
+    fn weekday_aggregates() {
+        use analytics::{TimedSolve, WeekdayAggregates};
+
+        // Mondays in January 2024 are the 1st, 8th, 15th, 22nd, and 29th
+        let date = |d| NaiveDate::from_ymd_opt(2024, 1, d).unwrap();
+        let solve = |d, solve_time, cheated| {
+            PuzzleStats::new(
+                date(d),
+                1,
+                Some(SolvedPuzzleStats {
+                    solve_time,
+                    cheated,
+                    ..Default::default()
+                }),
+            )
+        };
+        let mut untimed = PuzzleStats::new(date(29), 1, None);
+        untimed.cheated = Some(false);
+        let records = [
+            solve(1, 400, false),
+            solve(8, 200, false),
+            solve(15, 400, false),
+            solve(22, 100, true),
+            solve(2, 600, false),
+            untimed,
+        ];
+        let aggregates = WeekdayAggregates::new(&records);
+        let monday = aggregates.weekday(Weekday::Mon).unwrap();
+        assert_eq!(monday.count, 3);
+        assert_eq!(monday.mean_secs, Some(1000.0 / 3.0));
+        assert_eq!(monday.median_secs, Some(400.0));
+        let best = TimedSolve {
+            date: date(8),
+            solve_time_secs: 200,
+        };
+        let worst = TimedSolve {
+            date: date(1),
+            solve_time_secs: 400,
+        };
+        assert_eq!((monday.best, monday.worst), (Some(best), Some(worst)));
+        let weekdays: Vec<Weekday> = aggregates.iter().map(|(day, _)| day).collect();
+        assert_eq!(weekdays, [Weekday::Mon, Weekday::Tue]);
+
+        let even = WeekdayAggregates::new(&records[..2]);
+        assert_eq!(even.weekday(Weekday::Mon).unwrap().median_secs, Some(300.0));
+        assert!(even.weekday(Weekday::Tue).is_none());
+    }
+
+    #[test]
+    /// Goal progress counts matching solved puzzles and lists the remaining dates in order
+    fn goal_progress() {
+        let date = |d| NaiveDate::from_ymd_opt(2024, 1, d).unwrap();
+        let solve = |d, cheated| {
+            PuzzleStats::new(
+                date(d),
+                1,
+                Some(SolvedPuzzleStats {
+                    solve_time: 300,
+                    cheated,
+                    ..Default::default()
+                }),
+            )
+        };
+        // Mondays in January 2024 are the 1st, 8th, 15th, 22nd, and 29th
+        let records = [solve(1, false), solve(8, true), solve(9, false)];
+        let mut goal = goals::Goal {
+            name: "mondays".to_string(),
+            weekdays: vec![Weekday::Mon],
+            start: date(1),
+            end: None,
+            unassisted: false,
+        };
+        let progress = goal.progress(&records, date(22));
+        assert_eq!((progress.solved, progress.total()), (2, 4));
+        assert_eq!(progress.remaining, [date(15), date(22)]);
+
+        goal.unassisted = true;
+        goal.end = Some(date(10));
+        let progress = goal.progress(&records, date(31));
+        assert_eq!(progress.remaining, [date(8)]);
+    }
+
+    #[test]
+    /// Imports are detected by path and header, and every imported record has a source
+    fn import_formats() -> Result<()> {
+        use import::{ColumnMapping, Format};
+
+        let dir = tempfile::tempdir()?;
+        // A solved 2x2 grid with one revealed square and 95 seconds on the timer
+        let mut puz = vec![0_u8; 0x34];
+        puz[0x02..0x0E].copy_from_slice(b"ACROSS&DOWN\0");
+        puz[0x2C] = 2;
+        puz[0x2D] = 2;
+        puz[0x2E] = 4;
+        puz.extend_from_slice(b"ABCDabcd");
+        puz.extend_from_slice(b"Title\0Author\0\0a\0b\0c\0d\0\0");
+        puz.extend_from_slice(b"LTIM\x04\0\0\095,1\0");
+        puz.extend_from_slice(b"GEXT\x04\0\0\0\0\x40\0\0\0");
+        std::fs::write(dir.path().join("nyt-20240102.puz"), &puz)?;
+        std::fs::write(dir.path().join("notes.txt"), "not a puzzle")?;
+        std::fs::write(dir.path().join("undated.puz"), &puz)?;
+
+        let (format, puz_import) = import::read_path(dir.path(), None, &[])?;
+        assert_eq!(format, Format::Puz);
+        assert_eq!(puz_import.issues.len(), 1);
+        let record = &puz_import.records[0];
+        assert_eq!(record.date, NaiveDate::from_ymd_opt(2024, 1, 2).unwrap());
+        assert_eq!(record.solve_time_secs, Some(95));
+        assert_eq!(record.cheated, Some(true));
+        assert_eq!(record.author.as_deref(), Some("Author"));
+        assert_eq!(record.source.as_deref(), Some("puz:nyt-20240102.puz"));
+
+        let tracker = dir.path().join("log.csv");
+        std::fs::write(&tracker, "Day,Duration\n2024-01-03,4:00\n")?;
+        let map: [ColumnMapping; 2] = [
+            "date=Day".parse().unwrap(),
+            "solve-time=Duration".parse().unwrap(),
+        ];
+        let (format, tracker_import) = import::read_path(&tracker, None, &map)?;
+        assert_eq!(format, Format::Tracker);
+        assert_eq!(tracker_import.records[0].solve_time_secs, Some(240));
+        assert_eq!(
+            tracker_import.records[0].source.as_deref(),
+            Some("tracker:log.csv")
+        );
+
+        let db_path = dir.path().join("old.csv");
+        let mut db = Database::new(&db_path);
+        db.merge(tracker_import.records, merge::Precedence::default());
+        db.add(PuzzleStats::new(
+            NaiveDate::from_ymd_opt(2024, 1, 4).unwrap(),
+            1,
+            None,
+        ));
+        db.flush()?;
+        assert_eq!(import::detect(&db_path)?, Format::Database);
+        let (_, db_import) = import::read_path(&db_path, None, &[])?;
+        let sources: Vec<&str> = db_import
+            .records
+            .iter()
+            .filter_map(|r| r.source.as_deref())
+            .collect();
+        assert_eq!(sources, ["tracker:log.csv", "database:old.csv"]);
+        Ok(())
+    }
+
+    #[test]
+    fn cookie_file() -> Result<()> {
+        use std::io::Write;
+
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "# Netscape HTTP Cookie File")?;
+        writeln!(file, ".example.com\tTRUE\t/\tFALSE\t0\tNYT-S\twrong-site")?;
+        writeln!(
+            file,
+            "#HttpOnly_.nytimes.com\tTRUE\t/\tTRUE\t0\tNYT-S\tsecret"
+        )?;
+        let token = SubscriptionToken::from_cookie_file(file.path())?;
+        assert!(matches!(token, SubscriptionToken::Cookie(value) if value == "secret"));
+
+        let empty = NamedTempFile::new()?;
+        assert!(SubscriptionToken::from_cookie_file(empty.path()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn merge_precedence() {
+        use merge::Precedence;
+
+        let date = NaiveDate::from_ymd_opt(2021, 3, 1).unwrap();
+        let solve = |solve_time, solved, cheated| SolvedPuzzleStats {
+            solve_time,
+            opened: None,
+            solved: Some(solved),
+            cheated,
+        };
+        let unsolved = PuzzleStats::new(date, 1, None);
+        let slow_first = PuzzleStats::new(date, 1, Some(solve(600, 100, false)));
+        let fast_later = PuzzleStats::new(date, 1, Some(solve(300, 200, false)));
+        let fast_assisted = PuzzleStats::new(date, 1, Some(solve(60, 50, true)));
+
+        for precedence in [Precedence::Primary, Precedence::Other, Precedence::Fastest] {
+            assert_eq!(
+                precedence.choose(unsolved.clone(), fast_later.clone()),
+                fast_later
+            );
+            assert_eq!(
+                precedence.choose(fast_later.clone(), unsolved.clone()),
+                fast_later
+            );
+        }
+        assert_eq!(
+            Precedence::Primary.choose(slow_first.clone(), fast_later.clone()),
+            slow_first
+        );
+        assert_eq!(
+            Precedence::Other.choose(slow_first.clone(), fast_later.clone()),
+            fast_later
+        );
+        assert_eq!(
+            Precedence::Earliest.choose(fast_later.clone(), slow_first.clone()),
+            slow_first
+        );
+        assert_eq!(
+            Precedence::Fastest.choose(slow_first.clone(), fast_later.clone()),
+            fast_later
+        );
+        assert_eq!(
+            Precedence::Fastest.choose(slow_first.clone(), fast_assisted.clone()),
+            slow_first
+        );
+        let mut detailed = fast_later.clone();
+        detailed.title = Some("Title".to_string());
+        assert_eq!(
+            Precedence::MostComplete.choose(slow_first.clone(), detailed.clone()),
+            detailed
+        );
+
+        // Differing solves of the same puzzle are reported, but unsolved records never conflict
+        let conflict = merge::Conflict::between(&slow_first, &fast_later).unwrap();
+        let fields: Vec<&str> = conflict.fields.iter().map(|f| f.field.as_str()).collect();
+        assert_eq!(fields, ["solve_time_secs", "solved_unix"]);
+        assert!(merge::Conflict::between(&unsolved, &fast_later).is_none());
+        assert!(merge::Conflict::between(&fast_later, &detailed).is_none());
+    }
+
+    #[test]
+    fn solved_on_pub_day() {
+        use timezone::HomeTimezone;
+
+        // Released at 10pm EST on Sunday 2021-02-28, i.e. 03:00 UTC on 2021-03-01
+        let monday = NaiveDate::from_ymd_opt(2021, 3, 1).unwrap();
+        let release = 1_614_567_600;
+        let eastern: HomeTimezone = "-05:00".parse().unwrap();
+        let utc: HomeTimezone = "utc".parse().unwrap();
+
+        assert!(!timezone::solved_on_pub_day(monday, release - 1, eastern));
+        assert!(timezone::solved_on_pub_day(monday, release, eastern));
+        // 11pm Eastern on Monday is still Monday at home, but already Tuesday in UTC
+        let late_monday = release + 24 * 3600;
+        assert!(timezone::solved_on_pub_day(monday, late_monday, eastern));
+        assert!(!timezone::solved_on_pub_day(monday, late_monday, utc));
+    }
+
+    #[test]
+    /// Solves should only be ranked against earlier unassisted solves of the same weekday
+    fn percent_ranks() {
+        let solve = |date: &str, solve_time, solved, cheated| {
+            PuzzleStats::new(
+                date.parse().unwrap(),
+                1,
+                Some(SolvedPuzzleStats {
+                    solve_time,
+                    opened: None,
+                    solved: Some(solved),
+                    cheated,
+                }),
+            )
+        };
+        let records = [
+            solve("2024-01-01", 600, 100, false),
+            solve("2024-01-08", 400, 200, false),
+            solve("2024-01-15", 300, 300, true),
+            // Solved last, so it's ranked against both earlier Mondays
+            solve("2023-12-25", 500, 400, false),
+            solve("2024-01-02", 100, 500, false),
+        ];
+        let ranks = analytics::percent_ranks(&records);
+        let rank = |date: &str| {
+            ranks
+                .get(&(date.parse().unwrap(), PublishType::Daily))
+                .copied()
+        };
+        assert_eq!(rank("2024-01-01"), None);
+        assert_eq!(rank("2024-01-08"), Some(100));
+        assert_eq!(rank("2024-01-15"), None);
+        assert_eq!(rank("2023-12-25"), Some(50));
+        assert_eq!(rank("2024-01-02"), None);
+    }
+
+    #[test]
+    /// The published schema should list the columns that are actually written, in order
+    fn schema_matches_csv() -> Result<()> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.serialize(PuzzleStats::empty(
+            NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+        ))?;
+        let csv = String::from_utf8(writer.into_inner()?)?;
+        let header: Vec<&str> = csv.lines().next().unwrap().split(',').collect();
+        let columns: Vec<&str> = schema::Schema::current()
+            .columns
+            .iter()
+            .map(|c| c.name)
+            .collect();
+        assert_eq!(header, columns);
+
+        let schema = schema::Schema::current();
+        let publish_types = schema
+            .columns
+            .iter()
+            .find(|c| c.name == "publish_type")
+            .unwrap();
+        let variants: Vec<&str> = <PublishType as clap::ValueEnum>::value_variants()
+            .iter()
+            .map(|t| t.as_str())
+            .collect();
+        assert_eq!(publish_types.values, variants);
+        Ok(())
+    }
+
+    #[test]
+    fn watchdog_alerts() {
+        use chrono::{TimeZone, Utc};
+        use logger::RunSummary;
+        use watchdog::Watchdog;
+
+        let start = Utc.with_ymd_and_hms(2021, 3, 1, 0, 0, 0).unwrap();
+        let mut watchdog = Watchdog::new(Some(Duration::days(3)), start);
+        let summary = |current_streak| RunSummary {
+            current_streak,
+            ..Default::default()
+        };
+
+        assert!(watchdog.on_success(&summary(5), start).is_none());
+        assert!(watchdog.on_failure(start + Duration::days(2)).is_none());
+        assert!(watchdog.on_failure(start + Duration::days(3)).is_some());
+        // Only alert once per outage
+        assert!(watchdog.on_failure(start + Duration::days(4)).is_none());
+        assert!(watchdog
+            .on_success(&summary(0), start + Duration::days(5))
+            .is_some());
+        assert!(watchdog
+            .on_success(&summary(0), start + Duration::days(6))
+            .is_none());
+    }
+
+    /// Serve API requests on a local port, answering each with the status and body returned by
+    /// `respond`, and build a client that sends its requests there with the given credentials
+    async fn mock_api_with_auth<F>(
+        provider: std::sync::Arc<dyn auth::AuthProvider>,
+        mut respond: F,
+    ) -> Result<(api_client::RateLimitedClient, tokio::task::JoinHandle<()>)>
+    where
+        F: FnMut(&str) -> (u16, String) + Send + 'static,
+    {
+        use core::num::NonZeroU32;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 4096];
+                let n = stream.read(&mut buf).await.unwrap();
+                let (status, body) = respond(&String::from_utf8_lossy(&buf[..n]));
+                let response = format!(
+                    "HTTP/1.1 {status} Mock\r\nContent-Length: {}\r\n\
+                     Connection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        let client = api_client::RateLimitedClient::with_auth_provider(
+            provider,
+            NonZeroU32::new(1000).unwrap(),
+            None,
+        )?
+        .with_base_url(format!("http://{addr}"));
+        Ok((client, server))
+    }
+
+    /// `mock_api_with_auth` for a client with a fixed subscription token
+    async fn mock_api<F>(
+        respond: F,
+    ) -> Result<(api_client::RateLimitedClient, tokio::task::JoinHandle<()>)>
+    where
+        F: FnMut(&str) -> (u16, String) + Send + 'static,
+    {
+        let token = SubscriptionToken::Cookie("token".to_string());
+        mock_api_with_auth(std::sync::Arc::new(auth::StaticToken(token)), respond).await
+    }
+
+    #[tokio::test]
+    async fn mock_server() -> Result<()> {
+        let (requests, received) = std::sync::mpsc::channel();
+        let (client, server) = mock_api(move |request| {
+            requests.send(request.to_string()).unwrap();
+            let body =
+                r#"{"calcs":{"solved":true,"secondsSpentSolving":321},"firsts":{"solved":1}}"#;
+            (200, body.to_string())
+        })
+        .await?;
+        let stats =
+            api_client::get_solve_stats(&client, 42, NaiveDate::from_ymd_opt(2024, 1, 1).unwrap())
+                .await?
+                .unwrap();
+        assert_eq!(stats.solve_time, 321);
+        assert!(!stats.cheated);
+
+        let request = received.try_recv()?;
+        assert!(request.starts_with("GET /v6/game/42.json"));
+        assert!(request.contains("NYT-S=token"));
+        server.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    /// A single puzzle is looked up and fetched, keeping track of it being started but unsolved
+    async fn fetch_one() -> Result<()> {
+        let (client, server) = mock_api(|request| {
+            let body = if request.contains("date_start") {
+                r#"{"results":[{"print_date":"2024-03-09","puzzle_id":42,"percent_filled":40}]}"#
+            } else {
+                r#"{"calcs":{"solved":false},"firsts":{"opened":1709990000}}"#
+            };
+            (200, body.to_string())
+        })
+        .await?;
+        let date = NaiveDate::from_ymd_opt(2024, 3, 9).unwrap();
+        let record = search::fetch_one(&client, PublishType::Daily, date).await?;
+        assert_eq!(record.puzzle_id, Some(42));
+        assert_eq!(record.opened_unix, Some(1_709_990_000));
+        assert!(record.is_in_progress());
+        assert_eq!(client.n_requests(), 2);
+        server.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    /// A failed lookup comes back as the error of the request, not as the puzzle being missing
+    async fn fetch_one_lookup_failed() -> Result<()> {
+        let (client, server) = mock_api(|_| (401, String::new())).await?;
+        let date = NaiveDate::from_ymd_opt(2024, 3, 9).unwrap();
+        let error = search::fetch_one(&client, PublishType::Daily, date)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<api_client::ApiError>(),
+            Some(api_client::ApiError::Unauthorized(_))
+        ));
+        assert!(error.to_string().contains("2024-03-09"));
+        server.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    /// Every puzzle of the requested ranges comes through the stream
+    async fn streamed_stats() -> Result<()> {
+        use futures::StreamExt;
+
+        let (client, server) = mock_api(|request| {
+            let body = if request.contains("date_start") {
+                r#"{"results":[{"print_date":"2024-03-09","puzzle_id":9},
+                               {"print_date":"2024-03-10","puzzle_id":10}]}"#
+            } else {
+                r#"{"calcs":{"solved":true,"secondsSpentSolving":300},"firsts":{"solved":1}}"#
+            };
+            (200, body.to_string())
+        })
+        .await?;
+        let start = NaiveDate::from_ymd_opt(2024, 3, 9).unwrap();
+        let end = NaiveDate::from_ymd_opt(2024, 3, 11).unwrap();
+        let limit = std::sync::Arc::new(tokio::sync::Semaphore::new(4));
+        let results: Vec<Result<PuzzleStats>> = search::stream_stats(
+            client,
+            PublishType::Daily,
+            [(start, end)],
+            search::IdLookupPolicy::default(),
+            limit,
+        )
+        .collect()
+        .await;
+        // The puzzle from the 11th isn't listed, so its id can't be found
+        let errors: Vec<String> = results
+            .iter()
+            .filter_map(|r| r.as_ref().err().map(ToString::to_string))
+            .collect();
+        assert_eq!(errors, ["No daily puzzle found for 2024-03-11"]);
+        let mut solved: Vec<(NaiveDate, Option<u32>)> = results
+            .into_iter()
+            .filter_map(Result::ok)
+            .map(|r| (r.date, r.solve_time_secs))
+            .collect();
+        solved.sort_unstable();
+        assert_eq!(
+            solved,
+            [(start, Some(300)), (start.succ_opt().unwrap(), Some(300))]
+        );
+        server.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    /// Every puzzle whose id couldn't be looked up comes through the stream as an error naming it
+    async fn streamed_lookup_failure() -> Result<()> {
+        use api_client::{ApiError, RetryPolicy};
+        use futures::StreamExt;
+        use std::sync::Arc;
+
+        let (client, server) = mock_api(|_| (500, String::new())).await?;
+        let client = client.with_retry_policy(RetryPolicy::none());
+        let start = NaiveDate::from_ymd_opt(2024, 3, 9).unwrap();
+        let end = NaiveDate::from_ymd_opt(2024, 3, 11).unwrap();
+        let results: Vec<Result<PuzzleStats>> = search::stream_stats(
+            client,
+            PublishType::Daily,
+            [(start, end)],
+            search::IdLookupPolicy::none(),
+            Arc::new(tokio::sync::Semaphore::new(4)),
+        )
+        .collect()
+        .await;
+        let mut errors: Vec<String> = results
+            .iter()
+            .map(|r| {
+                let error = r.as_ref().unwrap_err();
+                let source = error.downcast_ref::<Arc<ApiError>>().unwrap();
+                assert!(matches!(**source, ApiError::Status(_)));
+                error.to_string()
+            })
+            .collect();
+        errors.sort_unstable();
+        assert_eq!(
+            errors,
+            ["09", "10", "11"].map(|day| format!(
+                "Failed to look up the id of the daily puzzle from 2024-03-{day}"
+            ))
+        );
+        server.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    /// Id lookups that keep failing are split in half until the halves succeed
+    async fn id_lookup_splits() -> Result<()> {
+        use api_client::RetryPolicy;
+        use search::IdLookupPolicy;
+
+        // Fails lookups of ten days or more, and lists a puzzle for every day of shorter ones
+        let (client, server) = mock_api(|request| {
+            let param = |name: &str| -> NaiveDate {
+                let start = request.find(name).unwrap() + name.len() + 1;
+                request[start..start + 10].parse().unwrap()
+            };
+            let (start, end) = (param("date_start"), param("date_end"));
+            if (end - start).num_days() >= 9 {
+                return (500, String::new());
+            }
+            let results: Vec<String> = start
+                .iter_days()
+                .take_while(|date| *date <= end)
+                .map(|date| {
+                    format!(
+                        r#"{{"print_date":"{date}","puzzle_id":{}}}"#,
+                        date.ordinal()
+                    )
+                })
+                .collect();
+            (200, format!(r#"{{"results":[{}]}}"#, results.join(",")))
+        })
+        .await?;
+        let client = client.with_retry_policy(RetryPolicy::none());
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let end = NaiveDate::from_ymd_opt(2024, 1, 20).unwrap();
+        let policy = |max_splits| IdLookupPolicy {
+            max_attempts: 2,
+            base_delay: std::time::Duration::from_millis(1),
+            max_splits,
+        };
+
+        let (info, failed) =
+            search::lookup_ids(&client, PublishType::Daily, start, end, policy(2)).await;
+        assert_eq!(info.len(), 20);
+        assert_eq!(info[&end].puzzle_id, 20);
+        assert!(failed.is_empty());
+        // Two attempts at 20 days, then at each 10-day half, then one request per 5-day quarter
+        assert_eq!(client.n_requests(), 2 + 4 + 4);
+
+        let (info, failed) =
+            search::lookup_ids(&client, PublishType::Daily, start, end, policy(1)).await;
+        assert!(info.is_empty());
+        let middle = NaiveDate::from_ymd_opt(2024, 1, 10).unwrap();
+        let failed: Vec<(NaiveDate, NaiveDate)> = failed
+            .iter()
+            .map(|(start, end, _)| (*start, *end))
+            .collect();
+        assert_eq!(failed, [(start, middle), (middle.succ_opt().unwrap(), end)]);
+        server.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refresh_rejected_token() -> Result<()> {
+        use auth::CookieFile;
+
+        let cookie_line = |token: &str| format!(".nytimes.com\tTRUE\t/\tTRUE\t0\tNYT-S\t{token}\n");
+        let file = NamedTempFile::new()?;
+        std::fs::write(file.path(), cookie_line("expired"))?;
+        let path = file.path().to_path_buf();
+
+        let (requests, received) = std::sync::mpsc::channel();
+        let provider = std::sync::Arc::new(CookieFile(file.path().to_path_buf()));
+        let (client, server) = mock_api_with_auth(provider, move |request| {
+            requests.send(request.to_string()).unwrap();
+            if request.contains("NYT-S=renewed") {
+                let body = r#"{"calcs":{"solved":true,"secondsSpentSolving":321}}"#;
+                (200, body.to_string())
+            } else {
+                // The user logs in again while the run is in progress
+                std::fs::write(&path, cookie_line("renewed")).unwrap();
+                (401, String::new())
+            }
+        })
+        .await?;
+        let stats =
+            api_client::get_solve_stats(&client, 42, NaiveDate::from_ymd_opt(2024, 1, 1).unwrap())
+                .await?
+                .unwrap();
+        assert_eq!(stats.solve_time, 321);
+        assert!(!client.auth_circuit_open());
+
+        let requests: Vec<String> = received.try_iter().collect();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].contains("NYT-S=expired"));
+        assert!(requests[1].contains("NYT-S=renewed"));
+        server.abort();
+        Ok(())
+    }
 }
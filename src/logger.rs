@@ -12,39 +12,330 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::api_client::ApiError;
 use crate::database::Database;
-use crate::PuzzleStats;
+use crate::notify::Notifiers;
+use crate::plan::FetchPlan;
+use crate::run_status::StatusFile;
+use crate::timezone::HomeTimezone;
+use crate::{PuzzleStats, RecordKey};
 use anyhow::Result;
+use chrono::naive::NaiveDate;
 use indicatif::ProgressBar;
-use tokio::sync::mpsc;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
 
-#[derive(Debug, Clone, Copy, Hash, PartialEq)]
+/// Only solves of puzzles published within this many days trigger a notification, so that
+/// backfilling the archive doesn't send one per puzzle
+const NOTIFY_SOLVE_DAYS: i64 = 7;
+
+/// Periodic saving of a run's progress: the database, and the part of the run's plan that's left
+/// to do, so that an interrupted run can be resumed from where it stopped with
+/// `FetchPlan::load`. The plan is removed once the run finishes.
+#[derive(Debug)]
+pub struct Checkpoint {
+    /// Path the remaining plan is saved to
+    path: PathBuf,
+    remaining: FetchPlan,
+    /// Number of records to add before saving
+    every: NonZeroUsize,
+    /// Time after which any records added are saved
+    interval: Duration,
+    unsaved: usize,
+    last_saved: Instant,
+}
+
+impl Checkpoint {
+    #[must_use]
+    pub fn new(path: PathBuf, plan: FetchPlan, every: NonZeroUsize, interval: Duration) -> Self {
+        Self {
+            path,
+            remaining: plan,
+            every,
+            interval,
+            unsaved: 0,
+            last_saved: Instant::now(),
+        }
+    }
+
+    /// Note that a record was added to the database, and whether fetching it is done, so it won't
+    /// be fetched again if the run is resumed
+    fn added(&mut self, key: RecordKey, done: bool) {
+        if done {
+            self.remaining.complete(key);
+        }
+        self.unsaved += 1;
+    }
+
+    fn is_due(&self) -> bool {
+        self.unsaved >= self.every.get()
+            || (self.unsaved > 0 && self.last_saved.elapsed() >= self.interval)
+    }
+
+    fn save(&mut self, stats_db: &Database) -> Result<()> {
+        // Flushing first means a crash in between only repeats some work
+        stats_db.flush()?;
+        self.remaining.save(&self.path)?;
+        self.unsaved = 0;
+        self.last_saved = Instant::now();
+        Ok(())
+    }
+
+    /// Remove the saved plan of a run that finished
+    fn finish(&self) -> Result<()> {
+        match std::fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(anyhow::Error::new(e)
+                    .context(format!("Failed to remove {}", self.path.display())))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug)]
 pub enum Payload {
     Solve(PuzzleStats),
     Unsolved(PuzzleStats),
-    FetchError(Option<PuzzleStats>),
+    /// The stats of the puzzle couldn't be fetched. Its record is still saved, since its id is
+    /// known.
+    FetchError(PuzzleStats, ApiError),
+    /// The id lookup covering the given date succeeded but didn't list a puzzle for it, e.g.
+    /// because it hasn't been released yet
+    MissingId(NaiveDate),
+    /// The id of the puzzle from the given date couldn't be looked up, even after retrying and
+    /// splitting the range of dates it was looked up with
+    LookupFailed(NaiveDate, Arc<ApiError>),
+    /// A fetch task panicked or was cancelled, so some puzzles may not have been fetched
+    TaskFailed,
+    /// A pass over the puzzles to fetch is done. The puzzles whose stats failed to fetch during the
+    /// pass are sent back so that they can be retried, and any errors they had are forgiven if they
+    /// succeed.
+    EndOfPass(oneshot::Sender<Vec<PuzzleStats>>),
     Finished(u32),
+    /// The run was stopped, e.g. with Ctrl+C, after the given number of requests. The results of
+    /// the cancelled tasks are still saved until they've all dropped their senders.
+    Interrupted(u32),
+}
+
+/// Summary of a completed run, suitable for passing on to other tools as JSON
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RunSummary {
+    /// Number of HTTP requests made
+    pub n_requests: u32,
+    /// Number of puzzles found to be solved
+    pub solved: u32,
+    /// Number of puzzles found to be unsolved
+    pub unsolved: u32,
+    /// Number of puzzles whose stats could not be fetched
+    pub errors: u32,
+    /// Number of the errors that are puzzles whose ids couldn't be looked up. Puzzles from today
+    /// aren't counted, since they may not have been released yet.
+    pub lookup_failures: u32,
+    /// Number of puzzles whose stats failed to fetch at first, but were fetched by a retry pass
+    pub recovered: u32,
+    /// Number of fetch tasks that panicked or were cancelled. Any failure here is a bug, and the
+    /// puzzles those tasks were fetching are missing from the results.
+    pub task_failures: u32,
+    /// Earliest date of the archive
+    pub archive_start: Option<NaiveDate>,
+    /// Number of complete records in the archive
+    pub archive_complete: usize,
+    /// Number of days in the archive
+    pub archive_total: usize,
+    /// Number of consecutive days solved up to today
+    pub current_streak: u32,
+    /// Whether the run was stopped before it was done. What it fetched was saved, and the rest can
+    /// be resumed.
+    pub interrupted: bool,
+}
+
+impl RunSummary {
+    /// Percentage of the archive that has been completed
+    #[must_use]
+    pub fn archive_percent(&self) -> f64 {
+        #[allow(clippy::cast_precision_loss)]
+        if self.archive_total == 0 {
+            100.0
+        } else {
+            self.archive_complete as f64 * 100.0 / self.archive_total as f64
+        }
+    }
 }
 
+/// Receive fetch results, store them in the database, and report progress
+///
+/// # Arguments
+///
+/// * `rx` - Channel where individual puzzle's statistics are received
+/// * `stats_db` - Database to store results in. Flushed once the `Finished` payload is received,
+///   or once the channel closes after an `Interrupted` payload, and then closed.
+/// * `progress` - Progress bar for the current run
+/// * `archive_start` - Earliest date of the archive being backfilled, used to report overall
+///   completion at the end of the run
+/// * `notifiers` - Sinks to notify of recent solves
+/// * `home_tz` - Solver's home time zone, used to derive `solved_on_pub_day` for every record
+/// * `percent_rank` - Whether to derive `percent_rank` for every record
+/// * `status` - Status file to keep up to date with the run's progress
+/// * `checkpoint` - When to save progress, and the plan of the run to save what's left of
+///
+/// Returns a summary of the run once the database has been closed
+#[allow(clippy::too_many_arguments)]
 pub async fn task_fn(
     mut rx: mpsc::UnboundedReceiver<Payload>,
     mut stats_db: Database,
     progress: ProgressBar,
-) -> Result<()> {
+    archive_start: NaiveDate,
+    notifiers: Arc<Notifiers>,
+    home_tz: HomeTimezone,
+    percent_rank: bool,
+    status: Arc<Mutex<StatusFile>>,
+    mut checkpoint: Checkpoint,
+) -> Result<RunSummary> {
+    let notify_after =
+        chrono::offset::Utc::now().date_naive() - chrono::Duration::days(NOTIFY_SOLVE_DAYS);
+    let mut summary = RunSummary {
+        archive_start: Some(archive_start),
+        ..Default::default()
+    };
+    let mut interrupted_after = None;
+    // Puzzles that failed in the current pass, and ones that failed in earlier passes
+    let mut failed = Vec::new();
+    let mut retrying = HashSet::new();
     while let Some(payload) = rx.recv().await {
         match payload {
-            Payload::Solve(stats) | Payload::Unsolved(stats) | Payload::FetchError(Some(stats)) => {
+            Payload::Solve(mut stats) => {
+                summary.solved += 1;
+                stats.fetch_failed = None;
+                if retrying.remove(&stats.key()) {
+                    summary.errors -= 1;
+                    summary.recovered += 1;
+                }
+                if stats.date >= notify_after {
+                    notifiers.notify_solve(&stats).await;
+                }
+                checkpoint.added(stats.key(), true);
+                if let Some(best) = stats_db.add(stats) {
+                    progress.println(format!(
+                        "🏆 New {} personal best: {}:{:02} on {}",
+                        best.weekday,
+                        best.solve_time_secs / 60,
+                        best.solve_time_secs % 60,
+                        best.date
+                    ));
+                }
+            }
+            Payload::Unsolved(mut stats) => {
+                summary.unsolved += 1;
+                stats.fetch_failed = None;
+                if retrying.remove(&stats.key()) {
+                    summary.errors -= 1;
+                    summary.recovered += 1;
+                }
+                checkpoint.added(stats.key(), true);
                 stats_db.add(stats);
             }
+            Payload::FetchError(mut stats, _) => {
+                if !retrying.contains(&stats.key()) {
+                    summary.errors += 1;
+                }
+                stats.fetch_failed = Some(true);
+                checkpoint.added(stats.key(), false);
+                failed.push(stats.clone());
+                stats_db.add(stats);
+            }
+            Payload::EndOfPass(reply) => {
+                retrying.extend(failed.iter().map(PuzzleStats::key));
+                // The run carries on without retries if it stopped listening
+                let _ = reply.send(std::mem::take(&mut failed));
+                continue;
+            }
             Payload::Finished(n_requests) => {
-                stats_db.flush()?;
-                let msg = format!("🎉 All done after {} requests", n_requests);
+                wrap_up(
+                    &mut stats_db,
+                    &mut summary,
+                    n_requests,
+                    home_tz,
+                    percent_rank,
+                )?;
+                checkpoint.finish()?;
+                let (complete, total) = (summary.archive_complete, summary.archive_total);
+                let msg = format!(
+                    "🎉 All done after {} requests. Archive is {:.1}% complete ({}/{} days since \
+                     {})",
+                    n_requests,
+                    summary.archive_percent(),
+                    complete,
+                    total,
+                    archive_start
+                );
                 progress.finish_with_message(msg);
                 break;
             }
-            Payload::FetchError(_) => (),
+            Payload::Interrupted(n_requests) => {
+                interrupted_after = Some(n_requests);
+                continue;
+            }
+            Payload::MissingId(_) => summary.errors += 1,
+            Payload::LookupFailed(date, _) => {
+                summary.errors += 1;
+                if date < chrono::offset::Utc::now().date_naive() {
+                    summary.lookup_failures += 1;
+                }
+            }
+            Payload::TaskFailed => summary.task_failures += 1,
         }
         progress.inc(1);
+        status.lock().unwrap().progress(&summary);
+        if checkpoint.is_due() {
+            checkpoint.save(&stats_db)?;
+        }
+    }
+    if let Some(n_requests) = interrupted_after {
+        wrap_up(
+            &mut stats_db,
+            &mut summary,
+            n_requests,
+            home_tz,
+            percent_rank,
+        )?;
+        checkpoint.save(&stats_db)?;
+        summary.interrupted = true;
+        progress.abandon_with_message(format!(
+            "⏹ Interrupted after {} requests with {} solved, {} unsolved, and {} errors saved",
+            n_requests, summary.solved, summary.unsolved, summary.errors
+        ));
+    }
+    stats_db.close()?;
+    Ok(summary)
+}
+
+/// Derive the columns computed from the whole database, flush it, and fill in the parts of the
+/// run's summary that describe the database
+fn wrap_up(
+    stats_db: &mut Database,
+    summary: &mut RunSummary,
+    n_requests: u32,
+    home_tz: HomeTimezone,
+    percent_rank: bool,
+) -> Result<()> {
+    stats_db.derive_solved_on_pub_day(home_tz);
+    if percent_rank {
+        stats_db.derive_percent_ranks();
+    }
+    stats_db.flush()?;
+    let today = chrono::offset::Utc::now().date_naive();
+    if let Some(archive_start) = summary.archive_start {
+        let (complete, total) = crate::archive_completion(stats_db, archive_start, today);
+        summary.archive_complete = complete;
+        summary.archive_total = total;
     }
+    summary.n_requests = n_requests;
+    summary.current_streak = crate::current_streak(stats_db, today);
     Ok(())
 }
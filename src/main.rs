@@ -13,27 +13,353 @@
 // limitations under the License.
 
 use anyhow::{Context, Result};
-use chrono::{naive::NaiveDate, Duration};
-use clap::{Args, Parser};
-use core::num::NonZeroU32;
-use crossword::api_client::{RateLimitedClient, SubscriptionToken};
-use crossword::database::Database;
-use crossword::{logger, DAY_STEP};
+use chrono::{naive::NaiveDate, Datelike, Duration, Weekday};
+use clap::{ArgGroup, Args, Parser, Subcommand};
+use core::num::{NonZeroU32, NonZeroUsize};
+use crossword::analytics::{AssistedReport, OnThisDay, WeekdayAggregates};
+use crossword::api_client::{
+    self, ApiError, PublishType, RateLimitedClient, RetryPolicy, StatsAndStreaks, SubscriptionToken,
+};
+#[cfg(feature = "browser-cookies")]
+use crossword::auth::BrowserCookies;
+use crossword::auth::{AuthProvider, CookieFile, StaticToken};
+#[cfg(feature = "browser-cookies")]
+use crossword::browser::Browser;
+use crossword::cache::{Freshness, ResponseCache};
+use crossword::database::{self, CsvDialect, Database, DuplicatePolicy, MemoryStorage, QuoteStyle};
+use crossword::diff::Change;
+use crossword::digest::{self, WeeklyDigest};
+#[cfg(feature = "exporters")]
+use crossword::export::{self, Format, Rollup, Unsolved};
+use crossword::goals::{self, Goal};
+use crossword::health::Health;
+use crossword::import;
+use crossword::journal::{self, JournalEntry};
+use crossword::logger::{self, Checkpoint, RunSummary};
+use crossword::merge::Precedence;
+use crossword::metrics::Metrics;
+use crossword::notify::{NotifierConfig, Notifiers};
+use crossword::plan::{self, FetchPlan};
+use crossword::repair;
+use crossword::run_status::{self, Phase, StatusFile};
+use crossword::schema::Schema;
+use crossword::search::IdLookupPolicy;
+use crossword::streaks::{self, LifetimeSnapshot, StreakSnapshot};
+use crossword::timezone::HomeTimezone;
+use crossword::watchdog::Watchdog;
+use crossword::Refresh;
+use crossword::{PuzzleStats, RecordKey};
 use indicatif::{ProgressBar, ProgressStyle};
-use log::warn;
-use std::path::PathBuf;
-use tokio::sync::mpsc;
+use log::{error, info, warn};
+#[cfg(feature = "exporters")]
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "exporters")]
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::time;
+use tokio::sync::{mpsc, oneshot, watch, Semaphore};
 
+/// Fetch NYT crossword solve stats into a CSV database
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Opt {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[command(flatten)]
-    subscription_token: NytToken,
+    fetch: FetchOpt,
+
+    #[command(flatten)]
+    database: DatabaseOpt,
+}
+
+/// Options for loading and saving the database, shared by every command
+#[derive(Args, Debug, Clone, Copy)]
+struct DatabaseOpt {
+    /// Which record to keep if a CSV database has more than one record for the same puzzle, e.g.
+    /// after being edited by hand
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t,
+        env = "NYT_ON_DUPLICATE"
+    )]
+    on_duplicate: DuplicatePolicy,
+
+    /// Number of backups of the database file to keep, as <database>.bak.1 (the most recent) to
+    /// <database>.bak.N. The file is backed up before a command first overwrites it. Zero disables
+    /// backups.
+    #[arg(long, global = true, default_value = "3", env = "NYT_BACKUPS")]
+    backups: usize,
+
+    /// Delimiter of the CSV files written, i.e. CSV databases and exports: `tab` or a single
+    /// character such as `;`. CSV files are read with whichever delimiter their header uses.
+    #[arg(
+        long,
+        global = true,
+        default_value = ",",
+        value_parser = database::parse_delimiter,
+        env = "NYT_CSV_DELIMITER"
+    )]
+    delimiter: u8,
+
+    /// Which fields of the CSV files written are quoted
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t,
+        env = "NYT_CSV_QUOTE_STYLE"
+    )]
+    quote_style: QuoteStyle,
+}
+
+impl DatabaseOpt {
+    /// Delimiter and quoting of the CSV files written
+    fn dialect(self) -> CsvDialect {
+        CsvDialect {
+            delimiter: self.delimiter,
+            quote_style: self.quote_style,
+        }
+    }
+
+    /// Load the database at the given path for reading. It isn't backed up when written back out.
+    fn load(self, path: &Path) -> Result<Database> {
+        Ok(Database::from_file_with_policy(path, self.on_duplicate)?
+            .with_csv_dialect(self.dialect()))
+    }
+
+    /// Read the records of the database at the given path without opening it as a `Database`
+    fn load_records(self, path: &Path) -> Result<HashMap<RecordKey, PuzzleStats>> {
+        database::load_records_with_policy(path, self.on_duplicate)
+    }
+
+    /// Load the database at the given path to update it, or create an empty one if the file
+    /// doesn't exist yet
+    fn open(self, path: &Path) -> Result<Database> {
+        let db = if path.exists() {
+            self.load(path).with_context(|| {
+                format!(
+                    "Given file exists but does not contain a valid database: {}",
+                    path.display()
+                )
+            })?
+        } else {
+            Database::new(path).with_csv_dialect(self.dialect())
+        };
+        Ok(db.with_backups(self.backups))
+    }
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Print statistics computed from an existing database
+    Stats(StatsOpt),
+    /// Re-fetch a random sample of solved records and report any that differ from the database
+    Verify(VerifyOpt),
+    /// Look up the id and stats of the puzzle of a single date and print them, e.g. to spot-check
+    /// a solve after the NYT app syncs
+    FetchOne(FetchOneOpt),
+    /// Export the database in a format suited to other tools
+    #[cfg(feature = "exporters")]
+    Export(ExportOpt),
+    /// Report records that were added, removed, or changed between two databases
+    Diff(DiffOpt),
+    /// Merge records from another database, e.g. one fetched with an old account's token
+    Merge(MergeOpt),
+    /// Rewrite CSV databases in the canonical format, e.g. after editing them by hand or resolving
+    /// a merge conflict in git
+    Fmt(FmtOpt),
+    /// Find and fix duplicate records, missing or wrong weekdays, and impossible values in a CSV
+    /// database
+    Repair(RepairOpt),
+    /// Import solve times from another tracker's CSV export, a database, or .puz files, reporting
+    /// any rows that are invalid
+    Import(ImportOpt),
+    /// Re-derive stored columns from the NYT API, e.g. after the parser learns about new fields
+    Reprocess(ReprocessOpt),
+    /// Fetch the official streaks and solve counts and add them to the streak history
+    Streaks(StreaksOpt),
+    /// Save the grid and clues of solved puzzles as ipuz files
+    Archive(ArchiveOpt),
+    /// Describe the columns of the database
+    Schema(SchemaOpt),
+    /// Fetch one live response from each NYT API endpoint and report any differences from the
+    /// expected schemas
+    #[cfg(feature = "schema-check")]
+    SchemaCheck(SchemaCheckOpt),
+    /// Summarize the health of an existing database, the status of its latest run, and progress
+    /// toward its goals
+    Status(StatusOpt),
+    /// Manage archive-completion goals, e.g. solving every Monday crossword since 2018
+    Goal(GoalOpt),
+    /// Manage the raw API responses saved in the cache directory
+    Raw(RawOpt),
+    /// Save a timestamped copy of the database and record it in the journal
+    Snapshot(SnapshotOpt),
+    /// Summarize the daily crosswords of a week and optionally send the summary to notifiers
+    Digest(DigestOpt),
+}
+
+// Options for the default command, which fetches missing stats into the database
+#[derive(Args, Debug)]
+struct FetchOpt {
+    #[command(flatten)]
+    client: ClientOpt,
 
     /// Earliest puzzle date to pull results from in YYYY-MM-DD format
-    #[arg(short, long, env = "NYT_XWORD_START")]
-    start_date: NaiveDate,
+    #[arg(short, long, env = "NYT_XWORD_START", required = true)]
+    start_date: Option<NaiveDate>,
+
+    /// Home time zone, used to decide whether each puzzle was solved on its publication day.
+    /// Either `local`, `utc`, or a fixed offset from UTC like -05:00.
+    #[arg(long, default_value = "local", env = "NYT_TIMEZONE")]
+    timezone: HomeTimezone,
+
+    /// Kind of crossword to fetch. Each kind is stored in the same database, with the kind in the
+    /// `publish_type` column.
+    #[arg(long, value_enum, default_value_t, env = "NYT_PUBLISH_TYPE")]
+    publish_type: PublishType,
+
+    /// Fill in the `percent_rank` column, ranking each solve's time against earlier solves of the
+    /// same weekday
+    #[arg(long)]
+    percent_rank: bool,
+
+    /// Only refresh cached records of the given kind, instead of looking up new puzzles and
+    /// refreshing unsolved ones
+    #[arg(long, value_enum)]
+    only: Option<Refresh>,
+
+    /// Refresh the stats of every cached puzzle in the range, including complete ones, e.g. to
+    /// fix solve times recorded before an API change. A solve that comes back with different stats
+    /// is overwritten, but an existing solve is never removed, even if the puzzle comes back
+    /// unsolved. New puzzles are looked up as usual.
+    #[arg(long, conflicts_with = "only")]
+    force_refresh: bool,
+
+    /// Print the plan of the run as JSON and exit without making any requests. The plan can be
+    /// edited, e.g. to split a backfill, and run with `--plan`. A summary of the requests it makes
+    /// for each kind of puzzle is printed to stderr, so it can be read without the JSON.
+    #[arg(long, conflicts_with = "daemon")]
+    dry_run: bool,
+
+    /// Print an estimate of the requests each phase of the run will make, including retries at
+    /// the failure rate of past runs in the journal, and how long it will take at the request
+    /// quota. Exits without making any requests, like `--dry-run`.
+    #[arg(long, conflicts_with_all = ["daemon", "dry_run"])]
+    simulate: bool,
+
+    /// Run the plan saved in the given file by `--dry-run`, instead of planning from the database
+    #[arg(long, conflicts_with_all = ["daemon", "only"])]
+    plan: Option<PathBuf>,
+
+    /// Plan the run from the database even if an interrupted run left a checkpoint to resume
+    #[arg(long, conflicts_with = "plan")]
+    no_resume: bool,
+
+    /// Save the database and what's left of the run's plan after this many records, so that an
+    /// interrupted run can be resumed from where it stopped
+    #[arg(long, default_value = "100", env = "NYT_CHECKPOINT_EVERY")]
+    checkpoint_every: NonZeroUsize,
+
+    /// Also save progress after this many seconds, if there's any to save
+    #[arg(long, default_value = "60", env = "NYT_CHECKPOINT_SECS")]
+    checkpoint_secs: NonZeroU32,
+
+    /// Limit the run to the leading part of its plan that makes at most this many requests. The
+    /// rest is left for later runs.
+    #[arg(long, env = "NYT_MAX_REQUESTS")]
+    max_requests: Option<usize>,
+
+    /// Most requests for puzzle ids and stats in flight at a time, on top of the rate limit.
+    /// Puzzles are only queued up as tasks once a slot is free, and finished tasks are cleaned up
+    /// as new ones are started, which bounds the memory used by long backfills.
+    #[arg(long, default_value = "32", env = "NYT_CONCURRENCY")]
+    concurrency: NonZeroUsize,
+
+    /// Times to retry looking up the puzzle ids of a block of dates, with backoff, on top of
+    /// `--retries`. Otherwise a single failed lookup skips up to 100 days for the run.
+    #[arg(long, default_value = "2", env = "NYT_ID_LOOKUP_RETRIES")]
+    id_lookup_retries: u32,
+
+    /// Times to split a block of dates whose id lookup keeps failing in half, retrying each half
+    /// on its own before giving up
+    #[arg(long, default_value = "2", env = "NYT_ID_LOOKUP_SPLITS")]
+    id_lookup_splits: u32,
+
+    /// Passes over the puzzles whose stats failed to fetch to make at the end of a run. Retry
+    /// passes fetch one puzzle at a time, after waiting `--retry-delay` seconds times the pass
+    /// number.
+    #[arg(long, default_value = "1", env = "NYT_RETRY_PASSES")]
+    retry_passes: u32,
 
-    /// Rate-limit (per second) for outgoing requests
+    /// Seconds to wait before the first retry pass
+    #[arg(long, default_value = "30", env = "NYT_RETRY_DELAY")]
+    retry_delay: u32,
+
+    /// Keep running in the background, fetching new stats every `--interval` hours
+    #[arg(long)]
+    daemon: bool,
+
+    /// Hours to wait between runs in daemon mode
+    #[arg(long, default_value = "24", requires = "daemon")]
+    interval: NonZeroU32,
+
+    /// In daemon mode, send an alert to the configured notifiers if no run has succeeded for this
+    /// many days. Alerts are always sent if the current streak drops to zero.
+    #[arg(long, requires = "daemon", env = "NYT_ALERT_AFTER_DAYS")]
+    alert_after_days: Option<NonZeroU32>,
+
+    /// Address to serve Prometheus metrics at in daemon mode, e.g. 127.0.0.1:9898
+    #[arg(long, requires = "daemon")]
+    metrics_addr: Option<SocketAddr>,
+
+    /// Send notifications of recent solves, run summaries, and failures. May be given multiple
+    /// times, e.g. `--notify slack=https://hooks.slack.com/... --notify desktop`. Supported sinks
+    /// are webhook=<url>, slack=<url>, discord=<url>, and desktop, depending on build features.
+    #[arg(long, env = "NYT_NOTIFY", value_delimiter = ',')]
+    notify: Vec<NotifierConfig>,
+
+    /// In daemon mode, send a digest of the previous week's solves to the configured notifiers
+    /// after the first run of each week
+    #[arg(long, requires = "daemon", env = "NYT_WEEKLY_DIGEST")]
+    weekly_digest: bool,
+
+    /// Program to run after the database has been written. It receives a JSON summary of the run
+    /// on stdin.
+    #[arg(long, env = "NYT_POST_RUN_HOOK")]
+    post_run_hook: Option<PathBuf>,
+
+    /// Directory of monthly Markdown reports. The report for each month with puzzles fetched in
+    /// the run is regenerated after the database has been written, along with goals.md if the
+    /// database has goals.
+    #[cfg(feature = "exporters")]
+    #[arg(long, env = "NYT_AUTO_REPORT")]
+    auto_report: Option<PathBuf>,
+
+    /// Path to write CSV output. If a CSV file from a previous program exists at that path, it
+    /// will be updated with missing data and the number of requests made will potentially be
+    /// reduced.
+    #[arg(required = true)]
+    db_path: Option<PathBuf>,
+}
+
+/// Options for commands that talk to the NYT API
+#[derive(Args, Debug)]
+struct ClientOpt {
+    #[command(flatten)]
+    subscription_token: NytToken,
+
+    /// Rate-limit (per second) for outgoing requests to each API host
     #[arg(
         short = 'q',
         long = "quota",
@@ -42,10 +368,89 @@ struct Opt {
     )]
     request_quota: NonZeroU32,
 
-    /// Path to write CSV output. If a CSV file from a previous program exists at that path, it
-    /// will be updated with missing data and the number of requests made will potentially be
-    /// reduced.
-    db_path: PathBuf,
+    /// Number of times to retry a request that fails with a server or network error
+    #[arg(long, default_value = "3", env = "NYT_RETRIES")]
+    retries: u32,
+
+    /// Proxy to send requests through, e.g. http://proxy.example.com:8080. Defaults to the
+    /// HTTPS_PROXY or HTTP_PROXY environment variables, if set.
+    #[arg(long)]
+    proxy: Option<String>,
+
+    /// Stop making requests after this many consecutive responses rejecting the subscription
+    /// token. Zero disables the check.
+    #[arg(long, default_value = "5", env = "NYT_MAX_AUTH_FAILURES")]
+    max_auth_failures: u32,
+
+    /// While the NYT API is down for maintenance, pause all requests for up to this many minutes
+    /// before giving up. Zero disables pausing.
+    #[arg(long, default_value = "60", env = "NYT_MAX_MAINTENANCE_PAUSE")]
+    max_maintenance_pause: u64,
+
+    /// Directory to save API responses in, untouched. Later runs revalidate saved responses with
+    /// conditional requests instead of downloading unchanged data again, and `reprocess
+    /// --from-raw` re-parses them, e.g. after the NYT changes the format of its responses.
+    #[arg(long, visible_alias = "raw-dir", env = "NYT_CACHE_DIR")]
+    cache_dir: Option<PathBuf>,
+
+    /// Use saved responses younger than this many hours without checking with the server
+    #[arg(long, env = "NYT_CACHE_TTL")]
+    cache_ttl: Option<u64>,
+
+    /// Don't read or write the response cache, even if a cache directory is configured
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Keep a running count of requests made per month in a local JSON file at this path
+    #[arg(long, env = "NYT_USAGE_FILE")]
+    usage_file: Option<PathBuf>,
+}
+
+impl ClientOpt {
+    fn build_client(&self) -> Result<RateLimitedClient> {
+        self.build_client_with_freshness(self.freshness())
+    }
+
+    /// Policy for using saved responses without a request, as configured by `--cache-ttl`
+    fn freshness(&self) -> Freshness {
+        self.cache_ttl.map_or(Freshness::Revalidate, |hours| {
+            Freshness::MaxAge(time::Duration::from_secs(hours * 3600))
+        })
+    }
+
+    /// Build a client whose response cache, if any, uses the given freshness policy
+    fn build_client_with_freshness(&self, freshness: Freshness) -> Result<RateLimitedClient> {
+        let retry_policy = RetryPolicy {
+            max_attempts: self.retries + 1,
+            ..Default::default()
+        };
+        let proxy = self
+            .proxy
+            .as_deref()
+            .map(reqwest::Proxy::all)
+            .transpose()
+            .context("Invalid proxy URL")?;
+        let mut client = RateLimitedClient::with_auth_provider(
+            self.subscription_token.to_provider()?,
+            self.request_quota,
+            proxy,
+        )?
+        .with_retry_policy(retry_policy)
+        .with_max_auth_failures(self.max_auth_failures)
+        .with_max_maintenance_pause(time::Duration::from_secs(self.max_maintenance_pause * 60));
+        if let Some(dir) = self.cache_dir.as_ref().filter(|_| !self.no_cache) {
+            client = client.with_cache(ResponseCache::new(dir)?.with_freshness(freshness));
+        }
+        Ok(client)
+    }
+
+    /// Add the requests made by the given client to the usage file, if one was requested
+    fn record_usage(&self, client: &RateLimitedClient) -> Result<()> {
+        if let Some(path) = &self.usage_file {
+            crossword::usage::record_requests(path, client.n_requests())?;
+        }
+        Ok(())
+    }
 }
 
 /// NYT subscription token extracted from web browser
@@ -58,35 +463,1202 @@ struct NytToken {
     /// NYT subscription token from NYT-S cookie
     #[arg(long, short = 't', env = "NYT_S_COOKIE")]
     nyt_cookie: Option<String>,
+    /// Read the NYT-S cookie from a cookies.txt file in the Netscape format
+    #[arg(long, env = "NYT_COOKIE_FILE")]
+    cookie_file: Option<PathBuf>,
+    /// Read the NYT-S cookie from a local browser profile
+    #[cfg(feature = "browser-cookies")]
+    #[arg(long, value_enum, env = "NYT_FROM_BROWSER")]
+    from_browser: Option<Browser>,
+}
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("report").required(true).multiple(true)))]
+struct StatsOpt {
+    /// Report on solves that used the check or reveal aids
+    #[arg(long, group = "report")]
+    assisted: bool,
+
+    /// Report solve times of the daily crossword on the same date, and the nearest puzzle on the
+    /// same weekday, in previous years. Defaults to today's date; pass another one as
+    /// `--on-this-day=YYYY-MM-DD`.
+    #[arg(
+        long,
+        group = "report",
+        value_name = "DATE",
+        num_args = 0..=1,
+        require_equals = true
+    )]
+    on_this_day: Option<Option<NaiveDate>>,
+
+    /// Report the count, mean, median, best, and worst unassisted solve times of the daily
+    /// crossword on each weekday
+    #[arg(long, group = "report")]
+    weekdays: bool,
+
+    /// Path to an existing CSV database
+    db_path: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct VerifyOpt {
+    #[command(flatten)]
+    client: ClientOpt,
+
+    /// Number of solved records to re-fetch
+    #[arg(long, default_value = "20")]
+    sample: usize,
+
+    /// Path to an existing CSV database
+    db_path: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct FetchOneOpt {
+    #[command(flatten)]
+    client: ClientOpt,
+
+    /// Date of the puzzle in YYYY-MM-DD format
+    date: NaiveDate,
+
+    /// Kind of crossword to fetch
+    #[arg(long, value_enum, default_value_t, env = "NYT_PUBLISH_TYPE")]
+    publish_type: PublishType,
+
+    /// Home time zone, used to decide whether the puzzle was solved on its publication day
+    #[arg(long, default_value = "local", env = "NYT_TIMEZONE")]
+    timezone: HomeTimezone,
+
+    /// Print the record as JSON
+    #[arg(long)]
+    json: bool,
+
+    /// Also save the record to this database. It's merged with any record of the same puzzle, so a
+    /// solve that's already saved is kept even if the puzzle comes back unsolved.
+    #[arg(long)]
+    save: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct SchemaOpt {
+    /// Print the schema as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct RawOpt {
+    /// Directory the responses are saved in
+    #[arg(long, visible_alias = "raw-dir", env = "NYT_CACHE_DIR")]
+    cache_dir: PathBuf,
+
+    #[command(subcommand)]
+    command: RawCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum RawCommand {
+    /// Delete the saved responses of all but the latest months of puzzles
+    Prune {
+        /// Number of months of puzzles to keep responses for
+        #[arg(long)]
+        keep_latest: usize,
+    },
+    /// List the responses saved for puzzles published within a date range
+    List {
+        /// Earliest puzzle date in YYYY-MM-DD format
+        #[arg(long)]
+        start_date: NaiveDate,
+        /// Latest puzzle date in YYYY-MM-DD format. Defaults to today.
+        #[arg(long)]
+        end_date: Option<NaiveDate>,
+    },
+}
+
+#[derive(Args, Debug)]
+struct SnapshotOpt {
+    /// Directory to save the snapshot in. Defaults to a `snapshots` directory next to the
+    /// database.
+    #[arg(long)]
+    dir: Option<PathBuf>,
+
+    /// Compress the snapshot with gzip
+    #[arg(long)]
+    gzip: bool,
+
+    /// Path to an existing database
+    db_path: PathBuf,
+}
+
+#[cfg(feature = "schema-check")]
+#[derive(Args, Debug)]
+struct SchemaCheckOpt {
+    #[command(flatten)]
+    client: ClientOpt,
+
+    /// Also list fields that are new in the responses
+    #[arg(long)]
+    show_added: bool,
+
+    /// Directory to write the observed schema of each response to, e.g. as a starting point for
+    /// updating the expected schemas
+    #[arg(long)]
+    write: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct DigestOpt {
+    /// Any date in the week to summarize, in YYYY-MM-DD format. Defaults to the previous week.
+    #[arg(long)]
+    week: Option<NaiveDate>,
+
+    /// Also send the digest to these notifiers, in the same format as for fetching
+    #[arg(long, env = "NYT_NOTIFY", value_delimiter = ',')]
+    notify: Vec<NotifierConfig>,
+
+    /// Path to an existing database
+    db_path: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct StatusOpt {
+    /// List every date left to solve for each goal, rather than the first few
+    #[arg(long)]
+    all_remaining: bool,
+
+    /// Path to an existing CSV database
+    db_path: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct GoalOpt {
+    #[command(subcommand)]
+    command: GoalCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum GoalCommand {
+    /// Add a goal of solving every daily crossword, or every one on the given days of the week,
+    /// published within a date range
+    Add {
+        /// Name to refer to the goal by
+        name: String,
+        /// Day of the week to solve, e.g. `mon`. Can be repeated. Defaults to every day.
+        #[arg(long = "weekday")]
+        weekdays: Vec<Weekday>,
+        /// Earliest puzzle date in YYYY-MM-DD format
+        #[arg(long)]
+        from: NaiveDate,
+        /// Latest puzzle date in YYYY-MM-DD format. Defaults to the latest puzzle published.
+        #[arg(long)]
+        to: Option<NaiveDate>,
+        /// Only count solves without check or reveal
+        #[arg(long)]
+        unassisted: bool,
+        /// Path to the database the goal is for
+        db_path: PathBuf,
+    },
+    /// List the goals of a database with progress toward each
+    List {
+        /// List every date left to solve, rather than the first few
+        #[arg(long)]
+        all_remaining: bool,
+        /// Path to an existing database
+        db_path: PathBuf,
+    },
+    /// Remove a goal
+    Remove {
+        /// Name of the goal
+        name: String,
+        /// Path to the database the goal is for
+        db_path: PathBuf,
+    },
+}
+
+#[derive(Args, Debug)]
+struct ArchiveOpt {
+    #[command(flatten)]
+    client: ClientOpt,
+
+    /// Archive unsolved puzzles too
+    #[arg(long)]
+    all: bool,
+
+    /// Directory to save puzzles in. Defaults to a `puzzles` directory next to the database.
+    #[arg(long)]
+    dir: Option<PathBuf>,
+
+    /// Path to an existing CSV database
+    db_path: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct StreaksOpt {
+    #[command(flatten)]
+    client: ClientOpt,
+
+    /// Path to the CSV database. The streak history is stored alongside it.
+    db_path: PathBuf,
+}
+
+#[cfg(feature = "exporters")]
+#[derive(Args, Debug)]
+struct ExportOpt {
+    /// Aggregate records into one row per period instead of exporting individual records
+    #[arg(long, value_enum)]
+    rollup: Option<Rollup>,
+
+    /// How to represent unsolved puzzles and other missing values. JSON formats always write
+    /// missing values as `null`.
+    #[arg(long, value_enum, default_value_t)]
+    unsolved: Unsolved,
+
+    /// File format of the export
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
+
+    /// Path to write the export to. Defaults to stdout.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Path to an existing CSV database
+    db_path: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct DiffOpt {
+    /// Path to the older CSV database
+    old: PathBuf,
+    /// Path to the newer CSV database
+    new: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct FmtOpt {
+    /// Only list the databases that aren't in the canonical format, failing if there are any,
+    /// e.g. in a pre-commit hook
+    #[arg(long)]
+    check: bool,
+
+    /// Paths to existing CSV databases
+    #[arg(required = true)]
+    db_paths: Vec<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct RepairOpt {
+    /// Which record to keep when the database has more than one for the same puzzle
+    #[arg(long, value_enum, default_value_t = DuplicatePolicy::KeepMostComplete)]
+    keep: DuplicatePolicy,
+
+    /// Clear impossible values, i.e. solve times of zero and solve timestamps earlier than the
+    /// puzzle was opened, instead of only reporting them
+    #[arg(long)]
+    clear_impossible: bool,
+
+    /// Only report problems, failing if there are any
+    #[arg(long)]
+    check: bool,
+
+    /// Path to an existing CSV database
+    db_path: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct MergeOpt {
+    /// Which record to keep when both databases have a solve for the same puzzle. A solve always
+    /// takes precedence over an unsolved puzzle.
+    #[arg(long, value_enum, default_value_t)]
+    prefer: Precedence,
+
+    /// Path to the CSV database to merge records from
+    other: PathBuf,
+
+    /// Path to the CSV database to merge records into
+    db_path: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct ImportOpt {
+    /// Which record to keep when the database already has a solve for an imported puzzle
+    #[arg(long, value_enum, default_value_t)]
+    prefer: Precedence,
+
+    /// Format of the input. Detected from its path and header if not given.
+    #[arg(long, value_enum)]
+    format: Option<import::Format>,
+
+    /// Read a column of a tracker CSV as one of the recognized fields, e.g.
+    /// `--map solve-time=Duration`. Implies `--format tracker`. Can be repeated.
+    #[arg(long, value_name = "FIELD=COLUMN")]
+    map: Vec<import::ColumnMapping>,
+
+    /// CSV file, database, .puz file, or directory of .puz files to import. Tracker CSVs must have
+    /// a `date` column. See the `import` module docs for the other recognized columns.
+    input: PathBuf,
+
+    /// Path to the CSV database to import records into
+    db_path: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct ReprocessOpt {
+    #[command(flatten)]
+    client: ClientOpt,
+
+    /// Use responses saved in the cache directory instead of re-fetching them. Only puzzles
+    /// without a saved response are requested from the network.
+    #[arg(long, requires = "cache_dir", conflicts_with = "no_cache")]
+    from_raw: bool,
+
+    /// Only reprocess puzzles published on or after this date, in YYYY-MM-DD format
+    #[arg(long)]
+    start_date: Option<NaiveDate>,
+
+    /// Only reprocess puzzles published on or before this date, in YYYY-MM-DD format
+    #[arg(long)]
+    end_date: Option<NaiveDate>,
+
+    /// Path to an existing CSV database
+    db_path: PathBuf,
+}
+
+impl NytToken {
+    /// Source of the token, which is asked for a new one if the token is rejected
+    fn to_provider(&self) -> Result<Arc<dyn AuthProvider>> {
+        if let Some(header) = &self.nyt_header {
+            Ok(Arc::new(StaticToken(SubscriptionToken::Header(
+                header.clone(),
+            ))))
+        } else if let Some(cookie) = &self.nyt_cookie {
+            Ok(Arc::new(StaticToken(SubscriptionToken::Cookie(
+                cookie.clone(),
+            ))))
+        } else if let Some(path) = &self.cookie_file {
+            Ok(Arc::new(CookieFile(path.clone())))
+        } else {
+            #[cfg(feature = "browser-cookies")]
+            if let Some(browser) = self.from_browser {
+                return Ok(Arc::new(BrowserCookies(browser)));
+            }
+            anyhow::bail!("No NYT subscription token provided");
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    #[cfg(feature = "dotenv")]
     dotenv::dotenv().ok();
     pretty_env_logger::init();
     let opt = Opt::parse();
+    let db_opt = opt.database;
+
+    match opt.command {
+        Some(Command::Stats(stats_opt)) => stats(stats_opt, db_opt),
+        Some(Command::Verify(verify_opt)) => verify(verify_opt, db_opt).await,
+        Some(Command::FetchOne(fetch_one_opt)) => fetch_one(fetch_one_opt, db_opt).await,
+        #[cfg(feature = "exporters")]
+        Some(Command::Export(export_opt)) => export(export_opt, db_opt),
+        Some(Command::Diff(diff_opt)) => diff(&diff_opt, db_opt),
+        Some(Command::Merge(merge_opt)) => merge(&merge_opt, db_opt),
+        Some(Command::Fmt(fmt_opt)) => format_databases(&fmt_opt, db_opt),
+        Some(Command::Repair(repair_opt)) => repair_database(&repair_opt, db_opt),
+        Some(Command::Import(import_opt)) => import(&import_opt, db_opt),
+        Some(Command::Reprocess(reprocess_opt)) => reprocess(reprocess_opt, db_opt).await,
+        Some(Command::Streaks(streaks_opt)) => fetch_streaks(streaks_opt).await,
+        Some(Command::Archive(archive_opt)) => archive(archive_opt, db_opt).await,
+        Some(Command::Schema(schema_opt)) => schema(&schema_opt),
+        #[cfg(feature = "schema-check")]
+        Some(Command::SchemaCheck(schema_check_opt)) => schema_check(&schema_check_opt).await,
+        Some(Command::Status(status_opt)) => status(&status_opt, db_opt),
+        Some(Command::Goal(goal_opt)) => goal(goal_opt, db_opt),
+        Some(Command::Raw(raw_opt)) => raw(&raw_opt),
+        Some(Command::Snapshot(snapshot_opt)) => snapshot(&snapshot_opt, db_opt),
+        Some(Command::Digest(digest_opt)) => digest(&digest_opt, db_opt).await,
+        None => fetch(opt.fetch, db_opt).await,
+    }
+}
+
+fn stats(opt: StatsOpt, db_opt: DatabaseOpt) -> Result<()> {
+    let stats_db = db_opt.load(&opt.db_path)?;
+    if opt.assisted {
+        print!("{}", AssistedReport::new(stats_db.solved()));
+    }
+    if let Some(date) = opt.on_this_day {
+        if opt.assisted {
+            println!();
+        }
+        let date = date.unwrap_or_else(|| chrono::offset::Local::now().date_naive());
+        print!("{}", OnThisDay::new(&stats_db, date));
+    }
+    if opt.weekdays {
+        if opt.assisted || opt.on_this_day.is_some() {
+            println!();
+        }
+        let daily = stats_db
+            .solved()
+            .filter(|r| r.publish_type == PublishType::Daily);
+        print!("{}", WeekdayAggregates::new(daily));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "exporters")]
+fn export(opt: ExportOpt, db_opt: DatabaseOpt) -> Result<()> {
+    let stats_db = db_opt.load(&opt.db_path)?;
+    let mut records: Vec<&PuzzleStats> = stats_db.iter().collect();
+    records.sort_unstable_by_key(|r| r.date);
+
+    let writer: Box<dyn Write> = match &opt.output {
+        Some(path) => Box::new(
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?,
+        ),
+        None => Box::new(io::stdout().lock()),
+    };
+    let omit = opt.unsolved == Unsolved::Omit;
+    match opt.rollup {
+        Some(rollup) => {
+            let mut rows = export::rollup(records.iter().copied(), rollup);
+            if omit {
+                rows.retain(|row| row.solved > 0);
+            }
+            write_rows(writer, &rows, opt.format, opt.unsolved, db_opt.dialect())
+        }
+        None => {
+            if omit {
+                records.retain(|r| r.is_solved());
+            }
+            #[cfg(feature = "parquet")]
+            if opt.format == Format::Parquet {
+                return crossword::parquet::write_parquet(writer, records);
+            }
+            write_rows(writer, &records, opt.format, opt.unsolved, db_opt.dialect())
+        }
+    }
+}
+
+#[cfg(feature = "exporters")]
+fn write_rows<T: Serialize>(
+    writer: Box<dyn Write>,
+    rows: &[T],
+    format: Format,
+    unsolved: Unsolved,
+    dialect: CsvDialect,
+) -> Result<()> {
+    match (format, unsolved) {
+        (Format::Json, _) => export::write_json(writer, rows),
+        (Format::Jsonl, _) => export::write_jsonl(writer, rows),
+        (Format::Csv, Unsolved::Na) => export::write_csv_filled(writer, rows, "NA", dialect),
+        (Format::Csv, Unsolved::Empty | Unsolved::Omit) => export::write_csv(writer, rows, dialect),
+        #[cfg(feature = "parquet")]
+        (Format::Parquet, _) => anyhow::bail!("Only individual records can be exported as Parquet"),
+    }
+}
+
+fn diff(opt: &DiffOpt, db_opt: DatabaseOpt) -> Result<()> {
+    let old = db_opt.load_records(&opt.old)?;
+    let new = db_opt.load_records(&opt.new)?;
+    let changes = crossword::diff::diff(&old, &new);
+    let (mut added, mut removed, mut changed) = (0, 0, 0);
+    for change in &changes {
+        println!("{change}");
+        match change {
+            Change::Added(_) => added += 1,
+            Change::Removed(_) => removed += 1,
+            Change::Changed { .. } => changed += 1,
+        }
+    }
+    println!("{added} added, {removed} removed, {changed} changed");
+    Ok(())
+}
+
+fn format_databases(opt: &FmtOpt, db_opt: DatabaseOpt) -> Result<()> {
+    let mut unformatted = 0;
+    for path in &opt.db_paths {
+        if database::is_sqlite(path) {
+            anyhow::bail!("{} is not a CSV database", path.display());
+        }
+        let records = db_opt.load_records(path)?;
+        let mut formatted = Vec::new();
+        database::write_csv_as(
+            &mut formatted,
+            &records.values().collect::<Vec<_>>(),
+            db_opt.dialect(),
+        )?;
+        if std::fs::read(path)? == formatted {
+            continue;
+        }
+        unformatted += 1;
+        if opt.check {
+            println!("{} is not formatted", path.display());
+            continue;
+        }
+        crossword::backup::rotate(path, db_opt.backups)?;
+        std::fs::write(path, formatted)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        println!("Formatted {}", path.display());
+    }
+    if opt.check && unformatted > 0 {
+        anyhow::bail!(
+            "{} of {} databases are not formatted",
+            unformatted,
+            opt.db_paths.len()
+        );
+    }
+    Ok(())
+}
+
+fn repair_database(opt: &RepairOpt, db_opt: DatabaseOpt) -> Result<()> {
+    let path = &opt.db_path;
+    if database::is_sqlite(path) {
+        anyhow::bail!("{} is not a CSV database", path.display());
+    }
+    let csv = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let repair = repair::scan(&csv, opt.keep, opt.clear_impossible)
+        .with_context(|| format!("Failed to scan {}", path.display()))?;
+    if opt.check {
+        for finding in &repair.findings {
+            println!("{finding:#}");
+        }
+        if !repair.findings.is_empty() {
+            anyhow::bail!(
+                "{} problems found in {}",
+                repair.findings.len(),
+                path.display()
+            );
+        }
+        return Ok(());
+    }
+    for finding in &repair.findings {
+        println!("{finding}");
+    }
+    let fixed = repair.findings.iter().filter(|f| f.fixed).count();
+    if repair.changed() {
+        let mut stats_db = Database::new(path)
+            .with_backups(db_opt.backups)
+            .with_csv_dialect(db_opt.dialect());
+        for record in repair.records.into_values() {
+            stats_db.add(record);
+        }
+        stats_db.close()?;
+    }
+    println!("{} problems found, {} fixed", repair.findings.len(), fixed);
+    Ok(())
+}
+
+fn merge(opt: &MergeOpt, db_opt: DatabaseOpt) -> Result<()> {
+    let other = db_opt.load_records(&opt.other)?;
+    let mut stats_db = db_opt.open(&opt.db_path)?;
+    let summary = stats_db.merge(other.into_values(), opt.prefer);
+    stats_db.close()?;
+    for conflict in &summary.conflicts {
+        println!("{conflict}");
+    }
+    println!(
+        "{} added, {} replaced, {} kept, {} conflicts",
+        summary.added,
+        summary.replaced,
+        summary.kept,
+        summary.conflicts.len()
+    );
+    Ok(())
+}
+
+fn import(opt: &ImportOpt, db_opt: DatabaseOpt) -> Result<()> {
+    let (format, import) = import::read_path(&opt.input, opt.format, &opt.map)?;
+    info!("Importing {} as {}", opt.input.display(), format);
+    for issue in &import.issues {
+        eprintln!("{issue}");
+    }
+    let mut stats_db = db_opt.open(&opt.db_path)?;
+    let summary = stats_db.merge(import.records, opt.prefer);
+    stats_db.close()?;
+    for conflict in &summary.conflicts {
+        println!("{conflict}");
+    }
+    println!(
+        "{} added, {} replaced, {} kept, {} rows skipped",
+        summary.added,
+        summary.replaced,
+        summary.kept,
+        import.issues.len()
+    );
+    Ok(())
+}
+
+async fn reprocess(opt: ReprocessOpt, db_opt: DatabaseOpt) -> Result<()> {
+    let mut stats_db = db_opt.load(&opt.db_path)?.with_backups(db_opt.backups);
+    let freshness = if opt.from_raw {
+        Freshness::Always
+    } else {
+        opt.client.freshness()
+    };
+    let client = opt.client.build_client_with_freshness(freshness)?;
+    let dates = (
+        opt.start_date.map_or(Bound::Unbounded, Bound::Included),
+        opt.end_date.map_or(Bound::Unbounded, Bound::Included),
+    );
+    let records: Vec<PuzzleStats> = stats_db.range(dates).cloned().collect();
+    if let (true, Some(dir), Some(first), Some(last)) = (
+        opt.from_raw,
+        &opt.client.cache_dir,
+        records.iter().map(|r| r.date).min(),
+        records.iter().map(|r| r.date).max(),
+    ) {
+        let saved = ResponseCache::new(dir)?
+            .entries(first, last)?
+            .iter()
+            .filter(|entry| entry.url.contains("/v6/game/"))
+            .count();
+        info!(
+            "{} of {} records have saved responses",
+            saved,
+            records.len()
+        );
+    }
+    let (updated, summary) = crossword::reprocess::reprocess(&client, &records).await;
+    opt.client.record_usage(&client)?;
+    for record in updated {
+        stats_db.add(record);
+    }
+    stats_db.close()?;
+    println!(
+        "{} updated, {} unchanged, {} errors after {} requests",
+        summary.updated,
+        summary.unchanged,
+        summary.errors,
+        client.n_requests()
+    );
+    Ok(())
+}
+
+fn raw(opt: &RawOpt) -> Result<()> {
+    let cache = ResponseCache::new(&opt.cache_dir)?;
+    match opt.command {
+        RawCommand::Prune { keep_latest } => {
+            let summary = cache.prune(keep_latest)?;
+            println!(
+                "Removed {} responses from {} months",
+                summary.responses, summary.months
+            );
+        }
+        RawCommand::List {
+            start_date,
+            end_date,
+        } => {
+            let end_date = end_date.unwrap_or_else(|| chrono::offset::Utc::now().date_naive());
+            for entry in cache.entries(start_date, end_date)? {
+                if let Some(date) = entry.date {
+                    println!("{}\t{}", date, entry.url);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn snapshot(opt: &SnapshotOpt, db_opt: DatabaseOpt) -> Result<()> {
+    // Also checks that every record of the database is readable before copying it. Only the keys
+    // are kept, to count the distinct records.
+    let mut keys = HashSet::new();
+    for record in database::stream_records(&opt.db_path)? {
+        let (date, publish_type) = record?.key();
+        if !keys.insert((date, publish_type)) && db_opt.on_duplicate == DuplicatePolicy::Error {
+            anyhow::bail!("Duplicate {} record for {}", publish_type, date);
+        }
+    }
+    let records = keys.len();
+    let dir = opt.dir.clone().unwrap_or_else(|| {
+        opt.db_path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join("snapshots")
+    });
+    let now = chrono::Utc::now();
+    let path = crossword::snapshot::snapshot(&opt.db_path, &dir, now, opt.gzip)?;
+    journal::append(
+        &journal::journal_path(&opt.db_path),
+        &JournalEntry::Snapshot {
+            unix: now.timestamp(),
+            path: path.clone(),
+            records,
+        },
+    )?;
+    println!("Saved {} records to {}", records, path.display());
+    Ok(())
+}
+
+/// Summarize the week containing `date` from the database at the given path, without writing it
+fn weekly_digest(db_opt: DatabaseOpt, db_path: &Path, date: NaiveDate) -> Result<WeeklyDigest> {
+    let records = db_opt.load_records(db_path)?;
+    let stats_db = Database::from_storage(Box::new(MemoryStorage(Arc::new(Mutex::new(records)))))?;
+    let week_start = digest::previous_week(date + Duration::weeks(1));
+    let today = chrono::offset::Utc::now().date_naive();
+    Ok(WeeklyDigest::new(&stats_db, week_start, today))
+}
+
+async fn digest(opt: &DigestOpt, db_opt: DatabaseOpt) -> Result<()> {
+    let today = chrono::offset::Utc::now().date_naive();
+    let date = opt.week.unwrap_or_else(|| digest::previous_week(today));
+    let digest = weekly_digest(db_opt, &opt.db_path, date)?;
+    println!("{digest}");
+    Notifiers::new(&opt.notify)?.notify_digest(&digest).await;
+    Ok(())
+}
+
+/// Send the digest of the previous week unless the journal shows that it has already been sent
+async fn send_weekly_digest(
+    db_opt: DatabaseOpt,
+    db_path: &Path,
+    notifiers: &Notifiers,
+) -> Result<()> {
+    let journal_path = journal::journal_path(db_path);
+    let week_start = digest::previous_week(chrono::offset::Utc::now().date_naive());
+    if journal::last_digest(&journal_path)?.is_some_and(|last| last >= week_start) {
+        return Ok(());
+    }
+    let digest = weekly_digest(db_opt, db_path, week_start)?;
+    notifiers.notify_digest(&digest).await;
+    journal::append(
+        &journal_path,
+        &JournalEntry::Digest {
+            unix: chrono::Utc::now().timestamp(),
+            week_start,
+        },
+    )
+}
+
+fn schema(opt: &SchemaOpt) -> Result<()> {
+    let schema = Schema::current();
+    if opt.json {
+        serde_json::to_writer_pretty(io::stdout().lock(), &schema)?;
+        println!();
+    } else {
+        print!("{schema}");
+    }
+    Ok(())
+}
 
+#[cfg(feature = "schema-check")]
+async fn schema_check(opt: &SchemaCheckOpt) -> Result<()> {
+    let client = opt.client.build_client()?;
     let today = chrono::offset::Utc::now().date_naive();
-    let stats_db = if opt.db_path.exists() {
-        Database::from_file(&opt.db_path).with_context(|| {
-            format!(
-                "Given file exists but does not contain a valid database: {}",
-                opt.db_path.display()
-            )
-        })?
+    let checks = crossword::api_schema::check_all(&client, today).await;
+    opt.client.record_usage(&client)?;
+    let mut breaking = 0;
+    for check in checks? {
+        if let Some(dir) = &opt.write {
+            std::fs::create_dir_all(dir)?;
+            let path = dir.join(format!("{}.json", check.endpoint.name()));
+            let file = File::create(&path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            serde_json::to_writer_pretty(file, &check.observed)?;
+        }
+        for drift in &check.drifts {
+            if drift.is_breaking() {
+                breaking += 1;
+                println!("{drift}");
+            } else if opt.show_added {
+                println!("{drift}");
+            }
+        }
+        println!("Checked {}", check.endpoint.name());
+    }
+    if breaking > 0 {
+        anyhow::bail!("{} fields differ from the expected schemas", breaking);
+    }
+    Ok(())
+}
+
+fn status(opt: &StatusOpt, db_opt: DatabaseOpt) -> Result<()> {
+    let records = db_opt.load_records(&opt.db_path)?;
+    let last_updated = std::fs::metadata(&opt.db_path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .map(chrono::DateTime::<chrono::Utc>::from);
+    let status_path = run_status::status_path(&opt.db_path);
+    let run_status = if status_path.exists() {
+        Some(run_status::load(&status_path)?)
     } else {
-        Database::new(opt.db_path)
+        None
     };
+    print!(
+        "{}",
+        Health::new(records.values(), last_updated).with_run_status(run_status)
+    );
+    print_goal_progress(&opt.db_path, &records, opt.all_remaining)
+}
+
+fn print_goal_progress(
+    db_path: &Path,
+    records: &HashMap<RecordKey, PuzzleStats>,
+    all_remaining: bool,
+) -> Result<()> {
+    let today = chrono::offset::Utc::now().date_naive();
+    for goal in goals::load(goals::goals_path(db_path))? {
+        let progress = goal.progress(records.values(), today);
+        if all_remaining {
+            println!("{progress:#}");
+        } else {
+            println!("{progress}");
+        }
+    }
+    Ok(())
+}
+
+fn goal(opt: GoalOpt, db_opt: DatabaseOpt) -> Result<()> {
+    match opt.command {
+        GoalCommand::Add {
+            name,
+            weekdays,
+            from,
+            to,
+            unassisted,
+            db_path,
+        } => {
+            let path = goals::goals_path(&db_path);
+            let mut goals = goals::load(&path)?;
+            if goals.iter().any(|goal| goal.name == name) {
+                anyhow::bail!("There is already a goal named {}", name);
+            }
+            let goal = Goal {
+                name,
+                weekdays,
+                start: from,
+                end: to,
+                unassisted,
+            };
+            println!("Added {goal}");
+            goals.push(goal);
+            goals::save(&path, &goals)
+        }
+        GoalCommand::List {
+            all_remaining,
+            db_path,
+        } => {
+            let records = db_opt.load_records(&db_path)?;
+            print_goal_progress(&db_path, &records, all_remaining)
+        }
+        GoalCommand::Remove { name, db_path } => {
+            let path = goals::goals_path(&db_path);
+            let mut goals = goals::load(&path)?;
+            let count = goals.len();
+            goals.retain(|goal| goal.name != name);
+            if goals.len() == count {
+                anyhow::bail!("There is no goal named {}", name);
+            }
+            goals::save(&path, &goals)
+        }
+    }
+}
+
+async fn archive(opt: ArchiveOpt, db_opt: DatabaseOpt) -> Result<()> {
+    let mut records: Vec<PuzzleStats> = db_opt.load_records(&opt.db_path)?.into_values().collect();
+    if !opt.all {
+        records.retain(PuzzleStats::is_solved);
+    }
+    records.sort_unstable_by_key(|r| r.date);
+    let dir = opt.dir.clone().unwrap_or_else(|| {
+        opt.db_path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join("puzzles")
+    });
+
+    let client = opt.client.build_client()?;
+    let summary = crossword::archive::archive(&client, &records, &dir).await;
+    opt.client.record_usage(&client)?;
+    let summary = summary?;
+    println!(
+        "{} saved, {} already archived, {} errors after {} requests",
+        summary.saved,
+        summary.skipped,
+        summary.errors,
+        client.n_requests()
+    );
+    Ok(())
+}
+
+async fn verify(opt: VerifyOpt, db_opt: DatabaseOpt) -> Result<()> {
+    let stats_db = db_opt.load(&opt.db_path)?;
+    let sample = crossword::verify::sample_solved(stats_db.solved(), opt.sample);
+    let client = opt.client.build_client()?;
+    if let Err(e) = client.validate_token().await {
+        opt.client.record_usage(&client)?;
+        return Err(e.into());
+    }
+
+    let mismatches = crossword::verify::verify_records(&client, &sample).await;
+    opt.client.record_usage(&client)?;
+    let mismatches = mismatches?;
+    for mismatch in &mismatches {
+        println!("{mismatch}");
+    }
+    println!(
+        "Checked {} records with {} requests: {} mismatched fields",
+        sample.len(),
+        client.n_requests(),
+        mismatches.len()
+    );
+    if !mismatches.is_empty() {
+        anyhow::bail!("Database does not match the NYT API");
+    }
+    Ok(())
+}
+
+async fn fetch_one(opt: FetchOneOpt, db_opt: DatabaseOpt) -> Result<()> {
+    let client = opt.client.build_client()?;
+    let record = crossword::search::fetch_one(&client, opt.publish_type, opt.date).await;
+    opt.client.record_usage(&client)?;
+    let mut record = record?;
+    record.derive_solved_on_pub_day(opt.timezone);
+
+    if opt.json {
+        println!("{}", serde_json::to_string_pretty(&record)?);
+    } else {
+        let id = record.puzzle_id.unwrap_or_default();
+        let state = match record.solve_time_secs {
+            Some(secs) if record.cheated.unwrap_or(false) => {
+                format!("solved in {}:{:02} with help", secs / 60, secs % 60)
+            }
+            Some(secs) => format!("solved in {}:{:02}", secs / 60, secs % 60),
+            None if record.is_in_progress() => format!(
+                "in progress ({}% filled)",
+                record.percent_filled.unwrap_or_default()
+            ),
+            None => "unsolved".to_string(),
+        };
+        println!(
+            "{} {} puzzle {} (id {}): {}",
+            record.date.weekday(),
+            record.publish_type,
+            record.date,
+            id,
+            state
+        );
+    }
 
-    let missing_ids = crossword::get_days_without_ids_chunked(
-        &stats_db,
-        opt.start_date,
-        today,
-        Duration::days(DAY_STEP),
+    if let Some(db_path) = &opt.save {
+        let mut stats_db = db_opt.open(db_path)?;
+        stats_db.add(record);
+        stats_db.close()?;
+    }
+    Ok(())
+}
+
+async fn fetch_streaks(opt: StreaksOpt) -> Result<()> {
+    let client = opt.client.build_client()?;
+    let stats = api_client::get_stats_and_streaks(&client).await;
+    opt.client.record_usage(&client)?;
+    let stats = stats?;
+    println!(
+        "Current streak: {} days (longest {}). Solved {} of {} puzzles attempted.",
+        stats.streaks.current_streak,
+        stats.streaks.longest_streak,
+        stats.stats.puzzles_solved,
+        stats.stats.puzzles_attempted
     );
-    let cached_unsolved = crossword::get_cached_unsolved_records(&stats_db, opt.start_date);
+    record_stats_and_streaks(&opt.db_path, chrono::offset::Utc::now().date_naive(), stats)
+}
 
-    let total_days = missing_ids.iter().map(Vec::len).sum::<usize>() + cached_unsolved.len();
+/// Add the official streaks to the streak history and save the lifetime stats alongside the
+/// database at the given path
+fn record_stats_and_streaks(
+    db_path: &Path,
+    today: NaiveDate,
+    stats: StatsAndStreaks,
+) -> Result<()> {
+    streaks::record(
+        streaks::history_path(db_path),
+        StreakSnapshot::new(today, &stats),
+    )?;
+    streaks::save_lifetime(
+        streaks::lifetime_path(db_path),
+        &LifetimeSnapshot {
+            date: today,
+            stats: stats.stats,
+        },
+    )
+}
+
+async fn fetch(opt: FetchOpt, db_opt: DatabaseOpt) -> Result<()> {
+    let db_path = opt.db_path.as_ref().context("No database path provided")?;
+    if opt.dry_run {
+        return dry_run(&opt, db_opt, db_path);
+    }
+    if opt.simulate {
+        return simulate(&opt, db_opt, db_path);
+    }
+    let notifiers = Arc::new(Notifiers::new(&opt.notify)?);
+    let status = Arc::new(Mutex::new(StatusFile::new(run_status::status_path(
+        db_path,
+    ))));
+    let shutdown = Shutdown::listen();
+    if !opt.daemon {
+        let result = fetch_once(&opt, db_opt, &notifiers, &status, &shutdown).await;
+        if let Err(e) = &result {
+            status.lock().unwrap().failed(e);
+            if !shutdown.is_requested() {
+                notifiers.notify_failure(e).await;
+            }
+        }
+        return result.map(|_| ());
+    }
+
+    let metrics = Arc::new(Metrics::default());
+    if let Some(addr) = opt.metrics_addr {
+        tokio::spawn(crossword::metrics::serve(addr, Arc::clone(&metrics)));
+    }
+    let interval = time::Duration::from_secs(u64::from(opt.interval.get()) * 3600);
+    let max_stale = opt
+        .alert_after_days
+        .map(|days| Duration::days(days.get().into()));
+    let mut watchdog = Watchdog::new(max_stale, chrono::Utc::now());
+    loop {
+        let result = fetch_once(&opt, db_opt, &notifiers, &status, &shutdown).await;
+        if shutdown.is_requested() {
+            if let Err(e) = &result {
+                status.lock().unwrap().failed(e);
+            }
+            return result.map(|_| ());
+        }
+        let alert = match result {
+            Ok(summary) => {
+                metrics.record_run(&summary);
+                watchdog.on_success(&summary, chrono::Utc::now())
+            }
+            Err(e) => {
+                error!("Run failed: {:?}", e);
+                status.lock().unwrap().failed(&e);
+                metrics.record_failed_run();
+                notifiers.notify_failure(&e).await;
+                watchdog.on_failure(chrono::Utc::now())
+            }
+        };
+        if let Some(alert) = alert {
+            notifiers.notify_alert(&alert).await;
+        }
+        info!("Next run in {} hours", opt.interval);
+        let next_run = chrono::Utc::now() + Duration::from_std(interval)?;
+        status.lock().unwrap().sleeping(next_run.timestamp());
+        tokio::select! {
+            () = tokio::time::sleep(interval) => (),
+            () = shutdown.requested() => return Ok(()),
+        }
+    }
+}
+
+/// Plan a fetch run, or load the plan given with `--plan` or the checkpoint of an interrupted run
+fn plan_fetch(opt: &FetchOpt, stats_db: &Database, today: NaiveDate) -> Result<FetchPlan> {
+    // clap enforces that these are present when no subcommand is given
+    let start_date = opt.start_date.context("No start date provided")?;
+    let db_path = opt.db_path.as_ref().context("No database path provided")?;
+    let checkpoint = plan::checkpoint_path(db_path);
+    let plan = match &opt.plan {
+        Some(path) => FetchPlan::load(path)?,
+        None if !opt.no_resume && checkpoint.exists() => {
+            let plan = FetchPlan::load(&checkpoint)?;
+            info!(
+                "Resuming the interrupted run saved in {} ({} items left)",
+                checkpoint.display(),
+                plan.len()
+            );
+            plan
+        }
+        None => FetchPlan::new(
+            stats_db,
+            opt.publish_type,
+            start_date,
+            today,
+            opt.only,
+            opt.force_refresh,
+        ),
+    };
+    Ok(match opt.max_requests {
+        Some(max_requests) => plan.with_budget(max_requests),
+        None => plan,
+    })
+}
+
+/// Plan a fetch run from the database at the given path, if it exists, without writing it
+fn plan_without_writing(opt: &FetchOpt, db_opt: DatabaseOpt, db_path: &Path) -> Result<FetchPlan> {
+    let records = if db_path.exists() {
+        db_opt.load_records(db_path)?
+    } else {
+        HashMap::new()
+    };
+    let stats_db = Database::from_storage(Box::new(MemoryStorage(Arc::new(Mutex::new(records)))))?;
+    let today = chrono::offset::Utc::now().date_naive();
+    plan_fetch(opt, &stats_db, today)
+}
+
+/// Print the plan of a fetch run without making any requests or writing the database
+fn dry_run(opt: &FetchOpt, db_opt: DatabaseOpt, db_path: &Path) -> Result<()> {
+    let plan = plan_without_writing(opt, db_opt, db_path)?;
+    println!("{}", serde_json::to_string_pretty(&plan)?);
+    eprintln!("{plan}");
+    Ok(())
+}
+
+/// Print an estimate of the cost of a fetch run without making any requests or writing the
+/// database
+fn simulate(opt: &FetchOpt, db_opt: DatabaseOpt, db_path: &Path) -> Result<()> {
+    let plan = plan_without_writing(opt, db_opt, db_path)?;
+    let history = journal::fetch_history(&journal::journal_path(db_path))?;
+    println!(
+        "{}",
+        plan.simulate(opt.client.request_quota, history, opt.client.retries)
+    );
+    Ok(())
+}
+
+/// Fetch missing stats into the database once
+async fn fetch_once(
+    opt: &FetchOpt,
+    db_opt: DatabaseOpt,
+    notifiers: &Arc<Notifiers>,
+    status: &Arc<Mutex<StatusFile>>,
+    shutdown: &Shutdown,
+) -> Result<RunSummary> {
+    // clap enforces that these are present when no subcommand is given
+    let start_date = opt.start_date.context("No start date provided")?;
+    let db_path = opt.db_path.as_ref().context("No database path provided")?;
+    status.lock().unwrap().start();
+
+    let today = chrono::offset::Utc::now().date_naive();
+    let stats_db = db_opt.open(db_path)?;
+
+    let client = opt.client.build_client()?;
+    if let Err(e) = client.validate_token().await {
+        opt.client.record_usage(&client)?;
+        return Err(e.into());
+    }
+
+    match api_client::get_stats_and_streaks(&client).await {
+        Ok(stats) => record_stats_and_streaks(db_path, today, stats)?,
+        Err(e) => warn!("Couldn't fetch official streaks: {:#}", e),
+    }
+
+    let plan = plan_fetch(opt, &stats_db, today)?;
+    let checkpoint = Checkpoint::new(
+        plan::checkpoint_path(db_path),
+        plan.clone(),
+        opt.checkpoint_every,
+        time::Duration::from_secs(opt.checkpoint_secs.get().into()),
+    );
+    let (missing_ids, to_refresh) = plan.resolve(&stats_db);
+    #[cfg(feature = "exporters")]
+    let touched_months = crossword::report::months(
+        missing_ids
+            .iter()
+            .flatten()
+            .chain(&to_refresh)
+            .map(|r| r.date),
+    );
+
+    let total_days = missing_ids.iter().map(Vec::len).sum::<usize>() + to_refresh.len();
+    status.lock().unwrap().fetching(total_days.try_into()?);
     let progress = ProgressBar::new(total_days.try_into()?).with_style(
         ProgressStyle::default_bar()
             .template("▕{bar:40}▏{eta} {percent}% {msg}")?
@@ -95,40 +1667,329 @@ async fn main() -> Result<()> {
 
     let msg = format!(
         "Fetching NYT crossword stats since {}",
-        &opt.start_date.to_string()
+        &start_date.to_string()
     );
     progress.println(msg);
 
+    let countdown = tokio::spawn(show_maintenance_countdown(
+        client.clone(),
+        progress.clone(),
+        Arc::clone(status),
+    ));
+    #[cfg(unix)]
+    let quota_signals = tokio::spawn(adjust_quota_on_signal(client.clone(), progress.clone()));
     let (tx, rx) = mpsc::unbounded_channel();
-    let logger_handle = tokio::spawn(logger::task_fn(rx, stats_db, progress));
-
-    let token = if let Some(header) = opt.subscription_token.nyt_header {
-        SubscriptionToken::Header(header)
-    } else if let Some(cookie) = opt.subscription_token.nyt_cookie {
-        SubscriptionToken::Cookie(cookie)
-    } else {
-        anyhow::bail!("No NYT subscription token provided");
-    };
-    let client = RateLimitedClient::new(token, opt.request_quota);
+    let logger_handle = tokio::spawn(logger::task_fn(
+        rx,
+        stats_db,
+        progress.clone(),
+        start_date,
+        Arc::clone(notifiers),
+        opt.timezone,
+        opt.percent_rank,
+        Arc::clone(status),
+        checkpoint,
+    ));
 
-    let ids_task = tokio::spawn(crossword::search::fetch_ids_and_stats(
+    let limit = Arc::new(Semaphore::new(opt.concurrency.get()));
+    let mut ids_task = tokio::spawn(crossword::search::fetch_ids_and_stats(
         client.clone(),
         missing_ids,
         tx.clone(),
+        IdLookupPolicy {
+            max_attempts: opt.id_lookup_retries + 1,
+            max_splits: opt.id_lookup_splits,
+            ..Default::default()
+        },
+        Arc::clone(&limit),
     ));
-    let unsolved_task = tokio::spawn(crossword::search::fetch_missing_times(
+    let mut unsolved_task = tokio::spawn(crossword::search::fetch_missing_times(
         client.clone(),
-        cached_unsolved,
+        to_refresh,
         tx.clone(),
+        limit,
     ));
 
-    if let Err(e) = ids_task.await? {
-        warn!("Error in fetch_ids_and_stats: {}", e);
+    let mut interrupted = tokio::select! {
+        () = async {
+            crossword::search::report_join_result(
+                "fetch_ids_and_stats",
+                (&mut ids_task).await,
+                &tx,
+            );
+            crossword::search::report_join_result(
+                "fetch_missing_times",
+                (&mut unsolved_task).await,
+                &tx,
+            );
+        } => false,
+        () = shutdown.requested() => {
+            ids_task.abort();
+            unsolved_task.abort();
+            true
+        }
     };
-    if let Err(e) = unsolved_task.await? {
-        warn!("Error in fetch_missing_times: {}", e);
+    if !interrupted {
+        interrupted = retry_failed(opt, &client, &tx, &progress, shutdown).await?;
+    }
+    if interrupted {
+        tx.send(logger::Payload::Interrupted(client.n_requests()))?;
+    } else {
+        tx.send(logger::Payload::Finished(client.n_requests()))?;
+    }
+    // After an interruption, the logger saves results until the cancelled tasks drop their senders
+    drop(tx);
+    countdown.abort();
+    #[cfg(unix)]
+    quota_signals.abort();
+    let summary = logger_handle.await??;
+    if summary.interrupted {
+        opt.client.record_usage(&client)?;
+        journal::append(
+            &journal::journal_path(db_path),
+            &JournalEntry::Run {
+                unix: chrono::Utc::now().timestamp(),
+                summary,
+            },
+        )?;
+        anyhow::bail!("Run interrupted. Run again to resume it.");
+    }
+    if client.auth_circuit_open() {
+        // The database has been flushed with everything fetched before the token was rejected
+        opt.client.record_usage(&client)?;
+        anyhow::bail!(ApiError::AuthCircuitOpen {
+            failures: opt.client.max_auth_failures
+        });
+    }
+    if client.n_not_modified() > 0 {
+        info!(
+            "{} of {} requests were unchanged since they were cached",
+            client.n_not_modified(),
+            client.n_requests()
+        );
+    }
+    opt.client.record_usage(&client)?;
+    #[cfg(feature = "exporters")]
+    if let Some(dir) = &opt.auto_report {
+        let records: Vec<PuzzleStats> = db_opt.load_records(db_path)?.into_values().collect();
+        crossword::report::write_monthly_reports(dir, &records, &touched_months)?;
+        let goals = goals::load(goals::goals_path(db_path))?;
+        if !goals.is_empty() {
+            let progress: Vec<_> = goals
+                .iter()
+                .map(|goal| goal.progress(&records, today))
+                .collect();
+            let path = crossword::report::goals_path(dir);
+            std::fs::write(&path, crossword::report::goals_markdown(&progress))
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+    }
+    journal::append(
+        &journal::journal_path(db_path),
+        &JournalEntry::Run {
+            unix: chrono::Utc::now().timestamp(),
+            summary: summary.clone(),
+        },
+    )?;
+    notifiers.notify_summary(&summary).await;
+    if opt.weekly_digest {
+        send_weekly_digest(db_opt, db_path, notifiers).await?;
+    }
+    if let Some(hook) = &opt.post_run_hook {
+        run_hook(hook, &summary)?;
+    }
+    if summary.task_failures > 0 {
+        anyhow::bail!(
+            "{} fetch tasks failed unexpectedly, so some puzzles may be missing from the database",
+            summary.task_failures
+        );
+    }
+    if summary.lookup_failures > 0 {
+        anyhow::bail!(
+            "The ids of {} puzzles couldn't be looked up, so they're missing from the database",
+            summary.lookup_failures
+        );
+    }
+    status.lock().unwrap().set_phase(Phase::Finished);
+    Ok(summary)
+}
+
+/// Fetch the stats of the puzzles that failed to fetch during a run again, in up to
+/// `--retry-passes` passes. Each pass waits longer before starting than the one before, and fetches
+/// one puzzle at a time to go easy on an API that's struggling.
+///
+/// Returns whether the run was interrupted while retrying
+async fn retry_failed(
+    opt: &FetchOpt,
+    client: &RateLimitedClient,
+    tx: &mpsc::UnboundedSender<logger::Payload>,
+    progress: &ProgressBar,
+    shutdown: &Shutdown,
+) -> Result<bool> {
+    for pass in 1..=opt.retry_passes {
+        // Retrying is pointless once the token has been rejected
+        if client.auth_circuit_open() {
+            break;
+        }
+        let (reply, failed) = oneshot::channel();
+        tx.send(logger::Payload::EndOfPass(reply))?;
+        let failed = failed.await?;
+        if failed.is_empty() {
+            break;
+        }
+        progress.inc_length(failed.len().try_into()?);
+        progress.println(format!(
+            "🔁 Retrying {} puzzles whose stats failed to fetch (pass {} of {})",
+            failed.len(),
+            pass,
+            opt.retry_passes
+        ));
+        let delay = time::Duration::from_secs(opt.retry_delay.into()) * pass;
+        let mut task = tokio::spawn({
+            let (client, tx) = (client.clone(), tx.clone());
+            async move {
+                tokio::time::sleep(delay).await;
+                let limit = Arc::new(Semaphore::new(1));
+                crossword::search::fetch_missing_times(client, failed, tx, limit).await
+            }
+        });
+        tokio::select! {
+            result = &mut task => {
+                crossword::search::report_join_result("fetch_missing_times", result, tx);
+            }
+            () = shutdown.requested() => {
+                task.abort();
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Whether the process was asked to stop with Ctrl+C, or SIGTERM on Unix. Fetch runs stop by
+/// saving what they fetched so far instead of being killed. Asking again exits immediately.
+#[derive(Debug, Clone)]
+struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    /// Start listening for requests to stop
+    fn listen() -> Self {
+        let (tx, rx) = watch::channel(false);
+        tokio::spawn(async move {
+            if let Err(e) = shutdown_signal().await {
+                warn!("Can't listen for Ctrl+C: {}", e);
+                return;
+            }
+            warn!("Stopping once progress is saved. Press Ctrl+C again to exit immediately.");
+            // The receivers live as long as the fetch loop
+            let _ = tx.send(true);
+            if shutdown_signal().await.is_ok() {
+                process::exit(130);
+            }
+        });
+        Self(rx)
+    }
+
+    fn is_requested(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Wait until the process is asked to stop
+    async fn requested(&self) {
+        let mut rx = self.0.clone();
+        if rx.wait_for(|stop| *stop).await.is_err() {
+            // The listener couldn't be installed, so a request will never come
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+/// Wait for Ctrl+C, or SIGTERM on Unix
+async fn shutdown_signal() -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await
+}
+
+/// Show how long requests are paused for while the NYT API is down for maintenance
+async fn show_maintenance_countdown(
+    client: RateLimitedClient,
+    progress: ProgressBar,
+    status: Arc<Mutex<StatusFile>>,
+) {
+    let mut paused = false;
+    loop {
+        match client.maintenance_remaining() {
+            Some(remaining) => {
+                progress.set_message(format!(
+                    "⏸️ NYT API is down for maintenance. Resuming in {}s",
+                    remaining.as_secs() + 1
+                ));
+                if !paused {
+                    status.lock().unwrap().set_phase(Phase::Paused);
+                    paused = true;
+                }
+            }
+            None if paused => {
+                progress.set_message("");
+                status.lock().unwrap().set_phase(Phase::Fetching);
+                paused = false;
+            }
+            None => (),
+        }
+        tokio::time::sleep(time::Duration::from_secs(1)).await;
+    }
+}
+
+/// Raise the request quota by one request per second on SIGUSR1 and lower it by one on SIGUSR2, so
+/// that a long run can be sped up or slowed down without restarting it
+#[cfg(unix)]
+async fn adjust_quota_on_signal(client: RateLimitedClient, progress: ProgressBar) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let (mut raise, mut lower) = match (
+        signal(SignalKind::user_defined1()),
+        signal(SignalKind::user_defined2()),
+    ) {
+        (Ok(raise), Ok(lower)) => (raise, lower),
+        (Err(e), _) | (_, Err(e)) => {
+            warn!("Can't listen for quota adjustment signals: {}", e);
+            return;
+        }
     };
-    tx.send(logger::Payload::Finished(client.n_requests()))?;
-    logger_handle.await??;
+    loop {
+        let quota = client.quota();
+        let quota = tokio::select! {
+            _ = raise.recv() => quota.saturating_add(1),
+            _ = lower.recv() => NonZeroU32::new(quota.get() - 1).unwrap_or(NonZeroU32::MIN),
+        };
+        client.set_quota(quota);
+        progress.println(format!("Request quota set to {} requests/sec", quota));
+    }
+}
+
+/// Run the given program, passing it the run summary as JSON on stdin
+fn run_hook(hook: &Path, summary: &RunSummary) -> Result<()> {
+    let mut child = process::Command::new(hook)
+        .stdin(process::Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run post-run hook {}", hook.display()))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        serde_json::to_writer(&mut stdin, summary)?;
+        stdin.write_all(b"\n")?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        anyhow::bail!("Post-run hook {} exited with {}", hook.display(), status);
+    }
     Ok(())
 }
@@ -0,0 +1,164 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rules for combining records of the same puzzle from two databases, e.g. when migrating solves
+//! from an old NYT account into the database for a new one
+
+use crate::diff::{self, FieldChange};
+use crate::{PuzzleStats, RecordKey};
+use serde::Serialize;
+use std::fmt;
+
+/// Columns describing how a puzzle was solved. Two solved records that differ in any of them are
+/// reported as a conflict when merged.
+const SOLVE_COLUMNS: [&str; 3] = ["solve_time_secs", "cheated", "solved_unix"];
+
+/// Which record to keep when both databases have a solve for the same puzzle. Regardless of the
+/// rule, a solved record always takes precedence over an unsolved or missing one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Precedence {
+    /// Keep the record already in the database
+    #[default]
+    Primary,
+    /// Replace with the record being merged in
+    Other,
+    /// Keep whichever record was solved first
+    Earliest,
+    /// Keep the faster unassisted solve, falling back to the faster assisted solve
+    Fastest,
+    /// Keep the record with the most columns filled in, e.g. to combine databases fetched on two
+    /// machines
+    MostComplete,
+}
+
+impl Precedence {
+    /// Choose between two records of the same puzzle
+    #[must_use]
+    pub fn choose(self, primary: PuzzleStats, other: PuzzleStats) -> PuzzleStats {
+        // Puzzle ids and descriptive fields are the same for every account, so fill in any that
+        // are missing from the chosen record
+        let primary_id = primary.puzzle_id;
+        let other_id = other.puzzle_id;
+        let author = primary.author.clone().or_else(|| other.author.clone());
+        let editor = primary.editor.clone().or_else(|| other.editor.clone());
+        let title = primary.title.clone().or_else(|| other.title.clone());
+        let mut chosen = match (primary.is_solved(), other.is_solved()) {
+            (true, false) => primary,
+            (false, true) => other,
+            (false, false) => primary,
+            (true, true) => match self {
+                Self::Primary => primary,
+                Self::Other => other,
+                Self::Earliest => {
+                    // Records without a solve timestamp sort last
+                    let key = |r: &PuzzleStats| r.solved_unix.unwrap_or(u32::MAX);
+                    if key(&other) < key(&primary) {
+                        other
+                    } else {
+                        primary
+                    }
+                }
+                Self::MostComplete => {
+                    if other.filled_columns() > primary.filled_columns() {
+                        other
+                    } else {
+                        primary
+                    }
+                }
+                Self::Fastest => {
+                    let key = |r: &PuzzleStats| {
+                        (
+                            r.cheated.unwrap_or(false),
+                            r.solve_time_secs.unwrap_or(u32::MAX),
+                        )
+                    };
+                    if key(&other) < key(&primary) {
+                        other
+                    } else {
+                        primary
+                    }
+                }
+            },
+        };
+        chosen.puzzle_id = chosen.puzzle_id.or(primary_id).or(other_id);
+        chosen.author = chosen.author.or(author);
+        chosen.editor = chosen.editor.or(editor);
+        chosen.title = chosen.title.or(title);
+        chosen
+    }
+}
+
+/// Two solves of the same puzzle that disagree about how it was solved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub key: RecordKey,
+    /// Solve columns that differ, from the existing record to the merged-in one
+    pub fields: Vec<FieldChange>,
+    /// Whether the merged-in record replaced the existing one
+    pub replaced: bool,
+}
+
+impl Conflict {
+    /// The conflict between an existing record and one being merged in, if both are solved and
+    /// differ in how
+    #[must_use]
+    pub fn between(existing: &PuzzleStats, other: &PuzzleStats) -> Option<Self> {
+        if !existing.is_solved() || !other.is_solved() {
+            return None;
+        }
+        let fields: Vec<FieldChange> = diff::field_changes(existing, other)
+            .into_iter()
+            .filter(|change| SOLVE_COLUMNS.contains(&change.field.as_str()))
+            .collect();
+        (!fields.is_empty()).then(|| Self {
+            key: existing.key(),
+            fields,
+            replaced: false,
+        })
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kept = if self.replaced {
+            "merged-in"
+        } else {
+            "existing"
+        };
+        write!(
+            f,
+            "! {}, kept the {} solve",
+            diff::format_key(self.key),
+            kept
+        )?;
+        for field in &self.fields {
+            write!(f, "\n    {field}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Counts of how records were combined during a merge
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MergeSummary {
+    /// Dates only present in the merged-in database
+    pub added: u32,
+    /// Dates where the merged-in record replaced the existing one
+    pub replaced: u32,
+    /// Dates present in both databases where the existing record was kept
+    pub kept: u32,
+    /// Puzzles solved in both databases whose solves differ
+    #[serde(skip)]
+    pub conflicts: Vec<Conflict>,
+}
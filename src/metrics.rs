@@ -0,0 +1,155 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Run metrics for daemon mode, served in the Prometheus text exposition format
+
+use crate::logger::RunSummary;
+use anyhow::Result;
+use log::{debug, info};
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Counters and gauges describing the runs made by a long-running daemon
+#[derive(Debug, Default)]
+pub struct Metrics {
+    runs: AtomicU64,
+    failed_runs: AtomicU64,
+    requests: AtomicU64,
+    errors: AtomicU64,
+    records_added: AtomicU64,
+    current_streak: AtomicU64,
+    last_run_timestamp: AtomicI64,
+}
+
+impl Metrics {
+    /// Record a completed run
+    pub fn record_run(&self, summary: &RunSummary) {
+        self.runs.fetch_add(1, Ordering::Relaxed);
+        self.requests
+            .fetch_add(u64::from(summary.n_requests), Ordering::Relaxed);
+        self.errors
+            .fetch_add(u64::from(summary.errors), Ordering::Relaxed);
+        self.records_added.fetch_add(
+            u64::from(summary.solved + summary.unsolved),
+            Ordering::Relaxed,
+        );
+        self.current_streak
+            .store(u64::from(summary.current_streak), Ordering::Relaxed);
+        self.last_run_timestamp
+            .store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+    }
+
+    /// Record a run that failed before producing a summary
+    pub fn record_failed_run(&self) {
+        self.runs.fetch_add(1, Ordering::Relaxed);
+        self.failed_runs.fetch_add(1, Ordering::Relaxed);
+        self.last_run_timestamp
+            .store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+    }
+
+    /// Render all metrics in the Prometheus text exposition format
+    #[must_use]
+    pub fn render(&self) -> String {
+        let metrics: [(&str, &str, &str, i128); 7] = [
+            (
+                "crossword_runs_total",
+                "counter",
+                "Number of fetch runs started",
+                self.runs.load(Ordering::Relaxed).into(),
+            ),
+            (
+                "crossword_failed_runs_total",
+                "counter",
+                "Number of fetch runs that failed",
+                self.failed_runs.load(Ordering::Relaxed).into(),
+            ),
+            (
+                "crossword_requests_total",
+                "counter",
+                "Number of HTTP requests made to the NYT API",
+                self.requests.load(Ordering::Relaxed).into(),
+            ),
+            (
+                "crossword_fetch_errors_total",
+                "counter",
+                "Number of puzzles whose stats could not be fetched",
+                self.errors.load(Ordering::Relaxed).into(),
+            ),
+            (
+                "crossword_records_added_total",
+                "counter",
+                "Number of records written to the database",
+                self.records_added.load(Ordering::Relaxed).into(),
+            ),
+            (
+                "crossword_current_streak",
+                "gauge",
+                "Number of consecutive days solved, as of the last run",
+                self.current_streak.load(Ordering::Relaxed).into(),
+            ),
+            (
+                "crossword_last_run_timestamp_seconds",
+                "gauge",
+                "Unix time at which the last run finished",
+                self.last_run_timestamp.load(Ordering::Relaxed).into(),
+            ),
+        ];
+
+        let mut out = String::new();
+        for (name, kind, help, value) in metrics {
+            // Writing to a String can't fail
+            let _ = writeln!(
+                out,
+                "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}"
+            );
+        }
+        out
+    }
+}
+
+/// Serve the given metrics over HTTP at `/metrics` until the task is cancelled
+pub async fn serve(addr: SocketAddr, metrics: Arc<Metrics>) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("Serving metrics at http://{}/metrics", addr);
+    loop {
+        let (mut stream, peer) = listener.accept().await?;
+        let metrics = Arc::clone(&metrics);
+        tokio::spawn(async move {
+            let mut buf = [0; 1024];
+            let Ok(n) = stream.read(&mut buf).await else {
+                return;
+            };
+            let request = String::from_utf8_lossy(&buf[..n]);
+            let path = request.split_whitespace().nth(1).unwrap_or_default();
+            debug!("Metrics request for {} from {}", path, peer);
+            let response = if path == "/metrics" {
+                let body = metrics.render();
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_string()
+            };
+            let _ = stream.write_all(response.as_bytes()).await;
+        });
+    }
+}
@@ -0,0 +1,399 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Notifications about solves and runs, sent to any number of sinks at once
+
+use crate::digest::WeeklyDigest;
+use crate::logger::RunSummary;
+use crate::PuzzleStats;
+use anyhow::Result;
+use futures::future::{join_all, BoxFuture};
+use log::warn;
+use std::str::FromStr;
+
+/// A destination for notifications
+pub trait Notifier: Send + Sync {
+    /// A newly-fetched puzzle was found to be solved
+    fn notify_solve<'a>(&'a self, puzzle: &'a PuzzleStats) -> BoxFuture<'a, Result<()>>;
+
+    /// A run finished successfully
+    fn notify_summary<'a>(&'a self, summary: &'a RunSummary) -> BoxFuture<'a, Result<()>>;
+
+    /// A run failed
+    fn notify_failure<'a>(&'a self, error: &'a anyhow::Error) -> BoxFuture<'a, Result<()>>;
+
+    /// Something needs the user's attention, e.g. fetches have been failing for days
+    fn notify_alert<'a>(&'a self, message: &'a str) -> BoxFuture<'a, Result<()>>;
+
+    /// A summary of the solves of the past week
+    fn notify_digest<'a>(&'a self, digest: &'a WeeklyDigest) -> BoxFuture<'a, Result<()>>;
+}
+
+#[cfg(any(
+    feature = "notify-slack",
+    feature = "notify-discord",
+    feature = "notify-desktop"
+))]
+fn solve_message(puzzle: &PuzzleStats) -> String {
+    let assisted = if puzzle.cheated.unwrap_or(false) {
+        " (assisted)"
+    } else {
+        ""
+    };
+    match puzzle.solve_time_secs {
+        Some(secs) => format!(
+            "🧩 Solved the {} crossword in {}:{:02}{}",
+            puzzle.date,
+            secs / 60,
+            secs % 60,
+            assisted
+        ),
+        None => format!("🧩 Solved the {} crossword{}", puzzle.date, assisted),
+    }
+}
+
+#[cfg(any(
+    feature = "notify-slack",
+    feature = "notify-discord",
+    feature = "notify-desktop"
+))]
+fn summary_message(summary: &RunSummary) -> String {
+    format!(
+        "🎉 Crossword run finished after {} requests: {} solved, {} unsolved, {} errors. \
+         Current streak: {} days",
+        summary.n_requests,
+        summary.solved,
+        summary.unsolved,
+        summary.errors,
+        summary.current_streak
+    )
+}
+
+#[cfg(any(
+    feature = "notify-slack",
+    feature = "notify-discord",
+    feature = "notify-desktop"
+))]
+fn failure_message(error: &anyhow::Error) -> String {
+    format!("⚠️ Crossword run failed: {error:#}")
+}
+
+#[cfg(any(
+    feature = "notify-slack",
+    feature = "notify-discord",
+    feature = "notify-desktop"
+))]
+fn alert_message(message: &str) -> String {
+    format!("🚨 {message}")
+}
+
+/// Post a JSON body to the given URL, failing on a non-success status
+#[cfg(any(
+    feature = "notify-webhook",
+    feature = "notify-slack",
+    feature = "notify-discord"
+))]
+async fn post_json(client: &reqwest::Client, url: &str, body: &serde_json::Value) -> Result<()> {
+    client
+        .post(url)
+        .json(body)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Posts a JSON description of each event to an arbitrary URL
+///
+/// The body has an `event` field of `solve`, `summary`, `failure`, `alert`, or `digest`, along
+/// with the puzzle, run summary, error message, alert message, or weekly digest respectively.
+#[cfg(feature = "notify-webhook")]
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    url: String,
+}
+
+#[cfg(feature = "notify-webhook")]
+impl WebhookNotifier {
+    #[must_use]
+    pub fn new(url: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+        }
+    }
+}
+
+#[cfg(feature = "notify-webhook")]
+impl Notifier for WebhookNotifier {
+    fn notify_solve<'a>(&'a self, puzzle: &'a PuzzleStats) -> BoxFuture<'a, Result<()>> {
+        let body = serde_json::json!({ "event": "solve", "puzzle": puzzle });
+        Box::pin(async move { post_json(&self.client, &self.url, &body).await })
+    }
+
+    fn notify_summary<'a>(&'a self, summary: &'a RunSummary) -> BoxFuture<'a, Result<()>> {
+        let body = serde_json::json!({ "event": "summary", "summary": summary });
+        Box::pin(async move { post_json(&self.client, &self.url, &body).await })
+    }
+
+    fn notify_failure<'a>(&'a self, error: &'a anyhow::Error) -> BoxFuture<'a, Result<()>> {
+        let body = serde_json::json!({ "event": "failure", "error": format!("{error:#}") });
+        Box::pin(async move { post_json(&self.client, &self.url, &body).await })
+    }
+
+    fn notify_alert<'a>(&'a self, message: &'a str) -> BoxFuture<'a, Result<()>> {
+        let body = serde_json::json!({ "event": "alert", "message": message });
+        Box::pin(async move { post_json(&self.client, &self.url, &body).await })
+    }
+
+    fn notify_digest<'a>(&'a self, digest: &'a WeeklyDigest) -> BoxFuture<'a, Result<()>> {
+        let body = serde_json::json!({ "event": "digest", "digest": digest });
+        Box::pin(async move { post_json(&self.client, &self.url, &body).await })
+    }
+}
+
+/// Chat service that accepts plain-text messages via an incoming webhook
+#[cfg(any(feature = "notify-slack", feature = "notify-discord"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatService {
+    #[cfg(feature = "notify-slack")]
+    Slack,
+    #[cfg(feature = "notify-discord")]
+    Discord,
+}
+
+/// Posts a human-readable message for each event to a Slack or Discord incoming webhook
+#[cfg(any(feature = "notify-slack", feature = "notify-discord"))]
+pub struct ChatNotifier {
+    client: reqwest::Client,
+    service: ChatService,
+    url: String,
+}
+
+#[cfg(any(feature = "notify-slack", feature = "notify-discord"))]
+impl ChatNotifier {
+    #[must_use]
+    pub fn new(service: ChatService, url: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            service,
+            url,
+        }
+    }
+
+    fn send(&self, message: String) -> BoxFuture<'_, Result<()>> {
+        // Slack and Discord only differ in the name of the field holding the message
+        let body = match self.service {
+            #[cfg(feature = "notify-slack")]
+            ChatService::Slack => serde_json::json!({ "text": message }),
+            #[cfg(feature = "notify-discord")]
+            ChatService::Discord => serde_json::json!({ "content": message }),
+        };
+        Box::pin(async move { post_json(&self.client, &self.url, &body).await })
+    }
+}
+
+#[cfg(any(feature = "notify-slack", feature = "notify-discord"))]
+impl Notifier for ChatNotifier {
+    fn notify_solve<'a>(&'a self, puzzle: &'a PuzzleStats) -> BoxFuture<'a, Result<()>> {
+        self.send(solve_message(puzzle))
+    }
+
+    fn notify_summary<'a>(&'a self, summary: &'a RunSummary) -> BoxFuture<'a, Result<()>> {
+        self.send(summary_message(summary))
+    }
+
+    fn notify_failure<'a>(&'a self, error: &'a anyhow::Error) -> BoxFuture<'a, Result<()>> {
+        self.send(failure_message(error))
+    }
+
+    fn notify_alert<'a>(&'a self, message: &'a str) -> BoxFuture<'a, Result<()>> {
+        self.send(alert_message(message))
+    }
+
+    fn notify_digest<'a>(&'a self, digest: &'a WeeklyDigest) -> BoxFuture<'a, Result<()>> {
+        self.send(digest.to_string())
+    }
+}
+
+/// Shows a desktop notification for each event using the platform's notification tool:
+/// `notify-send` on Linux and `osascript` on macOS
+#[cfg(feature = "notify-desktop")]
+#[derive(Debug, Default)]
+pub struct DesktopNotifier;
+
+#[cfg(feature = "notify-desktop")]
+impl DesktopNotifier {
+    fn send(message: String) -> BoxFuture<'static, Result<()>> {
+        Box::pin(async move {
+            let mut command = if cfg!(target_os = "macos") {
+                let mut command = tokio::process::Command::new("osascript");
+                command.arg("-e").arg(format!(
+                    "display notification {message:?} with title \"NYT Crossword\""
+                ));
+                command
+            } else {
+                let mut command = tokio::process::Command::new("notify-send");
+                command.arg("NYT Crossword").arg(message);
+                command
+            };
+            let status = command.status().await?;
+            anyhow::ensure!(
+                status.success(),
+                "Notification command exited with {status}"
+            );
+            Ok(())
+        })
+    }
+}
+
+#[cfg(feature = "notify-desktop")]
+impl Notifier for DesktopNotifier {
+    fn notify_solve<'a>(&'a self, puzzle: &'a PuzzleStats) -> BoxFuture<'a, Result<()>> {
+        Self::send(solve_message(puzzle))
+    }
+
+    fn notify_summary<'a>(&'a self, summary: &'a RunSummary) -> BoxFuture<'a, Result<()>> {
+        Self::send(summary_message(summary))
+    }
+
+    fn notify_failure<'a>(&'a self, error: &'a anyhow::Error) -> BoxFuture<'a, Result<()>> {
+        Self::send(failure_message(error))
+    }
+
+    fn notify_alert<'a>(&'a self, message: &'a str) -> BoxFuture<'a, Result<()>> {
+        Self::send(alert_message(message))
+    }
+
+    fn notify_digest<'a>(&'a self, digest: &'a WeeklyDigest) -> BoxFuture<'a, Result<()>> {
+        Self::send(digest.to_string())
+    }
+}
+
+/// Command-line description of a notification sink, in the form `<kind>=<url>` or `desktop`
+///
+/// Supported kinds depend on the features the crate was built with: `webhook`, `slack`,
+/// `discord`, and `desktop`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotifierConfig {
+    Webhook(String),
+    Slack(String),
+    Discord(String),
+    Desktop,
+}
+
+impl FromStr for NotifierConfig {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (kind, url) = match s.split_once('=') {
+            Some((kind, url)) => (kind, Some(url.to_string())),
+            None => (s, None),
+        };
+        let config = match (kind, url) {
+            ("webhook", Some(url)) => Self::Webhook(url),
+            ("slack", Some(url)) => Self::Slack(url),
+            ("discord", Some(url)) => Self::Discord(url),
+            ("desktop", None) => Self::Desktop,
+            ("webhook" | "slack" | "discord", None) => {
+                anyhow::bail!("Expected a URL for the {kind} notifier, e.g. {kind}=https://...")
+            }
+            _ => anyhow::bail!("Unknown notifier {s}"),
+        };
+        // Reject sinks that weren't compiled in up front rather than failing at the end of a run
+        config.build()?;
+        Ok(config)
+    }
+}
+
+impl NotifierConfig {
+    /// Name of the sink, as given on the command line
+    #[must_use]
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Webhook(_) => "webhook",
+            Self::Slack(_) => "slack",
+            Self::Discord(_) => "discord",
+            Self::Desktop => "desktop",
+        }
+    }
+
+    /// Create the notifier described by this config
+    pub fn build(&self) -> Result<Box<dyn Notifier>> {
+        match self {
+            #[cfg(feature = "notify-webhook")]
+            Self::Webhook(url) => Ok(Box::new(WebhookNotifier::new(url.clone()))),
+            #[cfg(feature = "notify-slack")]
+            Self::Slack(url) => Ok(Box::new(ChatNotifier::new(ChatService::Slack, url.clone()))),
+            #[cfg(feature = "notify-discord")]
+            Self::Discord(url) => Ok(Box::new(ChatNotifier::new(
+                ChatService::Discord,
+                url.clone(),
+            ))),
+            #[cfg(feature = "notify-desktop")]
+            Self::Desktop => Ok(Box::new(DesktopNotifier)),
+            #[allow(unreachable_patterns)]
+            _ => anyhow::bail!(
+                "This build does not support {} notifications. Rebuild with the notify-{} feature.",
+                self.kind(),
+                self.kind()
+            ),
+        }
+    }
+}
+
+/// Fans each notification out to all configured sinks. Failures are logged rather than returned
+/// so that a broken sink never fails a run.
+#[derive(Default)]
+pub struct Notifiers(Vec<Box<dyn Notifier>>);
+
+impl Notifiers {
+    pub fn new(configs: &[NotifierConfig]) -> Result<Self> {
+        Ok(Self(
+            configs
+                .iter()
+                .map(NotifierConfig::build)
+                .collect::<Result<_>>()?,
+        ))
+    }
+
+    fn log_failures(results: Vec<Result<()>>) {
+        for result in results {
+            if let Err(e) = result {
+                warn!("Failed to send notification: {:#}", e);
+            }
+        }
+    }
+
+    pub async fn notify_solve(&self, puzzle: &PuzzleStats) {
+        Self::log_failures(join_all(self.0.iter().map(|n| n.notify_solve(puzzle))).await);
+    }
+
+    pub async fn notify_summary(&self, summary: &RunSummary) {
+        Self::log_failures(join_all(self.0.iter().map(|n| n.notify_summary(summary))).await);
+    }
+
+    pub async fn notify_failure(&self, error: &anyhow::Error) {
+        Self::log_failures(join_all(self.0.iter().map(|n| n.notify_failure(error))).await);
+    }
+
+    pub async fn notify_alert(&self, message: &str) {
+        warn!("{}", message);
+        Self::log_failures(join_all(self.0.iter().map(|n| n.notify_alert(message))).await);
+    }
+
+    pub async fn notify_digest(&self, digest: &WeeklyDigest) {
+        Self::log_failures(join_all(self.0.iter().map(|n| n.notify_digest(digest))).await);
+    }
+}
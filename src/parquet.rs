@@ -0,0 +1,341 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Export of records as Parquet, so that they can be loaded into pandas or Polars with the column
+//! types of `schema::Schema` instead of being inferred from CSV. Only the small part of the format
+//! needed for that is written: a single row group with one uncompressed, plain-encoded page per
+//! column, and every column nullable. See https://github.com/apache/parquet-format for the
+//! layout and for the Thrift definitions of the metadata.
+
+use crate::schema::{Column, ColumnType, Schema};
+use crate::PuzzleStats;
+use anyhow::{Context, Result};
+use chrono::naive::NaiveDate;
+use std::io::Write;
+
+const MAGIC: &[u8] = b"PAR1";
+
+// Physical types
+const BOOLEAN: i32 = 0;
+const INT32: i32 = 1;
+const INT64: i32 = 2;
+const BYTE_ARRAY: i32 = 6;
+
+// Converted types, for readers that predate logical types
+const UTF8: i32 = 0;
+const DATE: i32 = 6;
+
+// Encodings
+const PLAIN: i32 = 0;
+const RLE: i32 = 3;
+
+const OPTIONAL: i32 = 1;
+const DATA_PAGE: i32 = 0;
+const UNCOMPRESSED: i32 = 0;
+
+// Thrift compact protocol types
+const T_I32: u8 = 5;
+const T_I64: u8 = 6;
+const T_BINARY: u8 = 8;
+const T_LIST: u8 = 9;
+const T_STRUCT: u8 = 12;
+
+fn write_varint(buf: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        buf.push((n & 0x7f) as u8 | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+/// Encoder for the Thrift compact protocol, which Parquet's metadata is written in
+struct Thrift {
+    buf: Vec<u8>,
+    /// Id of the last field written in each struct being written, since field ids are encoded as
+    /// the difference from the previous one
+    last_ids: Vec<i16>,
+}
+
+impl Thrift {
+    fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            last_ids: vec![0],
+        }
+    }
+
+    fn zigzag(&mut self, n: i64) {
+        write_varint(&mut self.buf, ((n << 1) ^ (n >> 63)) as u64);
+    }
+
+    fn field(&mut self, id: i16, kind: u8) {
+        let last = self
+            .last_ids
+            .last_mut()
+            .expect("Fields are written within a struct");
+        let delta = id - std::mem::replace(last, id);
+        if (1..=15).contains(&delta) {
+            self.buf.push((delta as u8) << 4 | kind);
+        } else {
+            self.buf.push(kind);
+            self.zigzag(id.into());
+        }
+    }
+
+    fn i32(&mut self, id: i16, n: i32) {
+        self.field(id, T_I32);
+        self.zigzag(n.into());
+    }
+
+    fn i64(&mut self, id: i16, n: i64) {
+        self.field(id, T_I64);
+        self.zigzag(n);
+    }
+
+    fn binary(&mut self, id: i16, bytes: &[u8]) {
+        self.field(id, T_BINARY);
+        self.binary_element(bytes);
+    }
+
+    fn binary_element(&mut self, bytes: &[u8]) {
+        write_varint(&mut self.buf, bytes.len() as u64);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Start a list of `len` elements of the given type. Elements follow without field headers.
+    fn list(&mut self, id: i16, kind: u8, len: usize) {
+        self.field(id, T_LIST);
+        if len < 15 {
+            self.buf.push((len as u8) << 4 | kind);
+        } else {
+            self.buf.push(0xf0 | kind);
+            write_varint(&mut self.buf, len as u64);
+        }
+    }
+
+    fn begin_struct(&mut self, id: i16) {
+        self.field(id, T_STRUCT);
+        self.begin_element();
+    }
+
+    /// Start a struct that's an element of a list
+    fn begin_element(&mut self) {
+        self.last_ids.push(0);
+    }
+
+    fn end_struct(&mut self) {
+        self.buf.push(0);
+        self.last_ids.pop();
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.end_struct();
+        self.buf
+    }
+}
+
+/// Run-length encode definition levels of bit width one, i.e. whether each value is present
+fn encode_levels(present: &[bool]) -> Vec<u8> {
+    let mut levels = Vec::new();
+    let mut runs = present.chunk_by(|a, b| a == b);
+    for run in &mut runs {
+        write_varint(&mut levels, (run.len() as u64) << 1);
+        levels.push(run[0].into());
+    }
+    levels
+}
+
+/// Physical and converted types of a column of the given type, and its plain-encoded values
+fn encode_values(
+    column: &Column,
+    values: &[&serde_json::Value],
+) -> Result<(i32, Option<i32>, Vec<u8>)> {
+    let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+    let mismatch = || format!("Unexpected value in column {}", column.name);
+    let mut buf = Vec::new();
+    let (physical_type, converted_type) = match column.kind {
+        ColumnType::Integer => {
+            for value in values {
+                buf.extend(value.as_i64().with_context(mismatch)?.to_le_bytes());
+            }
+            (INT64, None)
+        }
+        ColumnType::Boolean => {
+            buf.resize(values.len().div_ceil(8), 0);
+            for (i, value) in values.iter().enumerate() {
+                if value.as_bool().with_context(mismatch)? {
+                    buf[i / 8] |= 1 << (i % 8);
+                }
+            }
+            (BOOLEAN, None)
+        }
+        ColumnType::Date => {
+            for value in values {
+                let date: NaiveDate = value.as_str().with_context(mismatch)?.parse()?;
+                let days = i32::try_from((date - epoch).num_days())?;
+                buf.extend(days.to_le_bytes());
+            }
+            (INT32, Some(DATE))
+        }
+        ColumnType::Weekday | ColumnType::String | ColumnType::Enum => {
+            for value in values {
+                let text = value.as_str().with_context(mismatch)?;
+                buf.extend(u32::try_from(text.len())?.to_le_bytes());
+                buf.extend_from_slice(text.as_bytes());
+            }
+            (BYTE_ARRAY, Some(UTF8))
+        }
+    };
+    Ok((physical_type, converted_type, buf))
+}
+
+/// Where a column's page was written, for the file's metadata
+struct ColumnChunk {
+    physical_type: i32,
+    offset: usize,
+    size: usize,
+}
+
+/// Write the page holding every value of a column at the end of `file`
+fn write_column(
+    file: &mut Vec<u8>,
+    column: &Column,
+    values: &[Option<serde_json::Value>],
+) -> Result<(ColumnChunk, Option<i32>)> {
+    let present: Vec<bool> = values.iter().map(Option::is_some).collect();
+    let levels = encode_levels(&present);
+    let (physical_type, converted_type, encoded) =
+        encode_values(column, &values.iter().flatten().collect::<Vec<_>>())?;
+    let mut page = Vec::with_capacity(4 + levels.len() + encoded.len());
+    page.extend(u32::try_from(levels.len())?.to_le_bytes());
+    page.extend(levels);
+    page.extend(encoded);
+
+    let mut header = Thrift::new();
+    header.i32(1, DATA_PAGE);
+    header.i32(2, i32::try_from(page.len())?);
+    header.i32(3, i32::try_from(page.len())?);
+    header.begin_struct(5);
+    header.i32(1, i32::try_from(values.len())?);
+    header.i32(2, PLAIN);
+    header.i32(3, RLE);
+    header.i32(4, RLE);
+    header.end_struct();
+    let header = header.finish();
+
+    let offset = file.len();
+    file.extend(header);
+    file.extend(page);
+    let chunk = ColumnChunk {
+        physical_type,
+        offset,
+        size: file.len() - offset,
+    };
+    Ok((chunk, converted_type))
+}
+
+/// Write the given records as a Parquet file with the columns of the current schema
+pub fn write_parquet<'a, W, I>(mut writer: W, records: I) -> Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = &'a PuzzleStats>,
+{
+    let schema = Schema::current();
+    let mut columns: Vec<Vec<Option<serde_json::Value>>> = vec![Vec::new(); schema.columns.len()];
+    for record in records {
+        let serde_json::Value::Object(mut fields) = serde_json::to_value(record)? else {
+            unreachable!("PuzzleStats serializes to an object");
+        };
+        for (column, values) in schema.columns.iter().zip(&mut columns) {
+            values.push(fields.remove(column.name).filter(|value| !value.is_null()));
+        }
+    }
+    let n_rows = columns.first().map_or(0, Vec::len);
+
+    let mut file = MAGIC.to_vec();
+    let mut chunks = Vec::new();
+    let mut converted_types = Vec::new();
+    for (column, values) in schema.columns.iter().zip(&columns) {
+        let (chunk, converted_type) = write_column(&mut file, column, values)?;
+        chunks.push(chunk);
+        converted_types.push(converted_type);
+    }
+
+    let mut footer = Thrift::new();
+    footer.i32(1, 1);
+    footer.list(2, T_STRUCT, schema.columns.len() + 1);
+    footer.begin_element();
+    footer.binary(4, b"schema");
+    footer.i32(5, i32::try_from(schema.columns.len())?);
+    footer.end_struct();
+    for ((column, chunk), converted_type) in
+        schema.columns.iter().zip(&chunks).zip(&converted_types)
+    {
+        footer.begin_element();
+        footer.i32(1, chunk.physical_type);
+        footer.i32(3, OPTIONAL);
+        footer.binary(4, column.name.as_bytes());
+        if let Some(converted_type) = *converted_type {
+            footer.i32(6, converted_type);
+            // The logical type, a union of empty structs for strings and dates
+            footer.begin_struct(10);
+            footer.begin_struct(if converted_type == DATE { 6 } else { 1 });
+            footer.end_struct();
+            footer.end_struct();
+        }
+        footer.end_struct();
+    }
+    footer.i64(3, i64::try_from(n_rows)?);
+    if n_rows > 0 {
+        footer.list(4, T_STRUCT, 1);
+        footer.begin_element();
+        footer.list(1, T_STRUCT, chunks.len());
+        for (column, chunk) in schema.columns.iter().zip(&chunks) {
+            footer.begin_element();
+            footer.i64(2, i64::try_from(chunk.offset)?);
+            footer.begin_struct(3);
+            footer.i32(1, chunk.physical_type);
+            footer.list(2, T_I32, 2);
+            footer.zigzag(PLAIN.into());
+            footer.zigzag(RLE.into());
+            footer.list(3, T_BINARY, 1);
+            footer.binary_element(column.name.as_bytes());
+            footer.i32(4, UNCOMPRESSED);
+            footer.i64(5, i64::try_from(n_rows)?);
+            footer.i64(6, i64::try_from(chunk.size)?);
+            footer.i64(7, i64::try_from(chunk.size)?);
+            footer.i64(9, i64::try_from(chunk.offset)?);
+            footer.end_struct();
+            footer.end_struct();
+        }
+        let total_size: usize = chunks.iter().map(|chunk| chunk.size).sum();
+        footer.i64(2, i64::try_from(total_size)?);
+        footer.i64(3, i64::try_from(n_rows)?);
+        footer.end_struct();
+    } else {
+        footer.list(4, T_STRUCT, 0);
+    }
+    footer.binary(
+        6,
+        format!("crossword version {}", env!("CARGO_PKG_VERSION")).as_bytes(),
+    );
+    let footer = footer.finish();
+
+    file.extend(&footer);
+    file.extend(u32::try_from(footer.len())?.to_le_bytes());
+    file.extend(MAGIC);
+    writer.write_all(&file)?;
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,475 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Planning of the requests made by a fetch run. A `FetchPlan` lists the work to do without
+//! making any requests, so that it can be printed for a dry run, saved and resumed later, split
+//! across machines, capped to a request budget, or simulated to estimate how long it will take.
+//! The part of a plan left to do is also saved as a checkpoint during a run, so that an
+//! interrupted run can be resumed.
+
+use crate::api_client::PublishType;
+use crate::database::Database;
+use crate::journal::FetchHistory;
+use crate::{PuzzleStats, RecordKey, Refresh, DAY_STEP};
+use anyhow::{Context, Result};
+use chrono::{naive::NaiveDate, Duration};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::path::{Path, PathBuf};
+
+/// Path of the checkpoint left by an interrupted fetch run of the database at the given path,
+/// holding the part of its plan that was left to do
+#[must_use]
+pub fn checkpoint_path(db_path: &Path) -> PathBuf {
+    db_path.with_extension("checkpoint.json")
+}
+
+/// A unit of work in a fetch run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum WorkItem {
+    /// Look up the ids of the puzzles published within a range of dates, inclusive, and fetch the
+    /// stats of each one. Dates whose ids are already known when the item is run are skipped.
+    IdLookup {
+        publish_type: PublishType,
+        start: NaiveDate,
+        end: NaiveDate,
+    },
+    /// Fetch the stats of a puzzle whose id is already known
+    StatsFetch {
+        publish_type: PublishType,
+        date: NaiveDate,
+        id: u32,
+    },
+}
+
+impl WorkItem {
+    /// Most requests the item can make: one per puzzle, plus one for an id lookup
+    #[must_use]
+    pub fn max_requests(&self) -> usize {
+        match self {
+            Self::IdLookup { start, end, .. } => {
+                1 + start.iter_days().take_while(|date| date <= end).count()
+            }
+            Self::StatsFetch { .. } => 1,
+        }
+    }
+}
+
+/// Work to be done by a fetch run, in the order it will be started. Iterating over the plan yields
+/// and removes its items.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FetchPlan {
+    items: VecDeque<WorkItem>,
+}
+
+impl FetchPlan {
+    /// Plan a run that fetches puzzles of the given kind published from `start` to `end`,
+    /// inclusive. By default, ids are looked up for puzzles missing from the database and
+    /// unsolved puzzles are refreshed, or every puzzle if `force_refresh` is set. If `only` is
+    /// given, only records matching it are refreshed.
+    #[must_use]
+    pub fn new(
+        database: &Database,
+        publish_type: PublishType,
+        start: NaiveDate,
+        end: NaiveDate,
+        only: Option<Refresh>,
+        force_refresh: bool,
+    ) -> Self {
+        let (lookups, mut refreshes) = match only {
+            Some(refresh) => (
+                Vec::new(),
+                crate::get_records_to_refresh(database, publish_type, start, refresh),
+            ),
+            None => (
+                crate::get_days_without_ids_chunked(
+                    database,
+                    publish_type,
+                    start,
+                    end,
+                    Duration::days(DAY_STEP),
+                ),
+                if force_refresh {
+                    crate::get_records_to_refresh(database, publish_type, start, Refresh::All)
+                } else {
+                    crate::get_cached_unsolved_records(database, publish_type, start)
+                },
+            ),
+        };
+        refreshes.sort_unstable_by_key(|record| record.key());
+        let lookups = lookups.iter().filter_map(|block| {
+            Some(WorkItem::IdLookup {
+                publish_type,
+                start: block.first()?.date,
+                end: block.last()?.date,
+            })
+        });
+        let refreshes = refreshes.iter().filter_map(|record| {
+            Some(WorkItem::StatsFetch {
+                publish_type: record.publish_type,
+                date: record.date,
+                id: record.puzzle_id?,
+            })
+        });
+        lookups.chain(refreshes).collect()
+    }
+
+    /// Read a plan saved as JSON
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&json).with_context(|| format!("Malformed plan in {}", path.display()))
+    }
+
+    /// Write the plan as JSON, replacing any file at the given path
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Drop the stats fetch of the given puzzle, e.g. once it has been fetched. Id lookups are
+    /// kept, since they skip dates whose ids are known by the time they run.
+    pub fn complete(&mut self, (date, publish_type): RecordKey) {
+        self.items.retain(|item| {
+            !matches!(item, WorkItem::StatsFetch { publish_type: p, date: d, .. }
+                if *p == publish_type && *d == date)
+        });
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Most requests the plan can make
+    #[must_use]
+    pub fn max_requests(&self) -> usize {
+        self.items.iter().map(WorkItem::max_requests).sum()
+    }
+
+    /// Keep the leading items that together make at most `max_requests` requests, dropping the
+    /// rest for a later run. The last id lookup is shortened to fit the budget if needed.
+    #[must_use]
+    pub fn with_budget(mut self, max_requests: usize) -> Self {
+        let mut budget = max_requests;
+        let mut keep = 0;
+        for item in &mut self.items {
+            if let Some(remaining) = budget.checked_sub(item.max_requests()) {
+                budget = remaining;
+                keep += 1;
+                continue;
+            }
+            if let WorkItem::IdLookup { start, end, .. } = item {
+                // One request for the lookup itself leaves room for this many puzzles
+                if let Some(days) = budget.checked_sub(2).and_then(|d| i64::try_from(d).ok()) {
+                    *end = *start + Duration::days(days);
+                    keep += 1;
+                }
+            }
+            break;
+        }
+        self.items.truncate(keep);
+        self
+    }
+
+    /// The `index`th of `count` disjoint parts of the plan, e.g. to split a backfill across
+    /// several machines. `index` counts from zero.
+    #[must_use]
+    pub fn shard(&self, index: usize, count: NonZeroUsize) -> Self {
+        self.items
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i % count == index)
+            .map(|(_, item)| item.clone())
+            .collect()
+    }
+
+    /// Estimate the requests and duration of running the plan at `quota` requests per second.
+    /// Each puzzle is expected to fail as often as it did in past runs, and each failure to use up
+    /// all `retries`. Id lookups are assumed to find a puzzle on every date, so their estimate is
+    /// an upper bound.
+    #[must_use]
+    pub fn simulate(&self, quota: NonZeroU32, history: FetchHistory, retries: u32) -> Simulation {
+        let failure_rate = history.failure_rate().unwrap_or(0.0);
+        #[allow(clippy::cast_precision_loss)]
+        let phase = |name, items: usize, requests: usize| PhaseEstimate {
+            name,
+            items,
+            requests,
+            retries: requests as f64 * failure_rate * f64::from(retries),
+        };
+        let (lookups, refreshes): (Vec<&WorkItem>, Vec<&WorkItem>) = self
+            .items
+            .iter()
+            .partition(|item| matches!(item, WorkItem::IdLookup { .. }));
+        let requests = |items: &[&WorkItem]| items.iter().map(|item| item.max_requests()).sum();
+        Simulation {
+            phases: vec![
+                // Validating the token and fetching the official streaks
+                PhaseEstimate {
+                    name: "setup",
+                    items: 1,
+                    requests: 2,
+                    retries: 0.0,
+                },
+                phase("id lookups", lookups.len(), requests(&lookups)),
+                phase("stats refreshes", refreshes.len(), requests(&refreshes)),
+            ],
+            quota,
+            history,
+        }
+    }
+
+    /// Turn the plan into the records to search for, as expected by
+    /// `search::fetch_ids_and_stats`, and the records to refresh, as expected by
+    /// `search::fetch_missing_times`, using what's already known from the database
+    #[must_use]
+    pub fn resolve(self, database: &Database) -> (Vec<Vec<PuzzleStats>>, Vec<PuzzleStats>) {
+        let mut lookups = Vec::new();
+        let mut refreshes = Vec::new();
+        for item in self {
+            match item {
+                WorkItem::IdLookup {
+                    publish_type,
+                    start,
+                    end,
+                } => lookups.extend(crate::get_days_without_ids_chunked(
+                    database,
+                    publish_type,
+                    start,
+                    end,
+                    Duration::days(DAY_STEP),
+                )),
+                WorkItem::StatsFetch {
+                    publish_type,
+                    date,
+                    id,
+                } => {
+                    let record = database
+                        .get(date, publish_type)
+                        .filter(|record| record.puzzle_id == Some(id))
+                        .cloned()
+                        .unwrap_or_else(|| {
+                            let mut record = PuzzleStats::new(date, id, None);
+                            record.publish_type = publish_type;
+                            record
+                        });
+                    refreshes.push(record);
+                }
+            }
+        }
+        (lookups, refreshes)
+    }
+}
+
+impl Iterator for FetchPlan {
+    type Item = WorkItem;
+
+    fn next(&mut self) -> Option<WorkItem> {
+        self.items.pop_front()
+    }
+}
+
+impl FromIterator<WorkItem> for FetchPlan {
+    fn from_iter<I: IntoIterator<Item = WorkItem>>(items: I) -> Self {
+        Self {
+            items: items.into_iter().collect(),
+        }
+    }
+}
+
+/// Number of date ranges of each kind listed by the default `Display` of `FetchPlan`. The alternate
+/// form lists all of them.
+const RANGES_SHOWN: usize = 10;
+
+/// Collapse sorted dates into ranges of consecutive dates, inclusive
+fn date_ranges(dates: &[NaiveDate]) -> Vec<(NaiveDate, NaiveDate)> {
+    let mut ranges: Vec<(NaiveDate, NaiveDate)> = Vec::new();
+    for &date in dates {
+        match ranges.last_mut() {
+            Some((_, end)) if end.succ_opt() == Some(date) => *end = date,
+            _ => ranges.push((date, date)),
+        }
+    }
+    ranges
+}
+
+fn write_ranges(f: &mut fmt::Formatter<'_>, ranges: &[(NaiveDate, NaiveDate)]) -> fmt::Result {
+    let shown = if f.alternate() {
+        ranges.len()
+    } else {
+        RANGES_SHOWN
+    };
+    for (start, end) in ranges.iter().take(shown) {
+        if start == end {
+            writeln!(f, "  {start}")?;
+        } else {
+            writeln!(f, "  {start} to {end}")?;
+        }
+    }
+    if ranges.len() > shown {
+        writeln!(f, "  and {} more", ranges.len() - shown)?;
+    }
+    Ok(())
+}
+
+/// A count followed by a noun, which is pluralised unless the count is one
+fn counted(n: usize, noun: &str) -> String {
+    if n == 1 {
+        format!("{n} {noun}")
+    } else {
+        format!("{n} {noun}s")
+    }
+}
+
+/// Summary of the requests the plan makes for each kind of puzzle: the metadata requests of its id
+/// lookups and the stats requests of the puzzles they find, then the stats requests of puzzles
+/// whose ids are known, along with the dates each covers
+impl fmt::Display for FetchPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut groups: BTreeMap<PublishType, (Vec<_>, Vec<_>)> = BTreeMap::new();
+        for item in &self.items {
+            match item {
+                WorkItem::IdLookup {
+                    publish_type,
+                    start,
+                    end,
+                } => groups
+                    .entry(*publish_type)
+                    .or_default()
+                    .0
+                    .push((*start, *end)),
+                WorkItem::StatsFetch {
+                    publish_type, date, ..
+                } => groups.entry(*publish_type).or_default().1.push(*date),
+            }
+        }
+        for (publish_type, (lookups, mut refreshes)) in groups {
+            if !lookups.is_empty() {
+                let lookup_days: usize = lookups
+                    .iter()
+                    .map(|(start, end)| start.iter_days().take_while(|date| date <= end).count())
+                    .sum();
+                writeln!(
+                    f,
+                    "{publish_type}: {} looking up ids, then at most {} for the puzzles found:",
+                    counted(lookups.len(), "metadata request"),
+                    counted(lookup_days, "stats request"),
+                )?;
+                write_ranges(f, &lookups)?;
+            }
+            if !refreshes.is_empty() {
+                refreshes.sort_unstable();
+                writeln!(
+                    f,
+                    "{publish_type}: {} for puzzles whose ids are known:",
+                    counted(refreshes.len(), "stats request")
+                )?;
+                write_ranges(f, &date_ranges(&refreshes))?;
+            }
+        }
+        write!(
+            f,
+            "{}, making at most {}",
+            counted(self.len(), "item"),
+            counted(self.max_requests(), "request")
+        )
+    }
+}
+
+/// Estimated cost of one phase of a fetch run
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhaseEstimate {
+    pub name: &'static str,
+    /// Number of work items in the phase
+    pub items: usize,
+    /// Requests made if nothing fails
+    pub requests: usize,
+    /// Expected number of additional requests retrying failures
+    pub retries: f64,
+}
+
+/// Estimated cost of running a plan, from `FetchPlan::simulate`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Simulation {
+    pub phases: Vec<PhaseEstimate>,
+    /// Requests per second
+    pub quota: NonZeroU32,
+    /// Past runs the failure rate was taken from
+    pub history: FetchHistory,
+}
+
+impl Simulation {
+    /// Expected number of requests, including retries
+    #[must_use]
+    pub fn total_requests(&self) -> f64 {
+        #[allow(clippy::cast_precision_loss)]
+        self.phases
+            .iter()
+            .map(|phase| phase.requests as f64 + phase.retries)
+            .sum()
+    }
+
+    /// Expected duration of the run. Phases run concurrently but share the request quota, so the
+    /// quota is what limits how fast the run goes.
+    #[must_use]
+    pub fn duration(&self) -> std::time::Duration {
+        std::time::Duration::from_secs_f64(self.total_requests() / f64::from(self.quota.get()))
+    }
+}
+
+impl fmt::Display for Simulation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<16} {:>6} {:>9} {:>9}",
+            "Phase", "Items", "Requests", "Retries"
+        )?;
+        for phase in &self.phases {
+            writeln!(
+                f,
+                "{:<16} {:>6} {:>9} {:>9.0}",
+                phase.name, phase.items, phase.requests, phase.retries
+            )?;
+        }
+        let secs = self.duration().as_secs();
+        writeln!(
+            f,
+            "About {:.0} requests at {} requests/sec, taking about {}h {:02}m {:02}s",
+            self.total_requests(),
+            self.quota,
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60
+        )?;
+        match self.history.failure_rate() {
+            Some(rate) => write!(
+                f,
+                "Retries assume the failure rate of the {} runs in the journal, {:.1}% of puzzles",
+                self.history.runs,
+                rate * 100.0
+            ),
+            None => write!(f, "No past runs in the journal, so no retries are expected"),
+        }
+    }
+}
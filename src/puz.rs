@@ -0,0 +1,135 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reading of the solve progress saved in Across Lite .puz files
+//!
+//! Only what's needed to import a solve is read: the grid and the player's fill, the title and
+//! author, the timer (`LTIM` section), and whether squares were checked or revealed (`GEXT`
+//! section). Checksums aren't verified.
+
+use anyhow::{bail, ensure, Context, Result};
+
+const MAGIC: &[u8] = b"ACROSS&DOWN\0";
+/// Offset of the magic string from the start of the file
+const MAGIC_OFFSET: usize = 0x02;
+const HEADER_LEN: usize = 0x34;
+const BLACK_SQUARE: u8 = b'.';
+const EMPTY_SQUARE: u8 = b'-';
+/// `GEXT` flags of a square that was marked incorrect by a check, or whose answer was revealed
+const ASSISTED_FLAGS: u8 = 0x10 | 0x20 | 0x40;
+
+/// Progress saved in a .puz file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Puzzle {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// Seconds on the solving timer, if it was saved
+    pub elapsed_secs: Option<u32>,
+    /// Whether every square is filled in correctly. Files with a locked solution count as solved
+    /// once every square is filled in.
+    pub solved: bool,
+    /// Whether any square was checked or revealed
+    pub assisted: bool,
+    /// Percentage of the white squares that are filled in
+    pub percent_filled: u8,
+}
+
+/// Read a NUL-terminated Latin-1 string, returning it along with the rest of the input
+fn take_string(bytes: &[u8]) -> Result<(String, &[u8])> {
+    let end = bytes
+        .iter()
+        .position(|&b| b == 0)
+        .context("Unterminated string")?;
+    let string = bytes[..end].iter().copied().map(char::from).collect();
+    Ok((string, &bytes[end + 1..]))
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+/// Parse the contents of a .puz file
+pub fn parse(bytes: &[u8]) -> Result<Puzzle> {
+    ensure!(
+        bytes.len() >= HEADER_LEN && bytes[MAGIC_OFFSET..].starts_with(MAGIC),
+        "Not a .puz file"
+    );
+    let width = usize::from(bytes[0x2C]);
+    let height = usize::from(bytes[0x2D]);
+    let n_clues = u16_at(bytes, 0x2E);
+    let scrambled = u16_at(bytes, 0x32) != 0;
+    let squares = width * height;
+    ensure!(
+        bytes.len() >= HEADER_LEN + 2 * squares,
+        "Truncated .puz grid"
+    );
+    let solution = &bytes[HEADER_LEN..HEADER_LEN + squares];
+    let fill = &bytes[HEADER_LEN + squares..HEADER_LEN + 2 * squares];
+
+    let mut rest = &bytes[HEADER_LEN + 2 * squares..];
+    let mut strings = Vec::new();
+    // Title, author, copyright, the clues, and notes
+    for _ in 0..3 + usize::from(n_clues) + 1 {
+        let (string, remaining) = take_string(rest)?;
+        strings.push(string);
+        rest = remaining;
+    }
+    let non_empty = |s: &String| (!s.trim().is_empty()).then(|| s.trim().to_string());
+
+    let mut elapsed_secs = None;
+    let mut assisted = false;
+    // Extra sections are a 4-byte name, a 2-byte length, a 2-byte checksum, the data, and a NUL
+    while rest.len() >= 8 {
+        let len = usize::from(u16_at(rest, 4));
+        let Some(data) = rest.get(8..8 + len) else {
+            bail!("Truncated .puz section");
+        };
+        match &rest[..4] {
+            b"LTIM" => {
+                let timer = String::from_utf8_lossy(data);
+                elapsed_secs = timer.split(',').next().and_then(|s| s.trim().parse().ok());
+            }
+            b"GEXT" => assisted = data.iter().any(|flags| flags & ASSISTED_FLAGS != 0),
+            _ => (),
+        }
+        rest = rest.get(8 + len + 1..).unwrap_or_default();
+    }
+
+    let white: Vec<(u8, u8)> = solution
+        .iter()
+        .zip(fill)
+        .filter(|(&answer, _)| answer != BLACK_SQUARE)
+        .map(|(&answer, &filled)| (answer, filled))
+        .collect();
+    let n_filled = white.iter().filter(|(_, f)| *f != EMPTY_SQUARE).count();
+    let solved = if scrambled {
+        n_filled == white.len()
+    } else {
+        white.iter().all(|(a, f)| a.eq_ignore_ascii_case(f))
+    };
+    let percent_filled = if white.is_empty() {
+        0
+    } else {
+        u8::try_from(n_filled * 100 / white.len()).unwrap_or(100)
+    };
+
+    Ok(Puzzle {
+        title: non_empty(&strings[0]),
+        author: non_empty(&strings[1]),
+        elapsed_secs,
+        solved,
+        assisted,
+        percent_filled,
+    })
+}
@@ -0,0 +1,268 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::num::NonZeroU32;
+use governor::clock::DefaultClock;
+use governor::state::direct::NotKeyed;
+use governor::state::InMemoryState;
+use governor::{Quota, RateLimiter};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+type DirectRateLimiter = RateLimiter<NotKeyed, InMemoryState, DefaultClock>;
+
+/// Number of consecutive successful requests needed before the quota is raised by one step
+const RECOVERY_STREAK: u32 = 20;
+/// Pause applied after a rate-limited response that didn't say how long to wait
+const DEFAULT_BACKOFF: Duration = Duration::from_secs(5);
+/// Pause applied after a maintenance response that didn't say how long to wait
+const DEFAULT_MAINTENANCE_PAUSE: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+struct State {
+    /// Quota to recover to after being rate-limited
+    max_quota: NonZeroU32,
+    quota: NonZeroU32,
+    limiter: Arc<DirectRateLimiter>,
+    paused_until: Option<Instant>,
+    successes: u32,
+}
+
+/// A rate limiter that lowers its quota when the server signals that it is overloaded (HTTP 429)
+/// and gradually recovers to the configured quota once requests succeed again
+#[derive(Debug)]
+pub struct AdaptiveRateLimiter {
+    state: Mutex<State>,
+}
+
+impl AdaptiveRateLimiter {
+    /// Create a rate limiter allowing up to `max_quota` requests per second
+    #[must_use]
+    pub fn new(max_quota: NonZeroU32) -> Self {
+        Self {
+            state: Mutex::new(State {
+                max_quota,
+                quota: max_quota,
+                limiter: Arc::new(RateLimiter::direct(Quota::per_second(max_quota))),
+                paused_until: None,
+                successes: 0,
+            }),
+        }
+    }
+
+    /// Current quota in requests per second
+    pub fn quota(&self) -> NonZeroU32 {
+        self.state.lock().unwrap().quota
+    }
+
+    /// Change the configured quota, e.g. while a run is in progress. Takes effect immediately, even
+    /// if the quota was lowered after being rate-limited.
+    pub fn set_max_quota(&self, max_quota: NonZeroU32) {
+        let mut state = self.state.lock().unwrap();
+        state.max_quota = max_quota;
+        state.successes = 0;
+        state.set_quota(max_quota);
+    }
+
+    /// Wait until a request may be sent
+    pub async fn until_ready(&self) {
+        let (limiter, paused_until) = {
+            let state = self.state.lock().unwrap();
+            (Arc::clone(&state.limiter), state.paused_until)
+        };
+        if let Some(paused_until) = paused_until {
+            tokio::time::sleep_until(paused_until.into()).await;
+        }
+        limiter.until_ready().await;
+    }
+
+    /// Record that a request was rejected for exceeding the server's rate limit. Halves the
+    /// quota and pauses all requests for `retry_after`, or a default backoff if the server didn't
+    /// specify one.
+    pub fn on_rate_limited(&self, retry_after: Option<Duration>) {
+        let mut state = self.state.lock().unwrap();
+        let pause = retry_after.unwrap_or(DEFAULT_BACKOFF);
+        let paused_until = Instant::now() + pause;
+        state.paused_until = Some(
+            state
+                .paused_until
+                .map_or(paused_until, |p| p.max(paused_until)),
+        );
+        state.successes = 0;
+        let reduced = NonZeroU32::new(state.quota.get() / 2).unwrap_or(NonZeroU32::MIN);
+        if reduced < state.quota {
+            warn!(
+                "Rate-limited by server. Lowering quota to {} requests/sec and pausing for {:?}",
+                reduced, pause
+            );
+            state.set_quota(reduced);
+        }
+    }
+
+    /// Record a successful request, raising the quota back towards the configured maximum after a
+    /// streak of successes
+    pub fn on_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.quota >= state.max_quota {
+            return;
+        }
+        state.successes += 1;
+        if state.successes >= RECOVERY_STREAK {
+            let raised = state.quota.saturating_add(1).min(state.max_quota);
+            info!("Raising quota to {} requests/sec", raised);
+            state.set_quota(raised);
+            state.successes = 0;
+        }
+    }
+}
+
+/// A separate `AdaptiveRateLimiter` for each API host, so that limits apply per target host and a
+/// host that starts rate-limiting doesn't slow down requests to unrelated ones
+#[derive(Debug)]
+pub struct HostRateLimiter {
+    quota: Mutex<NonZeroU32>,
+    limiters: Mutex<HashMap<String, Arc<AdaptiveRateLimiter>>>,
+}
+
+impl HostRateLimiter {
+    /// Create a rate limiter allowing up to `quota` requests per second to each host
+    #[must_use]
+    pub fn new(quota: NonZeroU32) -> Self {
+        Self {
+            quota: Mutex::new(quota),
+            limiters: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Configured quota in requests per second to each host
+    pub fn quota(&self) -> NonZeroU32 {
+        *self.quota.lock().unwrap()
+    }
+
+    /// Change the quota of every host, including hosts that haven't been contacted yet
+    pub fn set_quota(&self, quota: NonZeroU32) {
+        let limiters = self.limiters.lock().unwrap();
+        *self.quota.lock().unwrap() = quota;
+        for limiter in limiters.values() {
+            limiter.set_max_quota(quota);
+        }
+    }
+
+    /// Rate limiter for the given host, created on first use
+    pub fn for_host(&self, host: &str) -> Arc<AdaptiveRateLimiter> {
+        let mut limiters = self.limiters.lock().unwrap();
+        let quota = self.quota();
+        Arc::clone(
+            limiters
+                .entry(host.to_string())
+                .or_insert_with(|| Arc::new(AdaptiveRateLimiter::new(quota))),
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+struct Outage {
+    started: Option<Instant>,
+    paused_until: Option<Instant>,
+}
+
+/// Pause shared by all requests while the server is down for maintenance (HTTP 503), so that an
+/// outage doesn't use up every request's retries. Requests resume once the server responds
+/// successfully again.
+#[derive(Debug)]
+pub struct MaintenancePause {
+    max_pause: Duration,
+    outage: Mutex<Outage>,
+}
+
+impl MaintenancePause {
+    /// Create a pause that gives up on an outage once it has lasted `max_pause`. Zero disables
+    /// pausing.
+    #[must_use]
+    pub fn new(max_pause: Duration) -> Self {
+        Self {
+            max_pause,
+            outage: Mutex::new(Outage::default()),
+        }
+    }
+
+    /// Wait until the current pause, if any, is over. The pause may be extended while waiting.
+    pub async fn until_ready(&self) {
+        loop {
+            let paused_until = self.outage.lock().unwrap().paused_until;
+            match paused_until {
+                Some(paused_until) if paused_until > Instant::now() => {
+                    tokio::time::sleep_until(paused_until.into()).await;
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// Record that the server responded that it is down for maintenance. Pauses all requests for
+    /// `retry_after`, or a default pause if the server didn't specify one.
+    ///
+    /// Returns whether the request should be retried once the pause is over, which is the case
+    /// until the outage has lasted `max_pause`
+    pub fn on_maintenance(&self, retry_after: Option<Duration>) -> bool {
+        let mut outage = self.outage.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.duration_since(*outage.started.get_or_insert(now));
+        if elapsed >= self.max_pause {
+            return false;
+        }
+        let pause = retry_after
+            .unwrap_or(DEFAULT_MAINTENANCE_PAUSE)
+            .min(self.max_pause - elapsed);
+        let paused_until = now + pause;
+        if outage.paused_until.is_none_or(|p| p < paused_until) {
+            if outage.paused_until.is_none_or(|p| p <= now) {
+                warn!(
+                    "Server is down for maintenance. Pausing all requests for {:?}",
+                    pause
+                );
+            }
+            outage.paused_until = Some(paused_until);
+        }
+        true
+    }
+
+    /// Record a successful request, ending any outage
+    pub fn on_success(&self) {
+        let mut outage = self.outage.lock().unwrap();
+        if outage.started.is_some() {
+            info!("Server is back from maintenance");
+            *outage = Outage::default();
+        }
+    }
+
+    /// Time left until requests resume, if they are paused
+    pub fn remaining(&self) -> Option<Duration> {
+        self.outage
+            .lock()
+            .unwrap()
+            .paused_until
+            .and_then(|p| p.checked_duration_since(Instant::now()))
+            .filter(|remaining| !remaining.is_zero())
+    }
+}
+
+impl State {
+    fn set_quota(&mut self, quota: NonZeroU32) {
+        self.quota = quota;
+        self.limiter = Arc::new(RateLimiter::direct(Quota::per_second(quota)));
+    }
+}
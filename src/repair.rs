@@ -0,0 +1,164 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Detection and repair of problems in the rows of a CSV database: duplicate records, missing or
+//! wrong weekdays, and values that can't be right
+//!
+//! Loading a database resolves duplicates and fills in weekdays without saying which rows were
+//! affected, so these problems are found by scanning the rows themselves.
+
+use crate::database::{csv_reader, CsvRecords, DuplicatePolicy};
+use crate::diff::format_key;
+use crate::{PuzzleStats, RecordKey};
+use anyhow::{Context, Result};
+use chrono::Datelike;
+use std::collections::HashMap;
+use std::fmt;
+
+/// A problem with a row of a database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// An earlier row, on the given line, has a record for the same puzzle
+    Duplicate { first_line: u64 },
+    /// The weekday column is empty
+    MissingWeekday,
+    /// The weekday column doesn't match the date
+    WrongWeekday { found: chrono::Weekday },
+    /// Solved with a solve time of zero
+    ZeroSolveTime,
+    /// Solved before it was opened
+    SolvedBeforeOpened,
+}
+
+/// A problem found in a database, and whether it was fixed. The alternate form of `Display`
+/// leaves out whether it was fixed, e.g. for when the fixes aren't saved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// Line of the file the row is on
+    pub line: u64,
+    pub key: RecordKey,
+    pub problem: Problem,
+    pub fixed: bool,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Line {}, {}: ", self.line, format_key(self.key))?;
+        match self.problem {
+            Problem::Duplicate { first_line } => write!(f, "duplicate of line {first_line}"),
+            Problem::MissingWeekday => write!(f, "missing weekday"),
+            Problem::WrongWeekday { found } => {
+                write!(f, "weekday is {found} instead of {}", self.key.0.weekday())
+            }
+            Problem::ZeroSolveTime => write!(f, "solved in 0 seconds"),
+            Problem::SolvedBeforeOpened => write!(f, "solved before it was opened"),
+        }?;
+        if self.fixed && !f.alternate() {
+            write!(f, " (fixed)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Records of a database with its problems fixed, and the problems that were found
+#[derive(Debug, Clone, Default)]
+pub struct Repair {
+    /// One record per puzzle
+    pub records: HashMap<RecordKey, PuzzleStats>,
+    /// Problems in the order of the rows they were found on
+    pub findings: Vec<Finding>,
+}
+
+impl Repair {
+    /// Whether any problem was fixed, so the records differ from the rows they were read from
+    #[must_use]
+    pub fn changed(&self) -> bool {
+        self.findings.iter().any(|finding| finding.fixed)
+    }
+}
+
+/// Scan the rows of a CSV database for problems. Duplicates are resolved with the given policy,
+/// and weekdays are derived from the date. Impossible values are cleared if `clear_impossible` is
+/// set, and only reported otherwise, since what they should have been isn't known.
+pub fn scan(csv: &[u8], duplicates: DuplicatePolicy, clear_impossible: bool) -> Result<Repair> {
+    let mut reader = csv_reader(csv)?;
+    let weekday_column = reader.headers()?.iter().position(|c| c == "weekday");
+    let mut repair = Repair::default();
+    let mut first_lines: HashMap<RecordKey, u64> = HashMap::new();
+    for (row, record) in reader.into_records().zip(CsvRecords::new(csv)?) {
+        let row = row.context("Malformed record")?;
+        let mut record = record?;
+        let line = row.position().map_or(0, csv::Position::line);
+        let key = record.key();
+        let mut found = |problem, fixed| {
+            repair.findings.push(Finding {
+                line,
+                key,
+                problem,
+                fixed,
+            });
+        };
+
+        // Empty weekdays are filled in from the date as the row is read
+        if weekday_column
+            .and_then(|i| row.get(i))
+            .unwrap_or_default()
+            .is_empty()
+        {
+            found(Problem::MissingWeekday, true);
+        } else if record.weekday != record.date.weekday() {
+            found(
+                Problem::WrongWeekday {
+                    found: record.weekday,
+                },
+                true,
+            );
+            record.weekday = record.date.weekday();
+        }
+        if record.solve_time_secs == Some(0) {
+            found(Problem::ZeroSolveTime, clear_impossible);
+            if clear_impossible {
+                record.solve_time_secs = None;
+            }
+        }
+        if let (Some(opened), Some(solved)) = (record.opened_unix, record.solved_unix) {
+            if solved < opened {
+                found(Problem::SolvedBeforeOpened, clear_impossible);
+                if clear_impossible {
+                    record.opened_unix = None;
+                    record.solved_unix = None;
+                    record.solved_on_pub_day = None;
+                }
+            }
+        }
+
+        let record = match repair.records.remove(&key) {
+            Some(earlier) => {
+                found(
+                    Problem::Duplicate {
+                        first_line: first_lines[&key],
+                    },
+                    true,
+                );
+                duplicates.choose(earlier, record)?
+            }
+            None => {
+                first_lines.insert(key, line);
+                record
+            }
+        };
+        repair.records.insert(key, record);
+    }
+    Ok(repair)
+}
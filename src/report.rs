@@ -0,0 +1,150 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Markdown reports summarizing a month of solves, and progress toward archive-completion goals
+
+use crate::analytics::format_secs;
+use crate::export::{self, Rollup};
+use crate::goals::GoalProgress;
+use crate::PuzzleStats;
+use anyhow::{Context, Result};
+use chrono::{naive::NaiveDate, Datelike};
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// First day of each month that the given dates fall in
+pub fn months<I: IntoIterator<Item = NaiveDate>>(dates: I) -> BTreeSet<NaiveDate> {
+    dates
+        .into_iter()
+        .map(|date| {
+            date.with_day(1)
+                .expect("First of the month is a valid date")
+        })
+        .collect()
+}
+
+/// Path of the report for the month starting on the given date within the given directory
+#[must_use]
+pub fn monthly_path(dir: &Path, month: NaiveDate) -> PathBuf {
+    dir.join(format!("{}.md", month.format("%Y-%m")))
+}
+
+/// Render a Markdown report of the puzzles published in the month starting on the given date
+#[must_use]
+pub fn monthly_markdown(records: &[PuzzleStats], month: NaiveDate) -> String {
+    let mut records: Vec<&PuzzleStats> = records
+        .iter()
+        .filter(|r| r.date.year() == month.year() && r.date.month() == month.month())
+        .collect();
+    records.sort_unstable_by_key(|r| r.date);
+
+    let mut report = String::new();
+    // Writing to a String can't fail
+    let _ = writeln!(report, "# NYT crossword: {}\n", month.format("%B %Y"));
+    let Some(summary) = export::rollup(records.iter().copied(), Rollup::Month).pop() else {
+        let _ = writeln!(report, "No puzzles recorded.");
+        return report;
+    };
+    let _ = write!(
+        report,
+        "Solved {} of {} puzzles ({} with check or reveal). Average unassisted time {}",
+        summary.solved,
+        summary.puzzles,
+        summary.cheated,
+        format_secs(summary.mean_solve_secs)
+    );
+    if let (Some(secs), Some(date)) = (summary.best_solve_secs, summary.best_date) {
+        let _ = write!(
+            report,
+            ", fastest {} on {}",
+            format_secs(Some(f64::from(secs))),
+            date
+        );
+    }
+    let _ = writeln!(report, ".\n");
+
+    let _ = writeln!(report, "| Date | Day | Time | Assisted |");
+    let _ = writeln!(report, "|------|-----|------|----------|");
+    for record in records {
+        let time = if record.is_solved() {
+            format_secs(record.solve_time_secs.map(f64::from))
+        } else {
+            "unsolved".to_string()
+        };
+        let assisted = if record.cheated.unwrap_or(false) {
+            "yes"
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            report,
+            "| {} | {} | {} | {} |",
+            record.date,
+            record.date.weekday(),
+            time,
+            assisted
+        );
+    }
+    report
+}
+
+/// Write the report for each of the given months into the given directory, replacing any earlier
+/// version
+pub fn write_monthly_reports(
+    dir: &Path,
+    records: &[PuzzleStats],
+    months: &BTreeSet<NaiveDate>,
+) -> Result<()> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create report directory {}", dir.display()))?;
+    for month in months {
+        let path = monthly_path(dir, *month);
+        fs::write(&path, monthly_markdown(records, *month))
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Path of the report of progress toward goals within the given directory
+#[must_use]
+pub fn goals_path(dir: &Path) -> PathBuf {
+    dir.join("goals.md")
+}
+
+/// Render a Markdown report of progress toward the given goals, listing every date left to solve
+#[must_use]
+pub fn goals_markdown(progress: &[GoalProgress]) -> String {
+    let mut report = String::new();
+    let _ = writeln!(report, "# NYT crossword: archive goals");
+    for goal in progress {
+        let _ = writeln!(report, "\n## {}\n", goal.goal.name);
+        let _ = writeln!(
+            report,
+            "{}. Solved {} of {} puzzles ({:.1}%).",
+            goal.goal,
+            goal.solved,
+            goal.total(),
+            goal.percent()
+        );
+        if !goal.remaining.is_empty() {
+            let _ = writeln!(report, "\nRemaining:\n");
+            for date in &goal.remaining {
+                let _ = writeln!(report, "- {} ({})", date, date.weekday());
+            }
+        }
+    }
+    report
+}
@@ -0,0 +1,75 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Re-derive stored columns from API responses, e.g. after the parser learns about new fields
+
+use crate::api_client::{self, RateLimitedClient};
+use crate::PuzzleStats;
+use futures::future;
+use log::error;
+use serde::Serialize;
+
+/// Counts of what happened to each record during a reprocess
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ReprocessSummary {
+    /// Records whose columns changed
+    pub updated: u32,
+    /// Records that were already up to date
+    pub unchanged: u32,
+    /// Records whose stats could not be retrieved
+    pub errors: u32,
+}
+
+/// Re-derive the solve stats of the given records from the API responses returned by the client.
+/// Configure the client with a cache to reuse saved responses instead of fetching them again.
+/// Records without a puzzle id are skipped.
+///
+/// Returns the records that changed
+pub async fn reprocess(
+    client: &RateLimitedClient,
+    records: &[PuzzleStats],
+) -> (Vec<PuzzleStats>, ReprocessSummary) {
+    let fetches = records.iter().filter_map(|record| {
+        let id = record.puzzle_id?;
+        Some(async move {
+            let stats = api_client::get_solve_stats(client, id, record.date).await;
+            (record, stats)
+        })
+    });
+
+    let mut summary = ReprocessSummary::default();
+    let mut updated = Vec::new();
+    for (record, stats) in future::join_all(fetches).await {
+        match stats {
+            Ok(stats) => {
+                let mut new = record.clone();
+                // An unsolved response carries no columns to re-derive
+                if let Some(stats) = stats {
+                    new.update_stats(stats);
+                }
+                if new == *record {
+                    summary.unchanged += 1;
+                } else {
+                    summary.updated += 1;
+                    updated.push(new);
+                }
+            }
+            Err(e) => {
+                error!("Failed to get stats for {}: {}", record.date, e);
+                summary.errors += 1;
+            }
+        }
+    }
+    (updated, summary)
+}
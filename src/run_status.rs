@@ -0,0 +1,177 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Progress of the current run, saved to a small status file so that it can be checked from
+//! another terminal or process
+
+use crate::logger::RunSummary;
+use anyhow::{Context, Result};
+use chrono::Utc;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Minimum time between saves of progress counts, so that large runs don't rewrite the file for
+/// every puzzle
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What a run is currently doing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// Checking the subscription token and recording official streaks
+    #[default]
+    Starting,
+    /// Fetching ids and stats
+    Fetching,
+    /// Waiting for the NYT API to come back from maintenance
+    Paused,
+    /// The run finished successfully
+    Finished,
+    /// The run failed. See `last_error`.
+    Failed,
+    /// Waiting for the next run in daemon mode
+    Sleeping,
+}
+
+/// Snapshot of a run's progress
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RunStatus {
+    pub phase: Phase,
+    /// Process id of the run
+    pub pid: u32,
+    /// Unix time the run started
+    pub started_unix: i64,
+    /// Unix time the status was last saved
+    pub updated_unix: i64,
+    /// Number of puzzles the run will fetch
+    pub total: u64,
+    pub solved: u32,
+    pub unsolved: u32,
+    pub errors: u32,
+    /// Error that failed the latest run, if it failed
+    pub last_error: Option<String>,
+    /// Unix time of the next run in daemon mode
+    pub next_run_unix: Option<i64>,
+}
+
+/// Path of the status file stored alongside the database at the given path
+#[must_use]
+pub fn status_path(db_path: &Path) -> PathBuf {
+    db_path.with_extension("status.json")
+}
+
+/// Read the status file at the given path
+pub fn load<P: AsRef<Path>>(path: P) -> Result<RunStatus> {
+    let path = path.as_ref();
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    serde_json::from_reader(file)
+        .with_context(|| format!("Malformed status file {}", path.display()))
+}
+
+/// Writer for a status file. Failures to save are logged rather than failing the run.
+#[derive(Debug)]
+pub struct StatusFile {
+    path: PathBuf,
+    status: RunStatus,
+    last_saved: Option<Instant>,
+}
+
+impl StatusFile {
+    /// Start tracking a new run, saving its status to the given path
+    #[must_use]
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        let mut status_file = Self {
+            path: path.into(),
+            status: RunStatus::default(),
+            last_saved: None,
+        };
+        status_file.start();
+        status_file
+    }
+
+    /// Reset the status for a new run
+    pub fn start(&mut self) {
+        self.status = RunStatus {
+            pid: std::process::id(),
+            started_unix: Utc::now().timestamp(),
+            ..RunStatus::default()
+        };
+        self.save();
+    }
+
+    /// Move on to the given phase of the run
+    pub fn set_phase(&mut self, phase: Phase) {
+        self.status.phase = phase;
+        self.save();
+    }
+
+    /// Start fetching the given number of puzzles
+    pub fn fetching(&mut self, total: u64) {
+        self.status.total = total;
+        self.set_phase(Phase::Fetching);
+    }
+
+    /// Record that the run failed with the given error
+    pub fn failed(&mut self, error: &anyhow::Error) {
+        self.status.last_error = Some(format!("{error:#}"));
+        self.set_phase(Phase::Failed);
+    }
+
+    /// Record that the next run starts at the given Unix time
+    pub fn sleeping(&mut self, next_run_unix: i64) {
+        self.status.next_run_unix = Some(next_run_unix);
+        self.set_phase(Phase::Sleeping);
+    }
+
+    /// Update the progress counts from the given partial summary. Saved at most once per
+    /// `SAVE_INTERVAL`.
+    pub fn progress(&mut self, summary: &RunSummary) {
+        self.status.solved = summary.solved;
+        self.status.unsolved = summary.unsolved;
+        self.status.errors = summary.errors;
+        if self
+            .last_saved
+            .is_none_or(|saved| saved.elapsed() >= SAVE_INTERVAL)
+        {
+            self.save();
+        }
+    }
+
+    /// Current status
+    #[must_use]
+    pub fn status(&self) -> &RunStatus {
+        &self.status
+    }
+
+    fn save(&mut self) {
+        self.status.updated_unix = Utc::now().timestamp();
+        self.last_saved = Some(Instant::now());
+        // Write to a temporary file first so that readers never see a partial status
+        let tmp = self.path.with_extension("tmp");
+        let result = File::create(&tmp)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| Ok(serde_json::to_writer(file, &self.status)?))
+            .and_then(|()| Ok(fs::rename(&tmp, &self.path)?));
+        if let Err(e) = result {
+            warn!(
+                "Failed to save run status to {}: {:#}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
@@ -0,0 +1,203 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Machine-readable description of the database columns, for tools that consume the output
+
+use serde::Serialize;
+use std::fmt;
+
+/// Version of the database schema. Bumped whenever a column is added, removed, or changes meaning.
+pub const SCHEMA_VERSION: u32 = 4;
+
+/// Type of the values stored in a column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+    /// Date in YYYY-MM-DD format
+    Date,
+    /// Abbreviated English day of the week, e.g. `Mon`
+    Weekday,
+    Integer,
+    Boolean,
+    String,
+    /// One of a fixed set of strings, listed in `values`
+    Enum,
+}
+
+/// Description of a single database column
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Column {
+    pub name: &'static str,
+    #[serde(rename = "type")]
+    pub kind: ColumnType,
+    /// Whether the column may be empty
+    pub nullable: bool,
+    /// Allowed values of an `Enum` column
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub values: &'static [&'static str],
+    pub description: &'static str,
+}
+
+const fn column(
+    name: &'static str,
+    kind: ColumnType,
+    nullable: bool,
+    description: &'static str,
+) -> Column {
+    Column {
+        name,
+        kind,
+        nullable,
+        values: &[],
+        description,
+    }
+}
+
+/// Schema of the database
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Schema {
+    pub version: u32,
+    /// Columns in the order they appear in the CSV
+    pub columns: Vec<Column>,
+}
+
+impl Schema {
+    /// Schema of databases written by this version
+    #[must_use]
+    pub fn current() -> Self {
+        use ColumnType::{Boolean, Date, Integer, String, Weekday};
+        Self {
+            version: SCHEMA_VERSION,
+            columns: vec![
+                column("date", Date, false, "Publication date of the puzzle"),
+                column(
+                    "puzzle_id",
+                    Integer,
+                    true,
+                    "Id the NYT uses to identify the puzzle",
+                ),
+                column(
+                    "weekday",
+                    Weekday,
+                    false,
+                    "Day of the week of the publication date",
+                ),
+                column(
+                    "solve_time_secs",
+                    Integer,
+                    true,
+                    "Time spent solving in seconds. Also set for assisted solves.",
+                ),
+                column(
+                    "opened_unix",
+                    Integer,
+                    true,
+                    "Unix time the puzzle was first opened",
+                ),
+                column(
+                    "solved_unix",
+                    Integer,
+                    true,
+                    "Unix time the puzzle was solved",
+                ),
+                column(
+                    "cheated",
+                    Boolean,
+                    true,
+                    "Whether check or reveal was used while solving",
+                ),
+                column(
+                    "solved_on_pub_day",
+                    Boolean,
+                    true,
+                    "Whether the puzzle was solved between its release and the end of its \
+                     publication day in the solver's home time zone",
+                ),
+                column("author", String, true, "Constructor(s) of the puzzle"),
+                column("editor", String, true, "Editor of the puzzle"),
+                column("title", String, true, "Title of the puzzle, if it has one"),
+                column(
+                    "percent_filled",
+                    Integer,
+                    true,
+                    "Percentage of the grid filled in as of when the puzzle's id was looked up",
+                ),
+                column(
+                    "gold_star",
+                    Boolean,
+                    true,
+                    "Whether the puzzle had earned a gold star as of when its id was looked up",
+                ),
+                column(
+                    "percent_rank",
+                    Integer,
+                    true,
+                    "Percentage of earlier unassisted solves of the same weekday that were slower",
+                ),
+                Column {
+                    name: "publish_type",
+                    kind: ColumnType::Enum,
+                    nullable: false,
+                    values: &["daily", "bonus", "variety", "acrostic"],
+                    description: "Kind of crossword. Databases written before this column \
+                                  was added only hold daily crosswords.",
+                },
+                column(
+                    "fetch_failed",
+                    Boolean,
+                    true,
+                    "Set when the puzzle's stats couldn't be fetched in the latest attempt",
+                ),
+                column(
+                    "source",
+                    String,
+                    true,
+                    "Where an imported record came from, as the import format and file name. \
+                     Empty for records fetched from the NYT.",
+                ),
+            ],
+        }
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Date => "date",
+            Self::Weekday => "weekday",
+            Self::Integer => "integer",
+            Self::Boolean => "boolean",
+            Self::String => "string",
+            Self::Enum => "enum",
+        };
+        f.write_str(name)
+    }
+}
+
+impl fmt::Display for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Schema version {}", self.version)?;
+        for column in &self.columns {
+            let nullable = if column.nullable { "?" } else { "" };
+            writeln!(
+                f,
+                "  {:<18} {:<9} {}",
+                column.name,
+                format!("{}{}", column.kind, nullable),
+                column.description
+            )?;
+        }
+        Ok(())
+    }
+}
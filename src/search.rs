@@ -12,32 +12,199 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::api_client::{self, RateLimitedClient};
+use crate::api_client::{
+    self, ApiError, PublishType, PuzzleInfo, RateLimitedClient, SolveProgress,
+};
 use crate::{logger, PuzzleStats};
-use anyhow::Result;
-use futures::future;
+use anyhow::{anyhow, Context, Result};
+use chrono::{naive::NaiveDate, Duration};
+use futures::stream::{self, Stream};
 use log::{debug, error, warn};
-use tokio::sync::mpsc;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time;
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::{JoinError, JoinSet};
 
+/// How hard to try looking up the puzzle ids of a block of dates before giving up on it. Id
+/// lookups are retried on top of the client's own retries of transient failures, since a single
+/// failed lookup would otherwise leave a whole block of dates unfetched for the run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdLookupPolicy {
+    /// Attempts per range of dates, including the first one
+    pub max_attempts: u32,
+    /// Delay before the first retry. The delay doubles with each subsequent retry.
+    pub base_delay: time::Duration,
+    /// How many times a range that keeps failing is split in half, with each half being tried on
+    /// its own
+    pub max_splits: u32,
+}
+
+impl Default for IdLookupPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: time::Duration::from_secs(5),
+            max_splits: 2,
+        }
+    }
+}
+
+impl IdLookupPolicy {
+    /// A policy that gives up on a range after the first failed lookup
+    #[must_use]
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            max_splits: 0,
+            ..Default::default()
+        }
+    }
+
+    /// Whether a failed lookup is worth trying again. Rejected tokens and other client errors
+    /// won't go away on their own.
+    fn is_retryable(error: &ApiError) -> bool {
+        match error {
+            ApiError::Unauthorized(_) | ApiError::AuthCircuitOpen { .. } => false,
+            ApiError::Status(status) => !status.is_client_error(),
+            _ => true,
+        }
+    }
+}
+
+/// Look up the puzzle info of the given range of dates, retrying and then splitting the range
+/// according to the policy.
+///
+/// Returns the info found and the ranges that were given up on, each with the error of its last
+/// attempt. The error is shared so that it can be reported for every date of the range.
+pub(crate) async fn lookup_ids(
+    client: &RateLimitedClient,
+    publish_type: PublishType,
+    start: NaiveDate,
+    end: NaiveDate,
+    policy: IdLookupPolicy,
+) -> (
+    HashMap<NaiveDate, PuzzleInfo>,
+    Vec<(NaiveDate, NaiveDate, Arc<ApiError>)>,
+) {
+    let mut info = HashMap::new();
+    let mut failed = Vec::new();
+    let mut ranges = vec![(start, end, 0)];
+    while let Some((start, end, splits)) = ranges.pop() {
+        let mut attempt = 1;
+        let error = loop {
+            match api_client::get_puzzle_info(client, publish_type, start, end).await {
+                Ok(found) => {
+                    info.extend(found);
+                    break None;
+                }
+                Err(e) if attempt < policy.max_attempts && IdLookupPolicy::is_retryable(&e) => {
+                    let delay = policy
+                        .base_delay
+                        .saturating_mul(2_u32.saturating_pow(attempt - 1));
+                    warn!(
+                        "Id lookup for {} to {} failed: {}. Retrying in {:?}",
+                        start, end, e, delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => break Some(e),
+            }
+        };
+        let Some(error) = error else {
+            continue;
+        };
+        if start < end && splits < policy.max_splits && IdLookupPolicy::is_retryable(&error) {
+            let middle = start + Duration::days((end - start).num_days() / 2);
+            debug!(
+                "Splitting id lookup for {} to {} after: {}",
+                start, end, error
+            );
+            ranges.push((middle + Duration::days(1), end, splits + 1));
+            ranges.push((start, middle, splits + 1));
+        } else {
+            // This may occur if the entire range consists of unreleased puzzles, which would
+            // happen if the puzzle from the last date in the range (today in UTC) hasn't been
+            // released yet.
+            warn!(
+                "Couldn't get puzzle id for date range {} to {}. Error: {:?}",
+                start, end, error
+            );
+            failed.push((start, end, Arc::new(error)));
+        }
+    }
+    (info, failed)
+}
+
+/// Concurrently refresh the stats of the given puzzles, whose ids are known, and send the results
+/// to the provided channel. Each fetch is a task owned by this one, so aborting this task aborts
+/// them too. Fetches are only started while a permit of `limit` is free.
 pub async fn fetch_missing_times(
     client: RateLimitedClient,
     dates: Vec<PuzzleStats>,
     logger: mpsc::UnboundedSender<logger::Payload>,
+    limit: Arc<Semaphore>,
 ) -> Result<()> {
-    let mut futures = Vec::new();
+    let mut tasks = JoinSet::new();
     for puzzle in dates {
-        futures.push(tokio::spawn(get_solve_stats(
-            client.clone(),
-            puzzle,
-            logger.clone(),
-        )));
+        let fetch = get_solve_stats(client.clone(), puzzle, logger.clone());
+        spawn_limited(&mut tasks, &limit, fetch, "get_solve_stats", &logger).await;
+    }
+    while let Some(result) = tasks.join_next().await {
+        report_join_result("get_solve_stats", result, &logger);
     }
-    future::join_all(futures).await;
     Ok(())
 }
 
+/// Spawn a fetch into `tasks` once one of the permits of `limit` is free, so that only so many
+/// fetches are in flight, and exist as tasks, at a time. The fetch holds the permit until it's
+/// done. Fetches that are already done are removed from `tasks` and reported as `task`, so that
+/// the set doesn't grow with the number of fetches.
+async fn spawn_limited<F>(
+    tasks: &mut JoinSet<Result<()>>,
+    limit: &Arc<Semaphore>,
+    fetch: F,
+    task: &str,
+    logger: &mpsc::UnboundedSender<logger::Payload>,
+) where
+    F: Future<Output = Result<()>> + Send + 'static,
+{
+    let permit = Arc::clone(limit)
+        .acquire_owned()
+        .await
+        .expect("The semaphore limiting fetches is never closed");
+    while let Some(result) = tasks.try_join_next() {
+        report_join_result(task, result, logger);
+    }
+    tasks.spawn(async move {
+        let _permit = permit;
+        fetch.await
+    });
+}
+
+/// Log the outcome of a spawned fetch task. Tasks that panicked or were cancelled are reported to
+/// the logger so that the data gap shows up in the run summary instead of going unnoticed.
+pub fn report_join_result(
+    task: &str,
+    result: Result<Result<()>, JoinError>,
+    logger: &mpsc::UnboundedSender<logger::Payload>,
+) {
+    match result {
+        Ok(Ok(())) => (),
+        Ok(Err(e)) => warn!("Error in {}: {}", task, e),
+        Err(e) => {
+            error!("Task {} failed: {}", task, e);
+            // The logger only exits once it's told the run is finished, so this can't fail
+            let _ = logger.send(logger::Payload::TaskFailed);
+        }
+    }
+}
+
 /// Concurrently fetch statistics for the crosswords from the given dates and send the results to
-/// the provided channel
+/// the provided channel. Each fetch is a task owned by this one, so aborting this task aborts them
+/// too.
 ///
 /// # Arguments
 ///
@@ -45,21 +212,29 @@ pub async fn fetch_missing_times(
 /// * `dates` - Blocks of dates to search. Each block must be sorted and contain no more than
 ///   `DAY_STEP` elements
 /// * `logger` - Channel where individual puzzle's statistics should be sent to
+/// * `lookup` - How hard to try looking up the ids of each block
+/// * `limit` - Permits for the requests in flight at a time. Each id lookup and each fetch of a
+///   puzzle's stats holds one while it runs.
 pub async fn fetch_ids_and_stats(
     client: RateLimitedClient,
     dates: Vec<Vec<PuzzleStats>>,
     logger: mpsc::UnboundedSender<logger::Payload>,
+    lookup: IdLookupPolicy,
+    limit: Arc<Semaphore>,
 ) -> Result<()> {
-    let mut futures = Vec::new();
+    let mut tasks = JoinSet::new();
     for block_of_dates in dates {
-        futures.push(tokio::spawn({
-            let client = client.clone();
-            let tx = logger.clone();
-            async move { search_date_block(client, block_of_dates, tx).await }
-        }));
+        tasks.spawn(search_date_block(
+            client.clone(),
+            block_of_dates,
+            logger.clone(),
+            lookup,
+            Arc::clone(&limit),
+        ));
+    }
+    while let Some(result) = tasks.join_next().await {
+        report_join_result("search_date_block", result, &logger);
     }
-
-    future::join_all(futures).await;
     Ok(())
 }
 
@@ -69,58 +244,63 @@ pub async fn fetch_ids_and_stats(
 /// # Arguments
 ///
 /// * `client` - A `RateLimitedClient` that can be used to send outgoing requests
-/// * `block_of_dates` - Sorted list of puzzle dates to search. Must contain no more than
-///   `DAY_STEP` elements
+/// * `block_of_dates` - Sorted list of puzzle dates of a single publish type to search. Must
+///   contain no more than `DAY_STEP` elements
 /// * `logger` - Channel where individual puzzle's statistics should be sent to
+/// * `lookup` - How hard to try looking up the ids of the block
+/// * `limit` - Permits for the requests in flight at a time
 async fn search_date_block(
     client: RateLimitedClient,
     block: Vec<PuzzleStats>,
     logger: mpsc::UnboundedSender<logger::Payload>,
+    lookup: IdLookupPolicy,
+    limit: Arc<Semaphore>,
 ) -> Result<()> {
     assert!(block.len() <= crate::DAY_STEP.try_into().unwrap());
     let start = block[0].date;
     let end = block.iter().last().unwrap().date;
 
     debug!("Fetching ids for date range {} to {}", start, end);
-    let id_map = match api_client::get_puzzle_ids(&client, start, end).await {
-        Ok(map) => map,
-        Err(e) => {
-            // This may occur if the entire date block consists of unreleased puzzles, which would
-            // happen if the puzzle from the last date in the search block (today in UTC) hasn't
-            // been released yet.
-            warn!(
-                "Couldn't get puzzle id for date range {} to {}. Error: {:?}",
-                start, end, e
-            );
-            return Ok(());
-        }
+    let publish_type = block[0].publish_type;
+    let (mut info_map, failed) = {
+        // Released before waiting for permits for the block's puzzles, which could otherwise all
+        // be held by lookups waiting for the same
+        let _permit = limit.acquire().await?;
+        lookup_ids(&client, publish_type, start, end, lookup).await
     };
 
     // Concurrently find stats for all puzzles in block
-    let mut futures = Vec::new();
+    let mut tasks = JoinSet::new();
     for mut puzzle in block {
         let date = puzzle.date;
-        puzzle.puzzle_id = if let Some(id) = id_map.get(&date) {
-            Some(*id)
+        if let Some((_, _, error)) = failed
+            .iter()
+            .find(|(start, end, _)| (*start..=*end).contains(&date))
+        {
+            // Already warned about
+            logger.send(logger::Payload::LookupFailed(date, Arc::clone(error)))?;
+            continue;
+        }
+        if let Some(info) = info_map.remove(&date) {
+            puzzle.update_info(info);
         } else {
             // This will occur if there are unreleased puzzles in this date block
             warn!("No id found for {}", date);
-            logger.send(logger::Payload::FetchError(None))?;
+            logger.send(logger::Payload::MissingId(date))?;
             continue;
-        };
+        }
         // Check if the solve time is already known. This would happen if the loaded database
         // contained a puzzle record that had a solve time but no saved id
         if puzzle.solve_time_secs.is_some() {
-            logger.send(logger::Payload::Solve(puzzle)).unwrap();
+            logger.send(logger::Payload::Solve(puzzle))?;
             continue;
         }
-        futures.push(tokio::spawn(get_solve_stats(
-            client.clone(),
-            puzzle,
-            logger.clone(),
-        )));
+        let fetch = get_solve_stats(client.clone(), puzzle, logger.clone());
+        spawn_limited(&mut tasks, &limit, fetch, "get_solve_stats", &logger).await;
+    }
+    while let Some(result) = tasks.join_next().await {
+        report_join_result("get_solve_stats", result, &logger);
     }
-    future::join_all(futures).await;
     Ok(())
 }
 
@@ -130,24 +310,149 @@ async fn get_solve_stats(
     logger: mpsc::UnboundedSender<logger::Payload>,
 ) -> Result<()> {
     let id = puzzle.puzzle_id.unwrap();
-    match api_client::get_solve_stats(&client, id).await {
-        Ok(Some(solve_stats)) => {
-            puzzle.update_stats(solve_stats);
-            logger.send(logger::Payload::Solve(puzzle)).unwrap();
-        }
-        Ok(None) => {
-            logger.send(logger::Payload::Unsolved(puzzle)).unwrap();
+    match api_client::get_solve_progress(&client, id, puzzle.date).await {
+        Ok(progress) => {
+            if record_progress(&mut puzzle, progress) {
+                logger.send(logger::Payload::Solve(puzzle))?;
+            } else {
+                logger.send(logger::Payload::Unsolved(puzzle))?;
+            }
         }
         Err(e) => {
-            error!(
-                "Failed to get stats for date={} id={}: {}",
-                puzzle.date, id, e
-            );
+            // Requests are stopped once the token is known to be bad. Don't log every one.
+            if matches!(e, ApiError::AuthCircuitOpen { .. }) {
+                debug!("Skipped stats for date={} id={}: {}", puzzle.date, id, e);
+            } else {
+                error!(
+                    "Failed to get stats for date={} id={}: {}",
+                    puzzle.date, id, e
+                );
+            }
             // Send puzzle stats to get added to database anyway. At least we know its id.
-            logger
-                .send(logger::Payload::FetchError(Some(puzzle)))
-                .unwrap();
+            logger.send(logger::Payload::FetchError(puzzle, e))?;
         }
     }
     Ok(())
 }
+
+/// Update a puzzle's record with the progress fetched for it
+///
+/// Returns whether the puzzle was solved
+fn record_progress(puzzle: &mut PuzzleStats, progress: SolveProgress) -> bool {
+    if let Some(solve_stats) = progress.solve {
+        puzzle.update_stats(solve_stats);
+        true
+    } else {
+        // Keeps track of puzzles that were started but not finished
+        puzzle.opened_unix = progress.opened;
+        false
+    }
+}
+
+/// Look up the id of the puzzle of the given kind published on the given date and fetch its stats,
+/// without going through the logger. Fails with the `ApiError` of the lookup if it failed, or with
+/// a "not found" error if it succeeded but didn't list the puzzle.
+pub async fn fetch_one(
+    client: &RateLimitedClient,
+    publish_type: PublishType,
+    date: NaiveDate,
+) -> Result<PuzzleStats> {
+    let (mut info, failed) =
+        lookup_ids(client, publish_type, date, date, IdLookupPolicy::default()).await;
+    if let Some((_, _, error)) = failed.into_iter().next() {
+        let error = Arc::try_unwrap(error)
+            .expect("The lookup of a single date doesn't share its error with other dates");
+        return Err(anyhow::Error::new(error).context(format!(
+            "Failed to look up the id of the {publish_type} puzzle from {date}"
+        )));
+    }
+    let info = info
+        .remove(&date)
+        .with_context(|| format!("No {publish_type} puzzle found for {date}"))?;
+    let mut puzzle = PuzzleStats::empty(date);
+    puzzle.publish_type = publish_type;
+    puzzle.update_info(info);
+    let progress = api_client::get_solve_progress(client, puzzle.puzzle_id.unwrap(), date).await?;
+    record_progress(&mut puzzle, progress);
+    Ok(puzzle)
+}
+
+/// Fetch the stats of the puzzles of the given kind published within the given ranges of dates,
+/// inclusive, as a stream of records in the order they're fetched. Each puzzle whose id or stats
+/// couldn't be fetched comes through as an error naming its date, and the stream ends once every
+/// puzzle has been tried. Dropping the stream stops the fetches.
+///
+/// This is an alternative to `fetch_ids_and_stats` for library users that handle results
+/// themselves instead of sending them to the logger. `lookup` and `limit` are as described there.
+///
+/// The source of an error, if any, is the `ApiError` of the failed request. The ids of a range of
+/// dates are looked up in a single request, so a failed lookup is shared by the errors of each
+/// of its dates as an `Arc<ApiError>`.
+pub fn stream_stats<I>(
+    client: RateLimitedClient,
+    publish_type: PublishType,
+    ranges: I,
+    lookup: IdLookupPolicy,
+    limit: Arc<Semaphore>,
+) -> impl Stream<Item = Result<PuzzleStats>>
+where
+    I: IntoIterator<Item = (NaiveDate, NaiveDate)>,
+{
+    let day_step = usize::try_from(crate::DAY_STEP).unwrap();
+    let blocks: Vec<Vec<PuzzleStats>> = ranges
+        .into_iter()
+        .flat_map(|(start, end)| {
+            let dates: Vec<NaiveDate> = start.iter_days().take_while(|d| *d <= end).collect();
+            dates
+                .chunks(day_step)
+                .map(|block| {
+                    block
+                        .iter()
+                        .map(|&date| {
+                            let mut puzzle = PuzzleStats::empty(date);
+                            puzzle.publish_type = publish_type;
+                            puzzle
+                        })
+                        .collect()
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    // Dropping the set, along with the stream, aborts the fetches
+    let mut fetches = JoinSet::new();
+    fetches.spawn(fetch_ids_and_stats(client, blocks, tx, lookup, limit));
+    stream::unfold((fetches, rx), move |(fetches, mut rx)| async move {
+        let item = loop {
+            match rx.recv().await? {
+                logger::Payload::Solve(puzzle) | logger::Payload::Unsolved(puzzle) => {
+                    break Ok(puzzle)
+                }
+                logger::Payload::FetchError(puzzle, error) => {
+                    break Err(anyhow::Error::new(error).context(format!(
+                        "Failed to get stats for the {publish_type} puzzle from {} (id {})",
+                        puzzle.date,
+                        puzzle.puzzle_id.unwrap_or_default()
+                    )))
+                }
+                logger::Payload::LookupFailed(date, error) => {
+                    break Err(anyhow::Error::new(error).context(format!(
+                        "Failed to look up the id of the {publish_type} puzzle from {date}"
+                    )))
+                }
+                logger::Payload::MissingId(date) => {
+                    break Err(anyhow!("No {publish_type} puzzle found for {date}"))
+                }
+                logger::Payload::TaskFailed => {
+                    break Err(anyhow!("A fetch task panicked or was cancelled"))
+                }
+                // Only sent by fetch runs
+                logger::Payload::EndOfPass(_)
+                | logger::Payload::Finished(_)
+                | logger::Payload::Interrupted(_) => continue,
+            }
+        };
+        Some((item, (fetches, rx)))
+    })
+}
@@ -0,0 +1,63 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Point-in-time copies of the database
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Path of a snapshot of the database at the given path, taken at the given time, within the given
+/// directory. The timestamp sorts chronologically.
+#[must_use]
+pub fn snapshot_path(db_path: &Path, dir: &Path, time: DateTime<Utc>, gzip: bool) -> PathBuf {
+    let stem = db_path
+        .file_stem()
+        .map_or_else(|| "database".into(), |stem| stem.to_string_lossy());
+    let mut name = format!("{}-{}", stem, time.format("%Y%m%dT%H%M%SZ"));
+    if let Some(ext) = db_path.extension() {
+        name = format!("{}.{}", name, ext.to_string_lossy());
+    }
+    if gzip {
+        name.push_str(".gz");
+    }
+    dir.join(name)
+}
+
+/// Copy the database at the given path into the given directory, optionally gzip-compressed.
+///
+/// Returns the path of the snapshot
+pub fn snapshot(db_path: &Path, dir: &Path, time: DateTime<Utc>, gzip: bool) -> Result<PathBuf> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create snapshot directory {}", dir.display()))?;
+    let path = snapshot_path(db_path, dir, time, gzip);
+    if gzip {
+        let mut source =
+            File::open(db_path).with_context(|| format!("Failed to open {}", db_path.display()))?;
+        let file =
+            File::create(&path).with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        io::copy(&mut source, &mut encoder)?;
+        encoder.finish()?;
+    } else {
+        fs::copy(db_path, &path).with_context(|| {
+            format!("Failed to copy {} to {}", db_path.display(), path.display())
+        })?;
+    }
+    Ok(path)
+}
@@ -0,0 +1,151 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! SQLite storage for the database. The table has the same columns as the CSV, as described by
+//! `schema::Schema`, with one row per record.
+
+use crate::schema::{ColumnType, Schema, SCHEMA_VERSION};
+use crate::{PuzzleStats, RecordKey};
+use anyhow::{Context, Result};
+use log::warn;
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{params_from_iter, Connection, OpenFlags};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+const TABLE: &str = "puzzles";
+
+fn sql_type(kind: ColumnType) -> &'static str {
+    match kind {
+        ColumnType::Integer | ColumnType::Boolean => "INTEGER",
+        ColumnType::Date | ColumnType::Weekday | ColumnType::String | ColumnType::Enum => "TEXT",
+    }
+}
+
+/// Names of the columns of the table
+fn table_columns(conn: &Connection) -> Result<HashSet<String>> {
+    let mut statement = conn.prepare(&format!("PRAGMA table_info({TABLE})"))?;
+    let columns = statement
+        .query_map([], |row| row.get::<_, String>("name"))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(columns)
+}
+
+/// Create the table if needed, and add any columns that are missing from a table written by an
+/// older version
+fn create_table(conn: &Connection, schema: &Schema) -> Result<()> {
+    let columns: Vec<String> = schema
+        .columns
+        .iter()
+        .map(|c| format!("{} {}", c.name, sql_type(c.kind)))
+        .collect();
+    conn.execute(
+        &format!(
+            "CREATE TABLE IF NOT EXISTS {TABLE} ({}, PRIMARY KEY (date, publish_type))",
+            columns.join(", ")
+        ),
+        [],
+    )?;
+    let existing = table_columns(conn)?;
+    for column in schema.columns.iter().filter(|c| !existing.contains(c.name)) {
+        conn.execute(
+            &format!(
+                "ALTER TABLE {TABLE} ADD COLUMN {} {}",
+                column.name,
+                sql_type(column.kind)
+            ),
+            [],
+        )?;
+    }
+    conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    Ok(())
+}
+
+/// Read the records stored in the SQLite database at the given path
+pub fn load(path: &Path) -> Result<HashMap<RecordKey, PuzzleStats>> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let existing = table_columns(&conn)?;
+    // Columns added since the database was written are left at their defaults
+    let columns: Vec<_> = Schema::current()
+        .columns
+        .into_iter()
+        .filter(|c| existing.contains(c.name))
+        .collect();
+    let names: Vec<&str> = columns.iter().map(|c| c.name).collect();
+    let mut statement = conn.prepare(&format!("SELECT {} FROM {TABLE}", names.join(", ")))?;
+    let mut rows = statement.query([])?;
+
+    let mut records = HashMap::new();
+    while let Some(row) = rows.next()? {
+        let mut fields = serde_json::Map::new();
+        for (i, column) in columns.iter().enumerate() {
+            let value = match row.get_ref(i)? {
+                ValueRef::Null => continue,
+                ValueRef::Integer(n) if column.kind == ColumnType::Boolean => {
+                    serde_json::Value::Bool(n != 0)
+                }
+                ValueRef::Integer(n) => n.into(),
+                ValueRef::Real(n) => n.into(),
+                ValueRef::Text(text) => String::from_utf8_lossy(text).into(),
+                ValueRef::Blob(_) => anyhow::bail!("Unexpected blob in column {}", column.name),
+            };
+            fields.insert(column.name.to_string(), value);
+        }
+        let record: PuzzleStats =
+            serde_json::from_value(fields.into()).with_context(|| "Malformed record")?;
+        records.insert(record.key(), record);
+    }
+    Ok(records)
+}
+
+/// Write the given records to the SQLite database at the given path in a single transaction,
+/// replacing any stored records with the same keys
+pub fn save<'a, I: IntoIterator<Item = &'a PuzzleStats>>(path: &Path, records: I) -> Result<()> {
+    let mut conn =
+        Connection::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let schema = Schema::current();
+    create_table(&conn, &schema)?;
+
+    let names: Vec<&str> = schema.columns.iter().map(|c| c.name).collect();
+    let placeholders = vec!["?"; names.len()].join(", ");
+    let transaction = conn.transaction()?;
+    {
+        let mut statement = transaction.prepare(&format!(
+            "INSERT OR REPLACE INTO {TABLE} ({}) VALUES ({placeholders})",
+            names.join(", ")
+        ))?;
+        for record in records {
+            let serde_json::Value::Object(mut fields) = serde_json::to_value(record)? else {
+                unreachable!("PuzzleStats serializes to an object");
+            };
+            let values = names.iter().map(|name| match fields.remove(*name) {
+                None | Some(serde_json::Value::Null) => Value::Null,
+                Some(serde_json::Value::Bool(b)) => Value::Integer(b.into()),
+                Some(serde_json::Value::Number(n)) => n.as_i64().map_or_else(
+                    || Value::Real(n.as_f64().unwrap_or_default()),
+                    Value::Integer,
+                ),
+                Some(serde_json::Value::String(s)) => Value::Text(s),
+                Some(other) => {
+                    warn!("Storing unexpected value in column {} as text", name);
+                    Value::Text(other.to_string())
+                }
+            });
+            statement.execute(params_from_iter(values))?;
+        }
+    }
+    transaction.commit()?;
+    Ok(())
+}
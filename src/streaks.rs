@@ -0,0 +1,122 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! History of the official NYT streak, snapshotted once per run, along with the NYT's latest
+//! lifetime stats
+
+use crate::api_client::{LifetimeStats, StatsAndStreaks};
+use anyhow::{Context, Result};
+use chrono::naive::NaiveDate;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Official streak as reported by the NYT on a given day
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct StreakSnapshot {
+    /// Date the snapshot was taken
+    pub date: NaiveDate,
+    pub current_streak: u32,
+    pub longest_streak: u32,
+    /// Lifetime number of puzzles attempted. Missing from snapshots taken by older versions.
+    #[serde(default)]
+    pub puzzles_attempted: Option<u32>,
+    /// Lifetime number of puzzles solved. Missing from snapshots taken by older versions.
+    #[serde(default)]
+    pub puzzles_solved: Option<u32>,
+}
+
+impl StreakSnapshot {
+    /// Snapshot of the given stats and streaks, taken on the given date
+    #[must_use]
+    pub fn new(date: NaiveDate, stats: &StatsAndStreaks) -> Self {
+        Self {
+            date,
+            current_streak: stats.streaks.current_streak,
+            longest_streak: stats.streaks.longest_streak,
+            puzzles_attempted: Some(stats.stats.puzzles_attempted),
+            puzzles_solved: Some(stats.stats.puzzles_solved),
+        }
+    }
+}
+
+/// Path of the streak history table stored alongside the database at the given path
+#[must_use]
+pub fn history_path(db_path: &Path) -> PathBuf {
+    db_path.with_extension("streaks.csv")
+}
+
+/// Path of the lifetime stats file stored alongside the database at the given path
+#[must_use]
+pub fn lifetime_path(db_path: &Path) -> PathBuf {
+    db_path.with_extension("lifetime.json")
+}
+
+/// NYT's lifetime stats as of a given day
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LifetimeSnapshot {
+    /// Date the snapshot was taken
+    pub date: NaiveDate,
+    #[serde(flatten)]
+    pub stats: LifetimeStats,
+}
+
+/// Save the latest lifetime stats to the given path, replacing the previous snapshot
+pub fn save_lifetime<P: AsRef<Path>>(path: P, snapshot: &LifetimeSnapshot) -> Result<()> {
+    let path = path.as_ref();
+    let file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    serde_json::to_writer_pretty(file, snapshot)?;
+    Ok(())
+}
+
+/// Load a streak history table. Returns an empty history if the file doesn't exist yet.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<StreakSnapshot>> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let reader = csv::Reader::from_path(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    reader
+        .into_deserialize()
+        .map(|snapshot| snapshot.with_context(|| "Malformed streak snapshot"))
+        .collect()
+}
+
+/// Add a snapshot to the streak history table at the given path, replacing any earlier snapshot
+/// from the same day
+pub fn record<P: AsRef<Path>>(path: P, snapshot: StreakSnapshot) -> Result<()> {
+    let path = path.as_ref();
+    let mut history = load(path)?;
+    if let Some(previous) = history.iter().rev().find(|s| s.date < snapshot.date) {
+        if snapshot.current_streak < previous.current_streak {
+            info!(
+                "Streak of {} days ended since {}",
+                previous.current_streak, previous.date
+            );
+        }
+    }
+    history.retain(|s| s.date != snapshot.date);
+    history.push(snapshot);
+    history.sort_unstable_by_key(|s| s.date);
+
+    let mut writer = csv::Writer::from_path(path)?;
+    for snapshot in history {
+        writer.serialize(snapshot)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
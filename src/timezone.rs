@@ -0,0 +1,116 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Puzzle release times and whether a puzzle was solved on the day it was published
+
+use anyhow::Context;
+use chrono::{
+    naive::NaiveDate, DateTime, Datelike, Duration, FixedOffset, Local, NaiveDateTime, NaiveTime,
+    TimeZone, Utc, Weekday,
+};
+use std::str::FromStr;
+
+/// Time zone that the solver considers home, used to decide when a puzzle's day ends
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HomeTimezone {
+    /// The system's local time zone
+    #[default]
+    Local,
+    /// A fixed offset from UTC. Daylight saving time is not taken into account.
+    Fixed(FixedOffset),
+}
+
+impl FromStr for HomeTimezone {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("local") {
+            return Ok(Self::Local);
+        }
+        if s.eq_ignore_ascii_case("utc") {
+            return Ok(Self::Fixed(FixedOffset::east_opt(0).unwrap()));
+        }
+        FixedOffset::from_str(s)
+            .map(Self::Fixed)
+            .with_context(|| format!("Expected `local`, `utc`, or an offset like -05:00, not {s}"))
+    }
+}
+
+impl HomeTimezone {
+    /// The first instant after the given date ends in this time zone
+    #[must_use]
+    pub fn end_of_day(self, date: NaiveDate) -> DateTime<Utc> {
+        let midnight = (date + Duration::days(1)).and_time(NaiveTime::MIN);
+        match self {
+            // Midnight can be skipped or repeated by a DST transition. Take the earliest valid
+            // instant, falling back to an hour later if it was skipped.
+            Self::Local => Local
+                .from_local_datetime(&midnight)
+                .earliest()
+                .or_else(|| {
+                    Local
+                        .from_local_datetime(&(midnight + Duration::hours(1)))
+                        .earliest()
+                })
+                .map_or_else(
+                    || Utc.from_utc_datetime(&midnight),
+                    |t| t.with_timezone(&Utc),
+                ),
+            Self::Fixed(offset) => offset
+                .from_local_datetime(&midnight)
+                .unwrap()
+                .with_timezone(&Utc),
+        }
+    }
+}
+
+/// UTC offset of US Eastern time at the given local date and time. DST runs from 2am on the
+/// second Sunday of March until 2am on the first Sunday of November.
+fn eastern_offset(local: NaiveDateTime) -> FixedOffset {
+    let nth_sunday = |month, n| {
+        NaiveDate::from_weekday_of_month_opt(local.year(), month, Weekday::Sun, n)
+            .unwrap()
+            .and_hms_opt(2, 0, 0)
+            .unwrap()
+    };
+    let hours = if local >= nth_sunday(3, 2) && local < nth_sunday(11, 1) {
+        -4
+    } else {
+        -5
+    };
+    FixedOffset::east_opt(hours * 3600).unwrap()
+}
+
+/// Time at which the puzzle for the given date was released in the app: 10pm Eastern the night
+/// before on weekdays, or 6pm Eastern the night before for the Saturday and Sunday puzzles
+#[must_use]
+pub fn release_time(date: NaiveDate) -> DateTime<Utc> {
+    let hour = match date.weekday() {
+        Weekday::Sat | Weekday::Sun => 18,
+        _ => 22,
+    };
+    let local = (date - Duration::days(1)).and_hms_opt(hour, 0, 0).unwrap();
+    eastern_offset(local)
+        .from_local_datetime(&local)
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+/// Whether the puzzle for the given date was solved between its release and the end of its
+/// publication day in the solver's home time zone, i.e. in time to count towards a streak
+#[must_use]
+pub fn solved_on_pub_day(date: NaiveDate, solved_unix: u32, home: HomeTimezone) -> bool {
+    let solved = i64::from(solved_unix);
+    solved >= release_time(date).timestamp() && solved < home.end_of_day(date).timestamp()
+}
@@ -0,0 +1,74 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Local record of how many requests have been made to the NYT API. Nothing is ever sent anywhere;
+//! the file only exists so users can keep an eye on their own request footprint.
+
+use anyhow::{Context, Result};
+use chrono::naive::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct UsageStats {
+    /// Cumulative number of requests made, keyed by month in YYYY-MM format
+    pub requests_by_month: BTreeMap<String, u64>,
+}
+
+impl UsageStats {
+    /// Load usage stats from the given path. Returns empty stats if the file doesn't exist yet.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        serde_json::from_reader(file)
+            .with_context(|| format!("Malformed usage file {}", path.display()))
+    }
+
+    /// Write usage stats to the given path
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let file =
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Add requests made on the given date to the running total for its month
+    pub fn record(&mut self, date: NaiveDate, n_requests: u32) {
+        *self
+            .requests_by_month
+            .entry(date.format("%Y-%m").to_string())
+            .or_default() += u64::from(n_requests);
+    }
+
+    /// Total number of requests made across all months
+    #[must_use]
+    pub fn total(&self) -> u64 {
+        self.requests_by_month.values().sum()
+    }
+}
+
+/// Add requests made today to the usage file at the given path
+pub fn record_requests<P: AsRef<Path>>(path: P, n_requests: u32) -> Result<()> {
+    let path = path.as_ref();
+    let mut usage = UsageStats::load(path)?;
+    usage.record(chrono::offset::Utc::now().date_naive(), n_requests);
+    usage.save(path)
+}
@@ -0,0 +1,125 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::api_client::{self, RateLimitedClient};
+use crate::PuzzleStats;
+use anyhow::Result;
+use chrono::naive::NaiveDate;
+use futures::future;
+use rand::seq::SliceRandom;
+use std::fmt;
+
+/// A field whose locally stored value differs from what the NYT API currently returns
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub date: NaiveDate,
+    pub field: &'static str,
+    pub local: String,
+    pub remote: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} is {} locally but {} on the server",
+            self.date, self.field, self.local, self.remote
+        )
+    }
+}
+
+fn format_option<T: ToString>(value: Option<T>) -> String {
+    value.map_or_else(|| "empty".to_string(), |v| v.to_string())
+}
+
+/// Pick up to `n` random solved records that have a known puzzle id
+#[must_use]
+pub fn sample_solved<'a, I: IntoIterator<Item = &'a PuzzleStats>>(
+    records: I,
+    n: usize,
+) -> Vec<PuzzleStats> {
+    let candidates: Vec<&PuzzleStats> = records
+        .into_iter()
+        .filter(|r| r.is_complete() && r.solve_time_secs.is_some())
+        .collect();
+    candidates
+        .choose_multiple(&mut rand::thread_rng(), n)
+        .map(|r| (*r).clone())
+        .collect()
+}
+
+/// Compare a stored record against a freshly-fetched copy of itself
+fn compare(local: &PuzzleStats, remote: &PuzzleStats) -> Vec<Mismatch> {
+    let fields = [
+        (
+            "solve_time_secs",
+            format_option(local.solve_time_secs),
+            format_option(remote.solve_time_secs),
+        ),
+        (
+            "cheated",
+            format_option(local.cheated),
+            format_option(remote.cheated),
+        ),
+        (
+            "opened_unix",
+            format_option(local.opened_unix),
+            format_option(remote.opened_unix),
+        ),
+        (
+            "solved_unix",
+            format_option(local.solved_unix),
+            format_option(remote.solved_unix),
+        ),
+    ];
+    fields
+        .into_iter()
+        .filter(|(_, local_value, remote_value)| local_value != remote_value)
+        .map(|(field, local_value, remote_value)| Mismatch {
+            date: local.date,
+            field,
+            local: local_value,
+            remote: remote_value,
+        })
+        .collect()
+}
+
+/// Re-fetch the given records from the NYT API and report any fields that differ from the stored
+/// copies. Records without a puzzle id are skipped.
+pub async fn verify_records(
+    client: &RateLimitedClient,
+    records: &[PuzzleStats],
+) -> Result<Vec<Mismatch>> {
+    let fetches = records.iter().filter_map(|record| {
+        let id = record.puzzle_id?;
+        Some(async move {
+            let mut remote = record.clone();
+            match api_client::get_solve_stats(client, id, record.date).await? {
+                Some(stats) => remote.update_stats(stats),
+                None => {
+                    remote.solve_time_secs = None;
+                    remote.cheated = Some(false);
+                }
+            }
+            Ok::<_, anyhow::Error>(compare(record, &remote))
+        })
+    });
+
+    let mut mismatches = Vec::new();
+    for result in future::join_all(fetches).await {
+        mismatches.extend(result?);
+    }
+    mismatches.sort_by_key(|m| m.date);
+    Ok(mismatches)
+}
@@ -0,0 +1,73 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Watchdog for daemon mode that raises alerts about problems that would otherwise go unnoticed
+
+use crate::logger::RunSummary;
+use chrono::{DateTime, Duration, Utc};
+
+/// Tracks the outcome of daemon runs and decides when to alert
+#[derive(Debug, Clone)]
+pub struct Watchdog {
+    /// Alert once no run has succeeded for this long
+    max_stale: Option<Duration>,
+    /// Time of the last successful run, or when the watchdog started if none has succeeded
+    last_success: DateTime<Utc>,
+    /// Whether the current stretch of failures has already been alerted on
+    stale_alerted: bool,
+    last_streak: Option<u32>,
+}
+
+impl Watchdog {
+    /// Create a watchdog that alerts if no run succeeds for `max_stale`, if given, and whenever
+    /// the current streak drops to zero
+    #[must_use]
+    pub fn new(max_stale: Option<Duration>, now: DateTime<Utc>) -> Self {
+        Self {
+            max_stale,
+            last_success: now,
+            stale_alerted: false,
+            last_streak: None,
+        }
+    }
+
+    /// Record a successful run. Returns an alert message if the streak was broken.
+    pub fn on_success(&mut self, summary: &RunSummary, now: DateTime<Utc>) -> Option<String> {
+        self.last_success = now;
+        self.stale_alerted = false;
+        let previous = self.last_streak.replace(summary.current_streak);
+        match previous {
+            Some(previous) if previous > 0 && summary.current_streak == 0 => {
+                Some(format!("Crossword streak of {previous} days was broken"))
+            }
+            _ => None,
+        }
+    }
+
+    /// Record a failed run. Returns an alert message the first time the time since the last
+    /// successful run exceeds the configured limit.
+    pub fn on_failure(&mut self, now: DateTime<Utc>) -> Option<String> {
+        let max_stale = self.max_stale?;
+        let stale = now - self.last_success;
+        if self.stale_alerted || stale < max_stale {
+            return None;
+        }
+        self.stale_alerted = true;
+        Some(format!(
+            "No successful crossword fetch in {} days, since {}",
+            stale.num_days(),
+            self.last_success.format("%Y-%m-%d %H:%M UTC")
+        ))
+    }
+}